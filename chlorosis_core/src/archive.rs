@@ -0,0 +1,134 @@
+use std::{
+    io::{self, Cursor, ErrorKind, Read},
+    path::Path,
+};
+
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+use crate::constants::MAX_ROM_SIZE;
+
+// ROMs can be loaded as is or out of a zip or gzip archive, which is told apart by its magic
+// number rather than the file name. Zip archives use the first entry with a ROM extension
+
+const ROM_EXTENSIONS: [&str; 3] = ["gb", "gbc", "sgb"];
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+
+fn is_rom_name(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| ROM_EXTENSIONS.iter().any(|r| e.eq_ignore_ascii_case(r)))
+}
+
+// Neither the sizes in a zip's headers nor a gzip stream can be trusted, so decompression stops
+// one byte past the largest cartridge
+fn read_rom(entry: impl Read) -> Result<Vec<u8>, io::Error> {
+    let mut rom = vec![];
+    entry.take(MAX_ROM_SIZE as u64 + 1).read_to_end(&mut rom)?;
+    if rom.len() > MAX_ROM_SIZE {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "Archived ROM is larger than any cartridge",
+        ));
+    }
+    Ok(rom)
+}
+
+// Returns the ROM along with the name of the archive entry it came from
+pub fn extract_rom(data: Vec<u8>) -> Result<(Vec<u8>, Option<String>), io::Error> {
+    if data.starts_with(ZIP_MAGIC) {
+        let mut archive = ZipArchive::new(Cursor::new(data)).map_err(io::Error::other)?;
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i).map_err(io::Error::other)?;
+            if entry.is_file() && is_rom_name(entry.name()) {
+                let rom = read_rom(&mut entry)?;
+                return Ok((rom, Some(entry.name().to_owned())));
+            }
+        }
+        Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "No ROM found in zip archive",
+        ))
+    } else if data.starts_with(GZIP_MAGIC) {
+        let mut decoder = GzDecoder::new(data.as_slice());
+        let rom = read_rom(&mut decoder)?;
+        let name = decoder
+            .header()
+            .and_then(|h| h.filename())
+            .map(|n| String::from_utf8_lossy(n).into_owned());
+        Ok((rom, name))
+    } else {
+        Ok((data, None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use flate2::{write::GzEncoder, Compression, GzBuilder};
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::extract_rom;
+    use crate::constants::MAX_ROM_SIZE;
+
+    #[test]
+    fn test_zip() {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("readme.txt", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"Not a ROM").unwrap();
+        zip.start_file("Game.GBC", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(&[1, 2, 3]).unwrap();
+        let data = zip.finish().unwrap().into_inner();
+
+        let (rom, name) = extract_rom(data).unwrap();
+        assert_eq!(rom, [1, 2, 3]);
+        assert_eq!(name.as_deref(), Some("Game.GBC"));
+
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("readme.txt", SimpleFileOptions::default())
+            .unwrap();
+        assert!(extract_rom(zip.finish().unwrap().into_inner()).is_err());
+    }
+
+    #[test]
+    fn test_gzip() {
+        let mut gz = GzBuilder::new()
+            .filename("game.gb")
+            .write(vec![], Compression::default());
+        gz.write_all(&[4, 5, 6]).unwrap();
+        let (rom, name) = extract_rom(gz.finish().unwrap()).unwrap();
+        assert_eq!(rom, [4, 5, 6]);
+        assert_eq!(name.as_deref(), Some("game.gb"));
+
+        let mut gz = GzEncoder::new(vec![], Compression::default());
+        gz.write_all(&[7]).unwrap();
+        assert_eq!(extract_rom(gz.finish().unwrap()).unwrap(), (vec![7], None));
+
+        assert_eq!(extract_rom(vec![0, 1]).unwrap(), (vec![0, 1], None));
+    }
+
+    #[test]
+    fn test_oversized() {
+        let mut gz = GzEncoder::new(vec![], Compression::default());
+        gz.write_all(&vec![0; MAX_ROM_SIZE + 1]).unwrap();
+        assert!(extract_rom(gz.finish().unwrap()).is_err());
+
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("game.gb", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(&vec![0; MAX_ROM_SIZE + 1]).unwrap();
+        assert!(extract_rom(zip.finish().unwrap().into_inner()).is_err());
+
+        let mut gz = GzEncoder::new(vec![], Compression::default());
+        gz.write_all(&vec![0; MAX_ROM_SIZE]).unwrap();
+        assert_eq!(
+            extract_rom(gz.finish().unwrap()).unwrap().0.len(),
+            MAX_ROM_SIZE
+        );
+    }
+}
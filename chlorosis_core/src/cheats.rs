@@ -0,0 +1,355 @@
+use std::{
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use crate::{device::Device, symbols::parse_address, Address, Byte};
+
+// Three kinds of code are accepted:
+//   Game Genie  ABC-DEF or ABC-DEF-GHI, patches ROM reads, optionally only when the ROM holds GI
+//   GameShark   ttvvllhh, writes vv to hhll every frame, types 80-9F pick RAM bank t & 0xF
+//   Raw         bank:address=value or address=value, writes every frame like a GameShark code
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheatKind {
+    RomPatch {
+        address: Address,
+        value: Byte,
+        compare: Option<Byte>,
+    },
+    Freeze {
+        bank: Option<usize>, // Any bank when none
+        address: Address,
+        value: Byte,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    pub code: String,
+    pub description: String,
+    pub kind: CheatKind,
+}
+
+impl Cheat {
+    pub fn parse(code: &str) -> Result<Self, io::Error> {
+        let code = code.trim().to_ascii_uppercase();
+        let kind = if let Some((location, value)) = code.split_once('=') {
+            parse_raw(location, value)
+        } else {
+            let digits = code.replace('-', "");
+            match digits.len() {
+                6 | 9 => parse_game_genie(&digits),
+                8 => parse_game_shark(&digits),
+                _ => None,
+            }
+        };
+        let Some(kind) = kind else {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid cheat code {code}"),
+            ));
+        };
+        Ok(Self {
+            code,
+            description: String::new(),
+            kind,
+        })
+    }
+}
+
+fn hex(s: &str) -> Option<u16> {
+    u16::from_str_radix(s, 16).ok()
+}
+
+// Only memory that is safe to write every frame can be frozen
+const fn is_freezable(address: Address) -> bool {
+    matches!(address.0, 0x8000..=0xDFFF | 0xFF80..=0xFFFE)
+}
+
+fn parse_game_genie(digits: &str) -> Option<CheatKind> {
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let value = hex(&digits[0..2])? as u8;
+    // CDEF holds the address as FCDE with F inverted
+    let address = hex(&digits[2..6])?.rotate_right(4) ^ 0xF000;
+    if address > 0x7FFF {
+        return None;
+    }
+    // GI is the compare byte XORed with BA and rotated left by 2, H goes unused
+    let compare = if digits.len() == 9 {
+        let encoded = hex(&format!("{}{}", &digits[6..7], &digits[8..9]))? as u8;
+        Some(Byte(encoded.rotate_right(2) ^ 0xBA))
+    } else {
+        None
+    };
+    Some(CheatKind::RomPatch {
+        address: Address(address),
+        value: Byte(value),
+        compare,
+    })
+}
+
+fn parse_game_shark(digits: &str) -> Option<CheatKind> {
+    let kind = hex(digits.get(0..2)?)? as usize;
+    let value = hex(digits.get(2..4)?)? as u8;
+    let address = Address(hex(digits.get(4..8)?)?.swap_bytes());
+    let bank = match kind {
+        0x00..=0x7F => None,
+        0x80..=0x9F => Some(kind & 0xF),
+        _ => return None,
+    };
+    is_freezable(address).then_some(CheatKind::Freeze {
+        bank,
+        address,
+        value: Byte(value),
+    })
+}
+
+fn parse_raw(location: &str, value: &str) -> Option<CheatKind> {
+    let (bank, address) = match location.split_once(':') {
+        Some((bank, address)) => (Some(hex(bank.trim())? as usize), address),
+        None => (None, location),
+    };
+    let address = parse_address(address)?;
+    let value = hex(value
+        .trim()
+        .trim_start_matches("0X")
+        .trim_start_matches('$'))?;
+    (is_freezable(address) && value <= 0xFF).then_some(CheatKind::Freeze {
+        bank,
+        address,
+        value: Byte(value as u8),
+    })
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CheatList {
+    cheats: Vec<Cheat>,
+}
+
+impl CheatList {
+    // One code per line, anything after it is the description, `;` starts a comment
+    pub fn parse(text: &str) -> Result<Self, io::Error> {
+        let mut list = Self::default();
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (code, description) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let mut cheat = Cheat::parse(code)?;
+            cheat.description = description.trim().to_owned();
+            list.add(cheat);
+        }
+        Ok(list)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, io::Error> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    // Adding a code twice replaces it
+    pub fn add(&mut self, cheat: Cheat) {
+        self.remove(&cheat.code);
+        self.cheats.push(cheat);
+    }
+
+    pub fn remove(&mut self, code: &str) -> bool {
+        let code = code.trim().to_ascii_uppercase();
+        let len = self.cheats.len();
+        self.cheats.retain(|c| c.code != code);
+        self.cheats.len() != len
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cheat> {
+        self.cheats.iter()
+    }
+
+    pub const fn len(&self) -> usize {
+        self.cheats.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    // Applies any Game Genie codes to a byte read from ROM
+    pub fn patch_rom(&self, address: Address, byte: Byte) -> Byte {
+        self.cheats
+            .iter()
+            .find_map(|c| match c.kind {
+                CheatKind::RomPatch {
+                    address: a,
+                    value,
+                    compare,
+                } if a == address && compare.is_none_or(|c| c == byte) => Some(value),
+                _ => None,
+            })
+            .unwrap_or(byte)
+    }
+}
+
+impl Device {
+    // Looks for a cheat file with the same stem as the ROM, e.g. game.gb and game.cheats
+    pub(crate) fn load_cheats_for(&mut self, rom: &Path) -> Result<(), io::Error> {
+        let path = rom.with_extension("cheats");
+        self.cheats = if path.exists() {
+            CheatList::load(path)?
+        } else {
+            CheatList::default()
+        };
+        Ok(())
+    }
+
+    pub const fn cheats(&self) -> &CheatList {
+        &self.cheats
+    }
+
+    pub fn add_cheat(&mut self, code: &str) -> Result<(), io::Error> {
+        self.cheats.add(Cheat::parse(code)?);
+        Ok(())
+    }
+
+    pub fn remove_cheat(&mut self, code: &str) -> bool {
+        self.cheats.remove(code)
+    }
+
+    pub(crate) fn list_cheats(&self) {
+        if self.cheats.is_empty() {
+            println!("No cheats");
+        }
+        for cheat in self.cheats.iter() {
+            println!("{:<12} {}", cheat.code, cheat.description);
+        }
+    }
+
+    // Called at the start of every frame
+    pub(crate) fn apply_cheats(&mut self) {
+        if self.cheats.is_empty() {
+            return;
+        }
+        let cheats = std::mem::take(&mut self.cheats);
+        for cheat in cheats.iter() {
+            if let CheatKind::Freeze {
+                bank,
+                address,
+                value,
+            } = cheat.kind
+            {
+                // Like a debugger, freezes reach VRAM mid-frame and cart RAM the game has disabled
+                if bank.is_none_or(|b| b == self.bank_of(address)) {
+                    self.poke(address, value);
+                }
+            }
+        }
+        self.cheats = cheats;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cheat, CheatKind, CheatList};
+    use crate::{
+        constants::ROM_BANK_SIZE,
+        types::{HeaderFix, MemoryBankControllerType},
+        Address, Byte, Device,
+    };
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Cheat::parse("3e2-34e-efa").unwrap().kind,
+            CheatKind::RomPatch {
+                address: Address(0x1234),
+                value: Byte(0x3E),
+                compare: Some(Byte(0x00)),
+            }
+        );
+        assert_eq!(
+            Cheat::parse("3E2-34E").unwrap().kind,
+            CheatKind::RomPatch {
+                address: Address(0x1234),
+                value: Byte(0x3E),
+                compare: None,
+            }
+        );
+        assert_eq!(
+            Cheat::parse("01FF34C2").unwrap().kind,
+            CheatKind::Freeze {
+                bank: None,
+                address: Address(0xC234),
+                value: Byte(0xFF),
+            }
+        );
+        assert_eq!(
+            Cheat::parse("02:D010=63").unwrap().kind,
+            CheatKind::Freeze {
+                bank: Some(2),
+                address: Address(0xD010),
+                value: Byte(0x63),
+            }
+        );
+        // ROM addresses can only be patched by Game Genie codes
+        assert!(Cheat::parse("0150=00").is_err());
+        assert!(Cheat::parse("3E2-347").is_err()); // 0x8234 is not ROM
+        assert!(Cheat::parse("nonsense").is_err());
+    }
+
+    #[test]
+    fn test_patch_rom() {
+        let list = CheatList::parse("3E2-34E-EFA Infinite lives\n; comment\n").unwrap();
+        assert_eq!(list.iter().next().unwrap().description, "Infinite lives");
+        assert_eq!(list.patch_rom(Address(0x1234), Byte(0x00)), Byte(0x3E));
+        assert_eq!(list.patch_rom(Address(0x1234), Byte(0x01)), Byte(0x01));
+        assert_eq!(list.patch_rom(Address(0x1235), Byte(0x00)), Byte(0x00));
+    }
+
+    #[test]
+    fn test_freeze() {
+        let mut d = Device::new();
+        d.add_cheat("01FF34C2").unwrap();
+        d.add_cheat("02:D010=63").unwrap();
+        d.apply_cheats();
+        assert_eq!(d.peek(Address(0xC234)), Byte(0xFF));
+        assert_eq!(d.peek(Address(0xD010)), Byte(0x00)); // Bank 1 is mapped
+
+        d.write(Address(0xFF70), Byte(2));
+        d.apply_cheats();
+        assert_eq!(d.peek(Address(0xD010)), Byte(0x63));
+
+        assert!(d.remove_cheat("01ff34c2"));
+        d.poke(Address(0xC234), Byte(0));
+        d.apply_cheats();
+        assert_eq!(d.peek(Address(0xC234)), Byte(0));
+    }
+
+    #[test]
+    fn test_freeze_cart_ram() {
+        let mut rom = vec![0; ROM_BANK_SIZE * 4];
+        HeaderFix {
+            mapper: Some(MemoryBankControllerType::MBC1_RAM),
+            rom_size: Some(0x01),
+            ram_size: Some(0x03),
+            ..HeaderFix::default()
+        }
+        .apply(&mut rom)
+        .unwrap();
+        let mut d = Device::new();
+        d.load_rom(&rom).unwrap();
+
+        // GameShark bank 1 of cart RAM, written while the game keeps the RAM disabled
+        d.add_cheat("816310A0").unwrap();
+        d.write(Address(0x6000), Byte(1));
+        d.apply_cheats();
+        d.write(Address(0x0000), Byte(0x0A));
+        assert_eq!(d.peek(Address(0xA010)), Byte(0x00));
+
+        d.write(Address(0x0000), Byte(0x00));
+        d.write(Address(0x4000), Byte(1));
+        d.apply_cheats();
+        d.write(Address(0x0000), Byte(0x0A));
+        assert_eq!(d.peek(Address(0xA010)), Byte(0x63));
+    }
+}
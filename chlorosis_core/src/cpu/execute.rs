@@ -1,6 +1,6 @@
 use crate::{
     addition_register_pairs, constants::*, decrement_register, increment_register, Address, Byte,
-    Device,
+    Device, Error,
 };
 
 use super::opcodes::Opcode;
@@ -112,7 +112,8 @@ impl Device {
                 // STOP SYSTEM CLOCK and OSCILLATOR CIRCUIT and LCD controller
                 // Cancelled by RESET signal
 
-                panic!("Unimplemented STOP");
+                self.fault(Error::Unimplemented("STOP instruction"));
+                self.cpu.cost = 1;
             }
             // 0x11
             LD_DE_d16(addr) => {
@@ -246,7 +247,8 @@ impl Device {
             // 0x27
             DAA => {
                 // TODO: implement BCD operation
-                unimplemented!()
+                self.fault(Error::Unimplemented("DAA instruction"));
+                self.cpu.cost = 1;
             }
             // 0x28
             JR_Z_s8(signed) => {
@@ -696,8 +698,8 @@ impl Device {
                 // STOP system clock
                 // Cancelled by interrupt or reset
                 // if interrupt master enable set PC is pushed to stack and jump to interrupt address
+                self.fault(Error::Unimplemented("HALT instruction"));
                 self.cpu.cost = 1;
-                unimplemented!();
             }
             // 0x77
             LD_aHL_A => {
@@ -1254,7 +1256,7 @@ impl Device {
             // 0xD8
             RET_C => {
                 if self.cpu.c_flag {
-                    self.cpu.sp = self.pop_address();
+                    self.cpu.pc = self.pop_address();
                     self.cpu.cost = 5;
                 } else {
                     self.cpu.cost = 2;
@@ -1263,9 +1265,10 @@ impl Device {
             // 0xD9
             RETI => {
                 // TODO: RETI instruction
-                unimplemented!("RETI instruction not implemented");
                 // toggle master interrupt enable flag
                 // load PC from SP? or other
+                self.fault(Error::Unimplemented("RETI instruction"));
+                self.cpu.cost = 4;
             }
             // 0xDA
             JP_C_a16(addr) => {
@@ -1443,7 +1446,7 @@ impl Device {
             }
             // 0xFF
             RST_7 => {
-                println!("RST_7 => may indicate 0xFF bug");
+                eprintln!("RST_7 => may indicate 0xFF bug");
                 self.push_address(self.cpu.pc);
                 self.cpu.pc = RST_7_ADDRESS.into();
                 self.cpu.cost = 4;
@@ -2846,7 +2849,7 @@ impl Device {
 
 #[cfg(test)]
 mod test {
-    use crate::Byte;
+    use crate::{Address, Byte, Device, Error};
 
     #[test]
     fn test_is_bit_set() {
@@ -2855,4 +2858,17 @@ mod test {
         assert!(!b.is_bit_set(6));
         assert!(b.is_bit_set(0));
     }
+
+    #[test]
+    fn test_illegal_instruction() {
+        let mut d = Device::new();
+        d.poke(Address(0xC000), Byte(0xD3));
+        d.cpu.pc = Address(0xC000);
+        assert!(matches!(d.step_instruction(), Err(Error::InvalidState(_))));
+        assert_eq!(d.cpu.pc, Address(0xC001));
+
+        // Skipped like a NOP, so the next instruction runs normally
+        d.poke(Address(0xC001), Byte(0x00));
+        assert!(d.step_instruction().is_ok());
+    }
 }
@@ -1,8 +1,17 @@
-use crate::Device;
+use crate::{Address, Byte, Device, Error};
 
 use super::opcodes::Opcode;
 
 impl Device {
+    // Real hardware locks up, here the opcode is skipped like a NOP
+    fn illegal(&mut self, op: Byte) -> Opcode {
+        let address = Address(self.cpu.pc.0.wrapping_sub(1));
+        self.fault(Error::InvalidState(format!(
+            "Illegal instruction {op} at {address}"
+        )));
+        Opcode::NOP
+    }
+
     pub fn fetch_instruction(&mut self) -> Opcode {
         use Opcode::*;
         let op = self.consume_byte();
@@ -259,7 +268,7 @@ impl Device {
             0xD0 => RET_NC,
             0xD1 => POP_DE,
             0xD2 => JP_NC_a16(self.consume_pair()),
-            0xD3 => panic!("Illegal instruction {}", op),
+            0xD3 => self.illegal(op),
             0xD4 => CALL_NC_a16(self.consume_pair()),
             0xD5 => PUSH_DE,
             0xD6 => SUB_d8(self.consume_byte()),
@@ -267,9 +276,9 @@ impl Device {
             0xD8 => RET_C,
             0xD9 => RETI,
             0xDA => JP_C_a16(self.consume_pair()),
-            0xDB => panic!("Illegal instruction {}", op),
+            0xDB => self.illegal(op),
             0xDC => CALL_C_a16(self.consume_pair()),
-            0xDD => panic!("Illegal instruction {}", op),
+            0xDD => self.illegal(op),
             0xDE => SBC_A_d8(self.consume_byte()),
             0xDF => RST_3,
             // Row D
@@ -278,17 +287,17 @@ impl Device {
             0xE0 => LD_a8_A(self.consume_byte().to_address()),
             0xE1 => POP_HL,
             0xE2 => LD_aC_A,
-            0xE3 => panic!("Illegal instruction {}", op),
-            0xE4 => panic!("Illegal instruction {}", op),
+            0xE3 => self.illegal(op),
+            0xE4 => self.illegal(op),
             0xE5 => PUSH_HL,
             0xE6 => AND_d8(self.consume_byte()),
             0xE7 => RST_4,
             0xE8 => ADD_SP_s8(self.consume_signed_byte()),
             0xE9 => JP_HL,
             0xEA => LD_a16_A(self.consume_pair()),
-            0xEB => panic!("Illegal instruction {}", op),
-            0xEC => panic!("Illegal instruction {}", op),
-            0xED => panic!("Illegal instruction {}", op),
+            0xEB => self.illegal(op),
+            0xEC => self.illegal(op),
+            0xED => self.illegal(op),
             0xEE => XOR_d8(self.consume_byte()),
             0xEF => RST_5,
             // Row E
@@ -298,7 +307,7 @@ impl Device {
             0xF1 => POP_AF,
             0xF2 => LD_A_aC,
             0xF3 => DI,
-            0xF4 => panic!("Illegal instruction {}", op),
+            0xF4 => self.illegal(op),
             0xF5 => PUSH_AF,
             0xF6 => OR_d8(self.consume_byte()),
             0xF7 => RST_6,
@@ -306,8 +315,8 @@ impl Device {
             0xF9 => LD_SP_HL,
             0xFA => LD_A_a16(self.consume_pair()),
             0xFB => EI,
-            0xFC => panic!("Illegal instruction {}", op),
-            0xFD => panic!("Illegal instruction {}", op),
+            0xFC => self.illegal(op),
+            0xFD => self.illegal(op),
             0xFE => CP_d8(self.consume_byte()),
             0xFF => RST_7,
             // Row F
@@ -1,5 +1,11 @@
-use crate::Device;
+use std::io;
 
+use crate::{
+    state::{Snapshot, StateReader, StateWriter},
+    Device,
+};
+
+use self::opcodes::Opcode;
 use super::{types::SignedByte, Address, Byte};
 
 mod arith;
@@ -9,6 +15,18 @@ mod macros;
 mod opcodes;
 mod registers;
 
+// Deep enough for any sane program, stops runaway recursion growing the stack forever
+const CALL_STACK_LIMIT: usize = 256;
+
+// A CALL or RST that has not yet returned, tracked for debuggers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrame {
+    pub call_site: Address,
+    pub target: Address,
+    pub return_address: Address,
+    pub sp: Address, // Stack pointer once the return address has been pushed
+}
+
 #[derive(Debug)]
 pub struct CentralProcessor {
     pub a: Byte,
@@ -81,37 +99,58 @@ impl CentralProcessor {
         Address(((self.a.0 as u16) << 8) + self.read_f().0 as u16)
     }
 
-    fn write_f(&mut self, val: Byte) {
+    pub const fn write_f(&mut self, val: Byte) {
         self.z_flag = val.is_bit_set(7);
         self.n_flag = val.is_bit_set(6);
         self.h_flag = val.is_bit_set(5);
         self.c_flag = val.is_bit_set(4);
     }
 
-    fn write_bc(&mut self, addr: Address) {
+    const fn write_bc(&mut self, addr: Address) {
         let (b, c) = addr.split();
         self.b = b;
         self.c = c;
     }
 
-    fn write_de(&mut self, addr: Address) {
+    const fn write_de(&mut self, addr: Address) {
         let (d, e) = addr.split();
         self.d = d;
         self.e = e;
     }
 
-    fn write_hl(&mut self, addr: Address) {
+    const fn write_hl(&mut self, addr: Address) {
         let (h, l) = addr.split();
         self.h = h;
         self.l = l;
     }
 
-    fn write_af(&mut self, addr: Address) {
+    const fn write_af(&mut self, addr: Address) {
         let (a, f) = addr.split();
         self.a = a;
         self.write_f(f);
     }
 
+    // gameboy-doctor log format
+    pub fn trace_state(&self, pcmem: [Byte; 4]) -> String {
+        format!(
+            "A:{} F:{} B:{} C:{} D:{} E:{} H:{} L:{} SP:{:04X} PC:{:04X} PCMEM:{},{},{},{}",
+            self.a,
+            self.read_f(),
+            self.b,
+            self.c,
+            self.d,
+            self.e,
+            self.h,
+            self.l,
+            self.sp.0,
+            self.pc.0,
+            pcmem[0],
+            pcmem[1],
+            pcmem[2],
+            pcmem[3]
+        )
+    }
+
     pub fn dump_state(&self) {
         println!("Cost: {}", self.cost);
         println!("PC: {} SP: {}", self.pc, self.sp);
@@ -129,16 +168,61 @@ impl CentralProcessor {
 
 impl Device {
     pub fn step_cpu(&mut self) {
+        self.cycles += 1;
+
         // return if cycle timer not 0
         if self.cpu.cost != 0 {
             self.cpu.cost -= 1;
             return;
         }
+
+        self.trace();
+
+        let pc = self.cpu.pc;
+        let sp = self.cpu.sp;
+
         // fetch instruction
         let op = self.fetch_instruction();
+        let next = self.cpu.pc;
 
         // execute instruction
         self.execute(op);
+
+        self.track_call_stack(op, pc, next, sp);
+    }
+
+    pub fn call_stack(&self) -> &[CallFrame] {
+        &self.call_stack
+    }
+
+    fn track_call_stack(&mut self, op: Opcode, pc: Address, next: Address, sp: Address) {
+        use Opcode::*;
+
+        // Only calls and returns that were taken move the stack pointer
+        if self.cpu.sp == sp {
+            return;
+        }
+        match op {
+            CALL_a16(_) | CALL_NZ_a16(_) | CALL_Z_a16(_) | CALL_NC_a16(_) | CALL_C_a16(_)
+            | RST_0 | RST_1 | RST_2 | RST_3 | RST_4 | RST_5 | RST_6 | RST_7 => {
+                if self.call_stack.len() == CALL_STACK_LIMIT {
+                    self.call_stack.remove(0);
+                }
+                self.call_stack.push(CallFrame {
+                    call_site: pc,
+                    target: self.cpu.pc,
+                    return_address: next,
+                    sp: self.cpu.sp,
+                });
+            }
+            RET | RET_NZ | RET_Z | RET_NC | RET_C | RETI => {
+                // Drop every frame whose return address is now above the stack pointer
+                while self.call_stack.last().is_some_and(|f| f.sp < self.cpu.sp) {
+                    self.call_stack.pop();
+                }
+            }
+            _ => {}
+        }
     }
 
     pub fn consume_byte(&mut self) -> Byte {
@@ -177,3 +261,36 @@ impl Device {
         Address::from_pair(h, l)
     }
 }
+
+impl Snapshot for CentralProcessor {
+    fn save(&self, w: &mut StateWriter) {
+        for r in [self.a, self.b, self.c, self.d, self.e, self.h, self.l] {
+            w.byte(r);
+        }
+        w.byte(self.read_f());
+        w.u16(self.pc.0);
+        w.u16(self.sp.0);
+        w.bool(self.interupt_master_enable);
+        w.u8(self.cost);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        for reg in [
+            &mut self.a,
+            &mut self.b,
+            &mut self.c,
+            &mut self.d,
+            &mut self.e,
+            &mut self.h,
+            &mut self.l,
+        ] {
+            *reg = r.byte()?;
+        }
+        self.write_f(r.byte()?);
+        self.pc = Address(r.u16()?);
+        self.sp = Address(r.u16()?);
+        self.interupt_master_enable = r.bool()?;
+        self.cost = r.u8()?;
+        Ok(())
+    }
+}
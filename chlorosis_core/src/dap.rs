@@ -0,0 +1,688 @@
+use std::{
+    collections::BTreeSet,
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::{TcpListener, ToSocketAddrs},
+    sync::mpsc::{self, TryRecvError},
+    thread,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    device::{Device, DeviceState},
+    symbols::parse_address,
+    Address, Error,
+};
+
+// Debug Adapter Protocol server, see https://microsoft.github.io/debug-adapter-protocol/specification
+// There is a single thread and no line information, so breakpoints are set by label or address
+
+const THREAD_ID: u64 = 1;
+const REGISTERS_REFERENCE: u64 = 1;
+const IO_REFERENCE: u64 = 2;
+
+// One frame's worth of dots between checks for incoming requests
+const TICKS_PER_POLL: usize = 70224;
+
+const IO_REGISTERS: [(&str, u16); 28] = [
+    ("JOYP", 0xFF00),
+    ("SB", 0xFF01),
+    ("SC", 0xFF02),
+    ("DIV", 0xFF04),
+    ("TIMA", 0xFF05),
+    ("TMA", 0xFF06),
+    ("TAC", 0xFF07),
+    ("IF", 0xFF0F),
+    ("LCDC", 0xFF40),
+    ("STAT", 0xFF41),
+    ("SCY", 0xFF42),
+    ("SCX", 0xFF43),
+    ("LY", 0xFF44),
+    ("LYC", 0xFF45),
+    ("DMA", 0xFF46),
+    ("BGP", 0xFF47),
+    ("OBP0", 0xFF48),
+    ("OBP1", 0xFF49),
+    ("WY", 0xFF4A),
+    ("WX", 0xFF4B),
+    ("KEY1", 0xFF4D),
+    ("VBK", 0xFF4F),
+    ("HDMA5", 0xFF55),
+    ("BCPS", 0xFF68),
+    ("BCPD", 0xFF69),
+    ("OCPS", 0xFF6A),
+    ("OCPD", 0xFF6B),
+    ("SVBK", 0xFF70),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Run {
+    Stopped,
+    Continue,
+    StepOut(usize), // Stops once the call stack is no deeper than this
+}
+
+// Bank and address, labels only stop in their own bank while plain addresses stop in all of them
+type Breakpoint = (Option<usize>, Address);
+
+#[derive(Debug)]
+struct Session<W: Write> {
+    writer: W,
+    seq: u64,
+    run: Run,
+    events: Vec<Value>, // Sent after the response to the current request
+    stop_on_entry: bool,
+    function_breakpoints: BTreeSet<Breakpoint>,
+    instruction_breakpoints: BTreeSet<Breakpoint>,
+    finished: bool,
+}
+
+impl<W: Write> Session<W> {
+    const fn new(writer: W) -> Self {
+        Self {
+            writer,
+            seq: 0,
+            run: Run::Stopped,
+            events: vec![],
+            stop_on_entry: false,
+            function_breakpoints: BTreeSet::new(),
+            instruction_breakpoints: BTreeSet::new(),
+            finished: false,
+        }
+    }
+
+    fn send(&mut self, mut message: Value) -> Result<(), io::Error> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.writer, &message)
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> Result<(), io::Error> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+        self.events
+            .push(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn stopped(&mut self, reason: &str) {
+        self.run = Run::Stopped;
+        self.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        );
+    }
+
+    // Faults stop the target the way an exception would, with the error as the description
+    fn exception(&mut self, error: &Error) {
+        self.run = Run::Stopped;
+        self.event(
+            "stopped",
+            json!({
+                "reason": "exception",
+                "description": error.to_string(),
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+            }),
+        );
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        for event in std::mem::take(&mut self.events) {
+            self.send(event)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>, io::Error> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or_default()];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Value) -> Result<(), io::Error> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()
+}
+
+fn variable(name: &str, value: String) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": 0 })
+}
+
+impl Device {
+    // Serves a single client until it disconnects, then flushes saves, traces and movies however
+    // the session ended
+    pub fn serve_dap(
+        &mut self,
+        reader: impl Read + Send + 'static,
+        writer: impl Write,
+    ) -> Result<(), io::Error> {
+        let result = self.dap_session(reader, writer);
+        self.shutdown();
+        result
+    }
+
+    // The reader is drained on its own thread
+    fn dap_session(
+        &mut self,
+        reader: impl Read + Send + 'static,
+        writer: impl Write,
+    ) -> Result<(), io::Error> {
+        let (sender, requests) = mpsc::channel();
+        thread::Builder::new()
+            .name("DAP".to_owned())
+            .spawn(move || {
+                let mut reader = BufReader::new(reader);
+                while let Ok(Some(message)) = read_message(&mut reader) {
+                    if sender.send(message).is_err() {
+                        break;
+                    }
+                }
+            })?;
+
+        let mut session = Session::new(writer);
+        self.state = DeviceState::Paused;
+        while !session.finished {
+            let message = if session.run == Run::Stopped {
+                match requests.recv() {
+                    Ok(message) => Some(message),
+                    Err(_) => break,
+                }
+            } else {
+                self.run_dap(&mut session);
+                match requests.try_recv() {
+                    Ok(message) => Some(message),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => break,
+                }
+            };
+
+            if let Some(message) = message {
+                if message["type"] == "request" {
+                    let result = self.handle_dap_request(&mut session, &message);
+                    session.respond(&message, result)?;
+                }
+            }
+            session.flush()?;
+        }
+        Ok(())
+    }
+
+    // Waits for one client on a local socket, for editors that cannot use stdio
+    pub fn listen_dap(&mut self, addr: impl ToSocketAddrs) -> Result<(), io::Error> {
+        let listener = TcpListener::bind(addr)?;
+        eprintln!("DAP server listening on {}", listener.local_addr()?);
+        let (stream, addr) = listener.accept()?;
+        eprintln!("DAP connected from {addr}");
+        self.serve_dap(stream.try_clone()?, stream)
+    }
+
+    fn run_dap(&mut self, session: &mut Session<impl Write>) {
+        for _ in 0..TICKS_PER_POLL {
+            if let Err(e) = self.tick() {
+                session.exception(&e);
+                return;
+            }
+            if self.cpu.cost != 0 {
+                continue;
+            }
+            if self.at_breakpoint() {
+                session.stopped("breakpoint");
+                return;
+            }
+            if let Run::StepOut(depth) = session.run {
+                if self.call_stack.len() <= depth {
+                    session.stopped("step");
+                    return;
+                }
+            }
+        }
+    }
+
+    fn handle_dap_request(
+        &mut self,
+        session: &mut Session<impl Write>,
+        request: &Value,
+    ) -> Result<Value, String> {
+        let args = &request["arguments"];
+        let command = request["command"].as_str().unwrap_or_default();
+        match command {
+            "initialize" => {
+                session.event("initialized", json!({}));
+                Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsFunctionBreakpoints": true,
+                    "supportsInstructionBreakpoints": true,
+                    "supportsTerminateRequest": true,
+                }))
+            }
+            "launch" => {
+                let program = args["program"].as_str().ok_or("Missing program")?;
+                // Same as opening it in the debugger, with its patch, save and cheats, but the
+                // session decides when it runs
+                self.load_cartrige(program)
+                    .map_err(|e| format!("{program}: {e}"))?;
+                self.state = DeviceState::Paused;
+                session.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+                Ok(json!({}))
+            }
+            "setBreakpoints" => {
+                let count = args["breakpoints"].as_array().map_or(0, Vec::len);
+                let unverified = json!({
+                    "verified": false,
+                    "message": "Source breakpoints are not supported, use an address",
+                });
+                Ok(json!({ "breakpoints": vec![unverified; count] }))
+            }
+            "setFunctionBreakpoints" => {
+                let names = args["breakpoints"].as_array().cloned().unwrap_or_default();
+                let breakpoints: Vec<_> = names
+                    .iter()
+                    .map(|b| {
+                        let name = b["name"].as_str().unwrap_or_default();
+                        self.symbols.resolve_banked(name)
+                    })
+                    .collect();
+                self.replace_breakpoints(
+                    &mut session.function_breakpoints,
+                    breakpoints.iter().flatten().copied().collect(),
+                    &session.instruction_breakpoints,
+                );
+                let addresses: Vec<_> = breakpoints.iter().map(|b| b.map(|(_, a)| a)).collect();
+                Ok(json!({ "breakpoints": breakpoint_results(&addresses) }))
+            }
+            "setInstructionBreakpoints" => {
+                let breakpoints = args["breakpoints"].as_array().cloned().unwrap_or_default();
+                let addresses: Vec<_> = breakpoints
+                    .iter()
+                    .map(|b| {
+                        let base = parse_address(b["instructionReference"].as_str()?)?;
+                        let offset = b["offset"].as_i64().unwrap_or_default();
+                        Some(Address(base.0.wrapping_add(offset as u16)))
+                    })
+                    .collect();
+                self.replace_breakpoints(
+                    &mut session.instruction_breakpoints,
+                    addresses.iter().flatten().map(|&a| (None, a)).collect(),
+                    &session.function_breakpoints,
+                );
+                Ok(json!({ "breakpoints": breakpoint_results(&addresses) }))
+            }
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => {
+                if session.stop_on_entry {
+                    session.stopped("entry");
+                } else {
+                    session.run = Run::Continue;
+                }
+                Ok(json!({}))
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "SM83" }] })),
+            "stackTrace" => Ok(self.dap_stack_trace()),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                { "name": "IO", "variablesReference": IO_REFERENCE, "expensive": false },
+            ]})),
+            "variables" => match args["variablesReference"].as_u64() {
+                Some(REGISTERS_REFERENCE) => Ok(json!({ "variables": self.dap_registers() })),
+                Some(IO_REFERENCE) => Ok(json!({ "variables": self.dap_io_registers() })),
+                _ => Err("Unknown variables reference".to_owned()),
+            },
+            "continue" => {
+                session.run = Run::Continue;
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" => {
+                // Steps over calls by running until the new frame returns
+                let depth = self.call_stack.len();
+                if let Err(e) = self.step_instruction() {
+                    session.exception(&e);
+                } else if self.call_stack.len() > depth {
+                    session.run = Run::StepOut(depth);
+                } else {
+                    session.stopped("step");
+                }
+                Ok(json!({}))
+            }
+            "stepIn" => {
+                match self.step_instruction() {
+                    Ok(()) => session.stopped("step"),
+                    Err(e) => session.exception(&e),
+                }
+                Ok(json!({}))
+            }
+            "stepOut" => {
+                session.run = match self.call_stack.len() {
+                    0 => Run::Continue,
+                    depth => Run::StepOut(depth - 1),
+                };
+                Ok(json!({}))
+            }
+            "pause" => {
+                session.stopped("pause");
+                Ok(json!({}))
+            }
+            "terminate" => {
+                session.event("terminated", json!({}));
+                session.finished = true;
+                Ok(json!({}))
+            }
+            "disconnect" => {
+                session.finished = true;
+                Ok(json!({}))
+            }
+            _ => Err(format!("Unsupported request {command}")),
+        }
+    }
+
+    fn replace_breakpoints(
+        &mut self,
+        current: &mut BTreeSet<Breakpoint>,
+        new: BTreeSet<Breakpoint>,
+        other: &BTreeSet<Breakpoint>,
+    ) {
+        for &(bank, address) in current.difference(other) {
+            match bank {
+                Some(bank) => self.banked_breakpoints.remove(&(bank, address)),
+                None => self.breakpoints.remove(&address),
+            };
+        }
+        for &(bank, address) in &new {
+            match bank {
+                Some(bank) => self.banked_breakpoints.insert((bank, address)),
+                None => self.breakpoints.insert(address),
+            };
+        }
+        *current = new;
+    }
+
+    // Innermost first, each caller is positioned at its CALL or RST
+    fn dap_stack_trace(&self) -> Value {
+        let mut frames = vec![];
+        let mut pc = self.cpu.pc;
+        for (i, frame) in self.call_stack.iter().rev().enumerate() {
+            frames.push(stack_frame(i, &self.symbolize(frame.target), pc));
+            pc = frame.call_site;
+        }
+        frames.push(stack_frame(frames.len(), "entry", pc));
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    fn dap_registers(&self) -> Vec<Value> {
+        let cpu = &self.cpu;
+        let flags: String = [
+            (cpu.z_flag, 'Z'),
+            (cpu.n_flag, 'N'),
+            (cpu.h_flag, 'H'),
+            (cpu.c_flag, 'C'),
+        ]
+        .iter()
+        .map(|&(set, c)| if set { c } else { '-' })
+        .collect();
+
+        let mut variables: Vec<_> = [
+            ("A", cpu.a),
+            ("F", cpu.read_f()),
+            ("B", cpu.b),
+            ("C", cpu.c),
+            ("D", cpu.d),
+            ("E", cpu.e),
+            ("H", cpu.h),
+            ("L", cpu.l),
+        ]
+        .iter()
+        .map(|(name, value)| variable(name, format!("${value}")))
+        .collect();
+        variables.extend(
+            [
+                ("AF", cpu.read_af()),
+                ("BC", cpu.read_bc()),
+                ("DE", cpu.read_de()),
+                ("HL", cpu.read_hl()),
+                ("SP", cpu.sp),
+                ("PC", cpu.pc),
+            ]
+            .iter()
+            .map(|(name, value)| variable(name, format!("${:04X}", value.0))),
+        );
+        variables.push(variable("Flags", flags));
+        variables
+    }
+
+    fn dap_io_registers(&mut self) -> Vec<Value> {
+        IO_REGISTERS
+            .iter()
+            .map(|&(name, address)| variable(name, format!("${}", self.peek(Address(address)))))
+            .collect()
+    }
+}
+
+fn stack_frame(id: usize, name: &str, pc: Address) -> Value {
+    json!({
+        "id": id,
+        "name": name,
+        "line": 0,
+        "column": 0,
+        "instructionPointerReference": pc.to_string(),
+    })
+}
+
+fn breakpoint_results(addresses: &[Option<Address>]) -> Vec<Value> {
+    addresses
+        .iter()
+        .map(|a| {
+            a.map_or_else(
+                || json!({ "verified": false, "message": "Expected a label or address such as Main+$10" }),
+                |a| json!({ "verified": true, "instructionReference": a.to_string() }),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use serde_json::{json, Value};
+
+    use super::{read_message, write_message, Run, Session};
+    use crate::{
+        constants::ROM_BANK_SIZE,
+        types::{HeaderFix, MemoryBankControllerType},
+        Address, Byte, Device, SymbolTable,
+    };
+
+    fn request(command: &str, arguments: Value) -> Value {
+        json!({ "seq": 1, "type": "request", "command": command, "arguments": arguments })
+    }
+
+    #[test]
+    fn test_message_framing() {
+        let mut buf = vec![];
+        write_message(&mut buf, &json!({ "seq": 1 })).unwrap();
+        assert_eq!(buf, b"Content-Length: 9\r\n\r\n{\"seq\":1}");
+        let mut reader = Cursor::new(buf);
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(json!({ "seq": 1 }))
+        );
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_step_over_call() {
+        // CALL $C010, NOP at $C000; RET at $C010
+        let mut d = Device::new();
+        for (i, b) in [0xCD, 0x10, 0xC0, 0x00].iter().enumerate() {
+            d.poke(Address(0xC000 + i as u16), Byte(*b));
+        }
+        d.poke(Address(0xC010), Byte(0xC9));
+        d.cpu.pc = Address(0xC000);
+
+        let mut session = Session::new(vec![]);
+        d.handle_dap_request(&mut session, &request("stepIn", json!({})))
+            .unwrap();
+        assert_eq!(d.cpu.pc, Address(0xC010));
+        assert_eq!(d.call_stack().len(), 1);
+        let trace = d.dap_stack_trace();
+        assert_eq!(trace["totalFrames"], 2);
+        assert_eq!(trace["stackFrames"][0]["name"], "0xC010");
+        assert_eq!(
+            trace["stackFrames"][1]["instructionPointerReference"],
+            "0xC000"
+        );
+
+        d.cpu.pc = Address(0xC000);
+        d.call_stack.clear();
+        d.cpu.sp = Address(0xFFFE);
+        d.handle_dap_request(&mut session, &request("next", json!({})))
+            .unwrap();
+        assert_eq!(session.run, Run::StepOut(0));
+        d.run_dap(&mut session);
+        assert_eq!(session.run, Run::Stopped);
+        assert_eq!(d.cpu.pc, Address(0xC003));
+        assert!(d.call_stack().is_empty());
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut d = Device::new();
+        d.set_symbols(SymbolTable::parse("00:0150 Main"));
+        let mut session = Session::new(vec![]);
+        let body = d
+            .handle_dap_request(
+                &mut session,
+                &request(
+                    "setFunctionBreakpoints",
+                    json!({ "breakpoints": [{ "name": "Main" }, { "name": "nowhere" }] }),
+                ),
+            )
+            .unwrap();
+        assert_eq!(body["breakpoints"][0]["verified"], true);
+        assert_eq!(body["breakpoints"][1]["verified"], false);
+        d.handle_dap_request(
+            &mut session,
+            &request(
+                "setInstructionBreakpoints",
+                json!({ "breakpoints": [{ "instructionReference": "0x0150", "offset": 2 }] }),
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            d.breakpoints.iter().copied().collect::<Vec<_>>(),
+            [Address(0x0152)]
+        );
+        assert_eq!(
+            d.banked_breakpoints.iter().copied().collect::<Vec<_>>(),
+            [(0, Address(0x0150))]
+        );
+
+        d.handle_dap_request(
+            &mut session,
+            &request("setFunctionBreakpoints", json!({ "breakpoints": [] })),
+        )
+        .unwrap();
+        assert_eq!(
+            d.breakpoints.iter().copied().collect::<Vec<_>>(),
+            [Address(0x0152)]
+        );
+        assert!(d.banked_breakpoints.is_empty());
+    }
+
+    #[test]
+    fn test_variables() {
+        let mut d = Device::new();
+        d.cpu.a = Byte(0x12);
+        d.cpu.z_flag = true;
+        let mut session = Session::new(vec![]);
+        let registers = d
+            .handle_dap_request(
+                &mut session,
+                &request("variables", json!({ "variablesReference": 1 })),
+            )
+            .unwrap();
+        assert_eq!(registers["variables"][0]["value"], "$12");
+        assert_eq!(registers["variables"][14]["value"], "Z---");
+        let io = d
+            .handle_dap_request(
+                &mut session,
+                &request("variables", json!({ "variablesReference": 2 })),
+            )
+            .unwrap();
+        assert_eq!(
+            io["variables"].as_array().unwrap().len(),
+            super::IO_REGISTERS.len()
+        );
+    }
+
+    #[test]
+    fn test_battery_saved_on_disconnect() {
+        let dir = std::env::temp_dir().join("chlorosis_dap_battery_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut rom = vec![0; ROM_BANK_SIZE * 2];
+        // NOP, JP $0150, then enable RAM and write $42 to $A000
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+        let code = [
+            0x3E, 0x0A, 0xEA, 0x00, 0x00, 0x3E, 0x42, 0xEA, 0x00, 0xA0, 0x18, 0xFE,
+        ];
+        rom[0x150..0x150 + code.len()].copy_from_slice(&code);
+        HeaderFix {
+            mapper: Some(MemoryBankControllerType::MBC1_RAM_BATTERY),
+            ram_size: Some(0x02),
+            ..HeaderFix::default()
+        }
+        .apply(&mut rom)
+        .unwrap();
+        let program = dir.join("game.gb");
+        std::fs::write(&program, rom).unwrap();
+
+        // The session ends on disconnect without the run loop ever seeing an exit
+        let mut input = vec![];
+        let launch = json!({ "program": program.to_str().unwrap() });
+        write_message(&mut input, &request("launch", launch)).unwrap();
+        for _ in 0..6 {
+            write_message(&mut input, &request("stepIn", json!({}))).unwrap();
+        }
+        write_message(&mut input, &request("disconnect", json!({}))).unwrap();
+        Device::new().serve_dap(Cursor::new(input), vec![]).unwrap();
+
+        let save = std::fs::read(dir.join("game.sav")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(save[0], 0x42);
+    }
+}
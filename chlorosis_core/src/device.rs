@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeSet,
     path::PathBuf,
     sync::mpsc::{Receiver, Sender, TryRecvError},
     time::{Duration, Instant},
@@ -6,32 +7,74 @@ use std::{
 
 use super::{Address, Byte};
 
-use crate::{constants::*, Event, Infrared, Joypad, KeyCode, Timer};
+use crate::{
+    archive::extract_rom,
+    cheats::CheatList,
+    constants::*,
+    cpu::CallFrame,
+    gdb::GdbStub,
+    mbc::{self, Memory, MMM01},
+    movie::{Movie, MoviePlayer},
+    patch::{apply_patch, find_patch},
+    rewind::RewindBuffer,
+    search::RamSearch,
+    symbols::SymbolTable,
+    trace::Tracer,
+    Error, Event, Infrared, Joypad, KeyCode, Speed, Timer,
+};
 
-use super::{types::CartrigeHeader, AudioProcessor, CentralProcessor, PixelProcessor};
+use super::{
+    types::{CartrigeHeader, RomSizeCheck},
+    AudioProcessor, CentralProcessor, PixelProcessor,
+};
 
 #[derive(Debug)]
 pub struct Device {
     pub cpu: CentralProcessor,
-    ppu: PixelProcessor,
+    pub(crate) ppu: PixelProcessor,
     _audio: Option<AudioProcessor>,
     cartrige: Option<CartrigeHeader>,
-    joypad: Joypad,
-    rom: Vec<Byte>,
-    wram: Vec<Byte>,
-    eram: Vec<Byte>,
-    hram: Vec<Byte>,
-    interrupt: Byte,
-    rom_bank: usize,
-    wram_bank: Byte,
-    infrared: Infrared,
-    timer: Timer,
-    state: DeviceState,
+    // MMM01 carts only, the header of the menu at the end of the ROM
+    pub(crate) menu_header: Option<CartrigeHeader>,
+    pub(crate) joypad: Joypad,
+    pub(crate) rom: Vec<Byte>,
+    // Carts with a mapper bank through it, the rest still use `rom` and `eram`
+    pub(crate) mbc: Option<Box<dyn Memory>>,
+    pub(crate) battery_path: Option<PathBuf>,
+    pub(crate) wram: Vec<Byte>,
+    pub(crate) eram: Vec<Byte>,
+    pub(crate) hram: Vec<Byte>,
+    pub(crate) interrupt: Byte,
+    pub(crate) rom_bank: usize,
+    pub(crate) wram_bank: Byte,
+    pub(crate) infrared: Infrared,
+    pub(crate) timer: Timer,
+    pub(crate) state: DeviceState,
     rom_path: Option<PathBuf>,
+    patch_path: Option<PathBuf>,
+    ppu_sender: Option<Sender<PixelProcessor>>,
+    pub(crate) tracer: Option<Tracer>,
+    pub(crate) cycles: u64,
+    pub(crate) gdb: Option<GdbStub>,
+    pub breakpoints: BTreeSet<Address>,
+    pub(crate) banked_breakpoints: BTreeSet<(usize, Address)>,
+    pub(crate) call_stack: Vec<CallFrame>,
+    pub(crate) symbols: SymbolTable,
+    pub(crate) rewind: RewindBuffer,
+    pub(crate) movie: Option<MoviePlayer>,
+    pub(crate) cheats: CheatList,
+    pub(crate) search: Option<RamSearch>,
+    speed: Speed,
+    fault: Option<Error>,
+    error_sender: Option<Sender<Error>>,
+    rumble: bool,
+    rumble_sender: Option<Sender<bool>>,
+    sent_state: DeviceState,
+    state_sender: Option<Sender<DeviceState>>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum DeviceState {
+pub enum DeviceState {
     Stopped,
     Running,
     Paused,
@@ -44,171 +87,503 @@ impl Device {
             ppu: PixelProcessor::default(),
             _audio: None,
             cartrige: None,
+            menu_header: None,
             joypad: Joypad::default(),
             infrared: Infrared::default(),
             timer: Timer::default(),
             rom: vec![Byte(0); ROM_BANK_SIZE * 2], // TODO: need better way of determing ROM vec size
+            mbc: None,
+            battery_path: None,
             wram: vec![Byte(0); WRAM_SIZE],
-            eram: vec![Byte(0); ERAM_SIZE],
+            eram: vec![],
             rom_bank: 1,
             wram_bank: Byte(1),
             hram: vec![Byte(0); HRAM_SIZE],
             interrupt: Byte(0),
             rom_path: None,
+            patch_path: None,
             state: DeviceState::Stopped,
+            ppu_sender: None,
+            tracer: None,
+            cycles: 0,
+            gdb: None,
+            breakpoints: BTreeSet::new(),
+            banked_breakpoints: BTreeSet::new(),
+            call_stack: vec![],
+            symbols: SymbolTable::default(),
+            rewind: RewindBuffer::default(),
+            movie: None,
+            cheats: CheatList::default(),
+            search: None,
+            speed: Speed::Normal,
+            fault: None,
+            error_sender: None,
+            rumble: false,
+            rumble_sender: None,
+            sent_state: DeviceState::Stopped,
+            state_sender: None,
+        }
+    }
+
+    // Sends a copy of the PPU state once per frame, used by the debugger's viewers
+    pub fn set_ppu_sender(&mut self, sender: Sender<PixelProcessor>) {
+        self.ppu_sender = Some(sender);
+    }
+
+    fn send_ppu_state(&mut self) {
+        if let Some(sender) = &self.ppu_sender {
+            if sender.send(self.ppu.clone()).is_err() {
+                self.ppu_sender = None;
+            }
+        }
+    }
+
+    // Sends anything that goes wrong on the core thread, faults pause the device
+    pub fn set_error_sender(&mut self, sender: Sender<Error>) {
+        self.error_sender = Some(sender);
+    }
+
+    // Sends the device state whenever it changes, including when the core pauses itself on a
+    // fault or breakpoint
+    pub fn set_state_sender(&mut self, sender: Sender<DeviceState>) {
+        self.state_sender = Some(sender);
+    }
+
+    fn send_state(&mut self) {
+        if self.state == self.sent_state {
+            return;
+        }
+        self.sent_state = self.state;
+        if let Some(sender) = &self.state_sender {
+            if sender.send(self.state).is_err() {
+                self.state_sender = None;
+            }
+        }
+    }
+
+    // Sends the cartridge's rumble motor state whenever it changes
+    pub fn set_rumble_sender(&mut self, sender: Sender<bool>) {
+        self.rumble_sender = Some(sender);
+    }
+
+    pub(crate) fn update_rumble(&mut self) {
+        let rumble = self.mbc.as_ref().is_some_and(|m| m.rumble());
+        if rumble == self.rumble {
+            return;
+        }
+        self.rumble = rumble;
+        if let Some(sender) = &self.rumble_sender {
+            if sender.send(rumble).is_err() {
+                self.rumble_sender = None;
+            }
+        }
+    }
+
+    pub(crate) fn report(&mut self, error: Error) {
+        eprintln!("Error: {error}");
+        if let Some(sender) = &self.error_sender {
+            if sender.send(error).is_err() {
+                self.error_sender = None;
+            }
         }
     }
 
+    // Returns once the frontend hangs up
     pub fn run(&mut self, buffer: Sender<Vec<u32>>, event: Receiver<Event>) {
-        // Outer loop should run at 4.1 MHz (or 8.2 if double speed enabled)
+        // Each frame is emulated as fast as possible, then the thread sleeps until it is due
 
-        let mut start = Instant::now();
+        let mut deadline = Instant::now();
         let mut last_frame = Instant::now();
 
         loop {
-            match self.state {
+            let connected = match self.state {
                 DeviceState::Stopped => self.stopped(&event),
-                DeviceState::Running => self.running(&mut start, &mut last_frame, &buffer, &event),
+                DeviceState::Running => {
+                    self.running(&mut deadline, &mut last_frame, &buffer, &event)
+                }
                 DeviceState::Paused => self.paused(&event),
+            };
+            self.send_state();
+            if !connected {
+                self.shutdown();
+                return;
+            }
+        }
+    }
+
+    // Flushes everything that's written out as the device runs
+    pub(crate) fn shutdown(&mut self) {
+        self.stop_trace();
+        self.stop_movie();
+        if let Err(e) = self.save_battery() {
+            eprintln!("Failed to save battery RAM: {e}");
+        }
+    }
+
+    // Handles every waiting event, none once the frontend has hung up or asked to exit, otherwise
+    // whether any of them rewound
+    fn handle_events(&mut self, event: &Receiver<Event>) -> Option<bool> {
+        let mut rewound = false;
+        loop {
+            match event.try_recv() {
+                Ok(Event::Exit) => return None,
+                Ok(event) => {
+                    rewound |= matches!(event, Event::Rewind);
+                    if let Err(e) = self.handle_event(event) {
+                        self.report(e);
+                    }
+                }
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => return Some(rewound),
             }
         }
     }
 
     fn running(
         &mut self,
-        start: &mut Instant,
+        deadline: &mut Instant,
         last_frame: &mut Instant,
         buffer: &Sender<Vec<u32>>,
         event: &Receiver<Event>,
-    ) {
-        const TARGET: Duration = Duration::from_nanos(240); // roughly 240 ns per tick
-
-        // Step CPU one cycle
-        self.step_cpu();
-
-        // Step PPU one cycle
-        self.ppu.step();
-
-        // Render audio
+    ) -> bool {
+        // Emulate up to the end of the current frame
+        loop {
+            if let Err(e) = self.tick() {
+                self.report(e);
+                self.state = DeviceState::Paused;
+                self.gdb_stopped(crate::gdb::SIGILL);
+                return true;
+            }
 
-        let end = Instant::now();
-        if (end - *last_frame) >= Duration::from_millis(16) {
-            // Send
-            if let Some(b) = &self.ppu.buffer {
-                buffer.send(b.to_vec()).unwrap();
-                self.ppu.buffer = None;
+            if self.cpu.cost == 0 && self.at_breakpoint() {
+                eprintln!("Breakpoint at {}", self.symbolize(self.cpu.pc));
+                self.state = DeviceState::Paused;
+                self.gdb_stopped(crate::gdb::SIGTRAP);
+                return true;
             }
 
-            // Get events
-            match event.try_recv() {
-                Ok(event) => self.handle_event(event),
-                Err(err) => match err {
-                    TryRecvError::Disconnected => panic!("{err}"),
-                    TryRecvError::Empty => {}
-                },
+            if self.cycles.is_multiple_of(DOTS_PER_FRAME) {
+                break;
             }
+        }
 
-            *last_frame = end;
+        // Present no faster than the display refreshes, fast forward drops the rest
+        let now = Instant::now();
+        if now - *last_frame >= FRAME_DURATION {
+            if let Some(b) = self.ppu.buffer.take() {
+                if buffer.send(b.to_vec()).is_err() {
+                    return false;
+                }
+            }
+            self.send_ppu_state();
+            *last_frame = now;
         }
 
-        // Sleep as needed
-        let dif = end - *start;
-        if dif < TARGET {
-            std::thread::sleep(TARGET - dif);
+        // Get events, all of them so input never lags behind
+        let Some(rewound) = self.handle_events(event) else {
+            return false;
+        };
+        if !rewound {
+            self.record_rewind();
+        }
+        self.poll_gdb();
+
+        // Sleep until the next frame is due
+        let Some(frame) = self.speed.frame_duration() else {
+            *deadline = Instant::now();
+            return true;
+        };
+        *deadline += frame;
+        let now = Instant::now();
+        if *deadline > now {
+            std::thread::sleep(*deadline - now);
+        } else if now - *deadline > frame {
+            // Too far behind, e.g. after a pause, so don't try to catch up
+            *deadline = now;
         }
-        *start = end;
+        true
     }
 
-    fn stopped(&mut self, event: &Receiver<Event>) {
-        match event.try_recv() {
-            Ok(event) => self.handle_event(event),
-            Err(err) => match err {
-                TryRecvError::Disconnected => panic!("{err}"),
-                TryRecvError::Empty => {}
-            },
+    fn stopped(&mut self, event: &Receiver<Event>) -> bool {
+        if self.handle_events(event).is_none() {
+            return false;
         }
+        self.poll_gdb();
         std::thread::sleep(Duration::from_millis(100));
+        true
     }
 
-    fn paused(&mut self, event: &Receiver<Event>) {
-        match event.try_recv() {
-            Ok(event) => self.handle_event(event),
-            Err(err) => match err {
-                TryRecvError::Disconnected => panic!("{err}"),
-                TryRecvError::Empty => {}
-            },
+    fn paused(&mut self, event: &Receiver<Event>) -> bool {
+        self.send_ppu_state();
+        if self.handle_events(event).is_none() {
+            return false;
         }
-        std::thread::sleep(Duration::from_millis(100));
+        self.poll_gdb();
+
+        // A connected debugger expects quick replies while the target is halted
+        if self.is_gdb_connected() {
+            std::thread::sleep(Duration::from_millis(1));
+        } else {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        true
+    }
+
+    // Memory and instruction faults don't stop the tick, they are returned once it is done
+    pub(crate) fn fault(&mut self, error: Error) {
+        self.fault.get_or_insert(error);
     }
 
-    fn reset(&mut self) {
+    pub fn tick(&mut self) -> Result<(), Error> {
+        // Step CPU one cycle
+        self.step_cpu();
+
+        // Step PPU one cycle
+        if let Err(e) = self.ppu.step() {
+            self.fault(e);
+        }
+
+        if self.cycles.is_multiple_of(DOTS_PER_FRAME) {
+            if self.movie.is_some() {
+                self.movie_frame();
+            }
+            self.apply_cheats();
+        }
+
+        // Render audio
+
+        self.fault.take().map_or(Ok(()), Err)
+    }
+
+    // Runs until the current instruction and its cycles have completed
+    pub fn step_instruction(&mut self) -> Result<(), Error> {
+        self.tick()?;
+        while self.cpu.cost != 0 {
+            self.tick()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn reset(&mut self) -> Result<(), Error> {
+        self.stop_movie();
+        self.save_battery()?;
         let rom = self.rom_path.clone();
+        let patch = self.patch_path.clone();
+        let ppu_sender = self.ppu_sender.take();
+        let error_sender = self.error_sender.take();
+        let rumble_sender = self.rumble_sender.take();
+        let rumble = self.rumble;
+        let state_sender = self.state_sender.take();
+        let sent_state = self.sent_state;
+        let tracer = self.tracer.take();
+        let gdb = self.gdb.take();
+        let breakpoints = std::mem::take(&mut self.breakpoints);
+        let banked_breakpoints = std::mem::take(&mut self.banked_breakpoints);
+        let speed = self.speed;
+        let cheats = std::mem::take(&mut self.cheats);
         *self = Self::new();
+        self.speed = speed;
+        self.ppu_sender = ppu_sender;
+        self.error_sender = error_sender;
+        self.rumble_sender = rumble_sender;
+        self.rumble = rumble;
+        self.update_rumble();
+        self.state_sender = state_sender;
+        self.sent_state = sent_state;
+        self.tracer = tracer;
+        self.gdb = gdb;
+        self.breakpoints = breakpoints;
+        self.banked_breakpoints = banked_breakpoints;
         if let Some(rom) = rom {
-            self.load_cartrige(rom).unwrap();
+            self.load_patched_cartrige(rom, patch.as_deref())?;
         }
+        self.cheats = cheats;
+        Ok(())
     }
 
-    pub fn load_cartrige(
+    pub fn load_cartrige(&mut self, path: impl AsRef<std::path::Path>) -> Result<(), Error> {
+        self.load_patched_cartrige(path, None)
+    }
+
+    // Applies the given patch, otherwise any patch with the same stem as the ROM
+    pub fn load_patched_cartrige(
         &mut self,
         path: impl AsRef<std::path::Path>,
-    ) -> Result<(), std::io::Error> {
-        use std::io::Read;
-        let mut f = std::fs::File::open(path)?;
-        let mut buf = vec![];
-        f.read_to_end(&mut buf)?;
-
-        let mut iter = buf.iter().skip(0x0100);
-        println!("Reading cartrige, {} bytes", buf.len());
-        for i in 0x0100..=ROM_0_END {
-            self.rom[i as usize] = Byte(*iter.next().expect("Early end to cartrige"));
-        }
-        self.cartrige = Some(CartrigeHeader::from_bytes(self.get_header()));
+        patch: Option<&std::path::Path>,
+    ) -> Result<(), Error> {
+        self.save_battery()?;
+        let (mut buf, entry) = extract_rom(std::fs::read(path.as_ref())?)?;
+        if let Some(entry) = entry {
+            eprintln!("Using {entry} from {}", path.as_ref().display());
+        }
+        if let Some(patch) = patch
+            .map(std::path::Path::to_path_buf)
+            .or_else(|| find_patch(path.as_ref()))
+        {
+            buf = apply_patch(&buf, &std::fs::read(&patch)?)?;
+            eprintln!("Applied patch {}", patch.display());
+        }
+
+        eprintln!("Reading cartrige, {} bytes", buf.len());
+        self.load_rom(&buf)?;
+        // Only a cartrige that loaded is the one to reset back to
+        self.rom_path = Some(path.as_ref().to_path_buf());
+        self.patch_path = patch.map(std::path::Path::to_path_buf);
         self.dump_cartrige_header();
-        // TODO: read rest of ROM
+        self.load_symbols_for(path.as_ref())?;
+        if !self.symbols.is_empty() {
+            eprintln!("Loaded {} symbols", self.symbols.len());
+        }
+        self.load_battery_for(path.as_ref())?;
+        self.load_cheats_for(path.as_ref())?;
+        if !self.cheats.is_empty() {
+            eprintln!("Loaded {} cheats", self.cheats.len());
+        }
 
         self.state = DeviceState::Running;
 
         Ok(())
     }
 
+    // Copies a ROM image into memory without logging, the device is left in its current state
+    pub(crate) fn load_rom(&mut self, buf: &[u8]) -> Result<(), Error> {
+        let header = CartrigeHeader::parse(buf)?;
+        header.verify_boot()?;
+        if buf.len() <= ROM_0_END as usize {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        match header.size_check() {
+            RomSizeCheck::Matches => {}
+            RomSizeCheck::Truncated(len) => eprintln!("Warning: ROM is truncated, {len} bytes"),
+            RomSizeCheck::Overdump(len) => eprintln!("Warning: ROM is overdumped, {len} bytes"),
+        }
+        if let Err(e) = header.verify_global_checksum(buf) {
+            eprintln!("Warning: {e}");
+        }
+
+        let bank = 0x0100..=ROM_0_END as usize;
+        for (dst, src) in self.rom[bank.clone()].iter_mut().zip(&buf[bank]) {
+            *dst = Byte(*src);
+        }
+        // TODO: read rest of ROM
+        let menu = MMM01::menu_header(buf);
+        self.mbc = mbc::from_header(&header, menu.as_ref(), buf.to_vec());
+        self.menu_header = menu;
+        // A mapper owns its RAM, the flat RAM is only for carts without one
+        self.eram = match self.mbc {
+            Some(_) => vec![],
+            None => vec![Byte(0); header.ram_size() as usize],
+        };
+        self.battery_path = None;
+        self.update_rumble();
+        self.rewind.clear();
+        // Candidates are offsets into the old cartridge's RAM
+        self.search = None;
+        self.cartrige = Some(header);
+        Ok(())
+    }
+
     pub const fn get_cartridge_header(&self) -> Option<&CartrigeHeader> {
         self.cartrige.as_ref()
     }
 
-    pub fn read(&mut self, address: Address) -> Byte {
+    fn prohibited(&mut self, address: Address) {
+        self.fault(Error::InvalidState(format!(
+            "Prohibited memory access at {address}"
+        )));
+    }
+
+    // Unusable memory reads back as 0xFF
+    fn or_fault(&mut self, value: Result<Byte, Error>) -> Byte {
+        value.unwrap_or_else(|e| {
+            self.fault(e);
+            Byte(0xFF)
+        })
+    }
+
+    fn prohibited_read(&mut self, address: Address) -> Byte {
+        self.prohibited(address);
+        Byte(0xFF)
+    }
+
+    fn unimplemented_read(&mut self, what: &'static str) -> Byte {
+        self.fault(Error::Unimplemented(what));
+        Byte(0xFF)
+    }
+
+    // Carts without a mapper see the flat ROM and whatever RAM the header says is fitted, which
+    // repeats across 0xA000-0xBFFF
+    fn read_cartrige(&self, address: Address) -> Byte {
+        if let Some(mbc) = &self.mbc {
+            return mbc.read(address);
+        }
         match address.0 {
             ROM_0_START..=ROM_0_END => self.rom[address],
+            ROM_1_START..=ROM_1_END => self.rom[self.flat_rom_offset(address)],
+            _ => self
+                .flat_ram_offset(address)
+                .map_or(Byte(0xFF), |offset| self.eram[offset]),
+        }
+    }
+
+    fn write_cartrige(&mut self, address: Address, value: Byte) {
+        if let Some(mbc) = &mut self.mbc {
+            mbc.write(address, value);
+            return;
+        }
+        match address.0 {
+            ROM_0_START..=ROM_0_END => self.rom[address] = value,
             ROM_1_START..=ROM_1_END => {
-                self.rom[address + Address(ROM_1_START) * (self.rom_bank - 1)]
+                let offset = self.flat_rom_offset(address);
+                self.rom[offset] = value
+            }
+            _ => {
+                if let Some(offset) = self.flat_ram_offset(address) {
+                    self.eram[offset] = value
+                }
             }
-            VRAM_START..=VRAM_END => self.ppu.read_vram(address),
-            ERAM_START..=ERAM_END => self.eram[address - Address(ERAM_START)], // External ram
+        }
+    }
+
+    fn flat_rom_offset(&self, address: Address) -> usize {
+        self.cartrige_bank() * ROM_BANK_SIZE + (address.0 - ROM_1_START) as usize
+    }
+
+    fn flat_ram_offset(&self, address: Address) -> Option<usize> {
+        let offset = (address.0 - ERAM_START) as usize;
+        (!self.eram.is_empty()).then(|| offset % self.eram.len())
+    }
+
+    pub fn read(&mut self, address: Address) -> Byte {
+        match address.0 {
+            ROM_0_START..=ROM_1_END => self.cheats.patch_rom(address, self.read_cartrige(address)),
+            VRAM_START..=VRAM_END => self.or_fault(self.ppu.read_vram(address)),
+            ERAM_START..=ERAM_END => self.read_cartrige(address), // External ram
             WRAM_0_START..=WRAM_0_END => self.wram[address - Address(WRAM_0_START)],
             WRAM_1_START..=WRAM_1_END => {
                 self.wram[address + (Address(WRAM_BANK_SIZE as u16) * self.wram_bank.0 as usize)
                     - Address(WRAM_1_START)]
             }
-            DEADZONE_0_START..=DEADZONE_0_END => panic!("Prohibited memory access at {address}"),
-            OAM_START..=OAM_END => self.ppu.read_oam(address),
-            DEADZONE_1_START..=DEADZONE_1_END => panic!("Prohibited memory access at {address}"),
+            DEADZONE_0_START..=DEADZONE_0_END => self.prohibited_read(address),
+            OAM_START..=OAM_END => self.or_fault(self.ppu.read_oam(address)),
+            DEADZONE_1_START..=DEADZONE_1_END => self.prohibited_read(address),
 
             // IO START
-            0xFF00 => self.joypad.read(), // Joypad
-            0xFF01..=0xFF02 => Byte(0),   // TODO: Serial
-            0xFF03 => panic!("Prohibited memory access at {address}"), // Prohibited
-            0xFF04..=0xFF07 => self.timer.read(address), // Timers
-            0xFF08..=0xFF0E => panic!("Prohibited memory access at {address}"), // Prohibited
-            0xFF0F => self.interrupt,     // Interrupt
-            0xFF10..=0xFF3F => unimplemented!("Audio"), // Audio
-            0xFF40..=0xFF55 => self.ppu.read_io(address), // PPU
-            0xFF56 => self.infrared.read(), // Infrared Com Port
-            0xFF57..=0xFF6F => self.ppu.read_io(address), // PPU
-            0xFF70 => self.wram_bank,     // WRAM BANK
-            0xFF71..=0xFF75 => panic!("Prohibited memory access at {address}"), // Prohibited
-            0xFF76 => unimplemented!("Audio"), // Audio 1&2
-            0xFF77 => unimplemented!("Audio"), // Audio 3&4
-            0xFF78..=0xFF7F => panic!("Prohibited memory access at {address}"), // Prohibited
+            0xFF00 => self.joypad.read(),                     // Joypad
+            0xFF01..=0xFF02 => Byte(0),                       // TODO: Serial
+            0xFF03 => self.prohibited_read(address),          // Prohibited
+            0xFF04..=0xFF07 => self.timer.read(address),      // Timers
+            0xFF08..=0xFF0E => self.prohibited_read(address), // Prohibited
+            0xFF0F => self.interrupt,                         // Interrupt
+            0xFF10..=0xFF3F => self.unimplemented_read("Audio"), // Audio
+            0xFF40..=0xFF55 => self.ppu.read_io(address),     // PPU
+            0xFF56 => self.infrared.read(),                   // Infrared Com Port
+            0xFF57..=0xFF6F => self.ppu.read_io(address),     // PPU
+            0xFF70 => self.wram_bank,                         // WRAM BANK
+            0xFF71..=0xFF75 => self.prohibited_read(address), // Prohibited
+            0xFF76 => self.unimplemented_read("Audio"),       // Audio 1&2
+            0xFF77 => self.unimplemented_read("Audio"),       // Audio 3&4
+            0xFF78..=0xFF7F => self.prohibited_read(address), // Prohibited
             // IO END
             HRAM_START..=HRAM_END => self.hram[address - Address(HRAM_START)],
             INTERRUPT_ENABLE => self.interrupt,
@@ -217,39 +592,45 @@ impl Device {
 
     pub fn write(&mut self, address: Address, value: Byte) {
         match address.0 {
-            ROM_0_START..=ROM_0_END => self.rom[address] = value,
-            ROM_1_START..=ROM_1_END => {
-                self.rom[address + Address(ROM_1_START) * (self.rom_bank - 1)] = value
+            ROM_0_START..=ROM_1_END => {
+                self.write_cartrige(address, value); // Bank registers
+                self.update_rumble();
             }
             VRAM_START..=VRAM_END => {
-                self.ppu.write_vram(address, value);
+                if let Err(e) = self.ppu.write_vram(address, value) {
+                    self.fault(e);
+                }
             }
-            ERAM_START..=ERAM_END => self.eram[address - Address(ERAM_START)] = value, // External ram
+            ERAM_START..=ERAM_END => self.write_cartrige(address, value), // External ram
             WRAM_0_START..=WRAM_0_END => self.wram[address - Address(WRAM_0_START)] = value,
             WRAM_1_START..=WRAM_1_END => {
                 self.wram[address + (Address(WRAM_BANK_SIZE as u16) * self.wram_bank.0 as usize)
                     - Address(WRAM_1_START)] = value
             }
-            DEADZONE_0_START..=DEADZONE_0_END => panic!("Prohibited memory access at {address}"),
-            OAM_START..=OAM_END => self.ppu.write_oam(address, value),
-            DEADZONE_1_START..=DEADZONE_1_END => panic!("Prohibited memory access at {address}"),
+            DEADZONE_0_START..=DEADZONE_0_END => self.prohibited(address),
+            OAM_START..=OAM_END => {
+                if let Err(e) = self.ppu.write_oam(address, value) {
+                    self.fault(e);
+                }
+            }
+            DEADZONE_1_START..=DEADZONE_1_END => self.prohibited(address),
 
             // IO_START
             0xFF00 => self.joypad.write(value), // Joypad
             0xFF01..=0xFF02 => {}               // TODO: Serial
-            0xFF03 => panic!("Prohibited memory access at {address}"), // Prohibited
+            0xFF03 => self.prohibited(address), // Prohibited
             0xFF04..=0xFF07 => self.timer.write(address, value), // Timers
-            0xFF08..=0xFF0E => panic!("Prohibited memory access at {address}"), // Prohibited
+            0xFF08..=0xFF0E => self.prohibited(address), // Prohibited
             0xFF0F => self.interrupt = value,   // Interrupt
-            0xFF10..=0xFF3F => unimplemented!("Audio"), // Audio
+            0xFF10..=0xFF3F => self.fault(Error::Unimplemented("Audio")), // Audio
             0xFF40..=0xFF55 => self.ppu.write_io(address, value), // PPU
             0xFF56 => self.infrared.write(value), // Infrared Com Port
             0xFF57..=0xFF6F => self.ppu.write_io(address, value), // PPU
             0xFF70 => self.wram_bank = value,   // WRAM BANK
-            0xFF71..=0xFF75 => panic!("Prohibited memory access at {address}"), // Prohibited
-            0xFF76 => unimplemented!("Audio"),  // Audio channels 1 & 2,
-            0xFF77 => unimplemented!("Audio"),  // Audio channels 3 & 4,
-            0xFF78..=0xFF7F => panic!("Prohibited memory access at {address}"), // Prohibited
+            0xFF71..=0xFF75 => self.prohibited(address), // Prohibited
+            0xFF76 => self.fault(Error::Unimplemented("Audio")), // Audio channels 1 & 2,
+            0xFF77 => self.fault(Error::Unimplemented("Audio")), // Audio channels 3 & 4,
+            0xFF78..=0xFF7F => self.prohibited(address), // Prohibited
             // IO END
             HRAM_START..=HRAM_END => self.hram[address - Address(HRAM_START)] = value,
             INTERRUPT_ENABLE => self.interrupt = value,
@@ -260,6 +641,14 @@ impl Device {
         self.rom_bank = value;
     }
 
+    // Bank currently mapped at 0x4000-0x7FFF, wrapping at the size of the ROM
+    pub(crate) fn cartrige_bank(&self) -> usize {
+        self.mbc.as_ref().map_or_else(
+            || self.rom_bank % (self.rom.len() / ROM_BANK_SIZE),
+            |m| m.rom_bank(),
+        )
+    }
+
     pub fn get_header(&self) -> &[Byte] {
         &self.rom[0x100..=0x14F]
     }
@@ -269,7 +658,7 @@ impl Device {
             let byte = self.read(Address(i));
             if i % 32 == 0 {
                 println!();
-                print!("{}: ", Address(i));
+                print!("{}: ", self.symbolize(Address(i)));
             }
             if i % 8 == 0 {
                 print!("  ");
@@ -280,36 +669,86 @@ impl Device {
 
     pub fn dump_cpu(&self) {
         println!("CPU State: ");
+        println!("At {}", self.symbolize(self.cpu.pc));
         self.cpu.dump_state();
     }
 
     pub fn dump_cartrige_header(&self) {
         self.cartrige
             .as_ref()
-            .map_or_else(|| println!("No cartrige loaded"), |c| println!("{c:#?}"))
+            .map_or_else(|| eprintln!("No cartrige loaded"), |c| eprintln!("{c:#?}"))
     }
 
-    fn handle_event(&mut self, event: Event) {
-        println!("{event:?}");
+    fn handle_event(&mut self, event: Event) -> Result<(), Error> {
+        eprintln!("{event:?}");
         match event {
             Event::KeyDown(k) => self.handle_keydown(k),
             Event::KeyUp(k) => self.handle_keyup(k),
-            Event::LoadFile(f) => self.load_cartrige(f).unwrap(),
+            Event::LoadFile(f) => self.load_cartrige(f)?,
+            Event::LoadPatchedFile(f, patch) => self.load_patched_cartrige(f, Some(&patch))?,
             Event::Pause => self.state = DeviceState::Paused,
+            Event::SetSpeed(speed) => self.speed = speed,
             Event::Run => self.state = DeviceState::Running,
-            Event::Reset => self.reset(),
-            Event::Exit => std::process::exit(0),
-            _ => unimplemented!("Unimplemented event {event:?}"),
+            Event::Reset => self.reset()?,
+            Event::WritePalette(p, c, v) => self.ppu.write_palette_value(p, c, v),
+            Event::StartTrace(options) => {
+                if let Err(e) = self.start_trace(options) {
+                    eprintln!("Failed to start trace: {e}");
+                }
+            }
+            Event::StopTrace => self.stop_trace(),
+            Event::Rewind => self.rewind(),
+            Event::SaveState(path) => {
+                if let Err(e) = std::fs::write(&path, self.save_state()) {
+                    eprintln!("Failed to save state to {}: {e}", path.display());
+                }
+            }
+            Event::LoadState(path) => self.load_state(&std::fs::read(path)?)?,
+            Event::RecordMovie(path, power_on) => self.record_movie(path, power_on)?,
+            Event::PlayMovie(path) => self.play_movie(Movie::load(&path)?)?,
+            Event::StopMovie => self.stop_movie(),
+            Event::AddCheat(code) => {
+                if let Err(e) = self.add_cheat(&code) {
+                    eprintln!("{e}");
+                }
+            }
+            Event::RemoveCheat(code) => {
+                if !self.remove_cheat(&code) {
+                    println!("No cheat {code}");
+                }
+            }
+            Event::ListCheats => self.list_cheats(),
+            Event::StartSearch(size) => {
+                let candidates = self.start_search(size);
+                println!("{candidates} candidates");
+            }
+            Event::FilterSearch(filter) => match self.filter_search(filter) {
+                Some(candidates) => println!("{candidates} candidates"),
+                None => println!("No search started"),
+            },
+            Event::ListSearch => self.list_search(),
+            Event::LoadCheats(path) => match CheatList::load(&path) {
+                Ok(cheats) => self.cheats = cheats,
+                Err(e) => eprintln!("Failed to load cheats from {}: {e}", path.display()),
+            },
+            Event::Exit => {} // Ends the run loop, see handle_events
         }
+        Ok(())
     }
 
-    fn handle_keydown(&mut self, keys: Vec<KeyCode>) {
+    pub(crate) fn handle_keydown(&mut self, keys: Vec<KeyCode>) {
+        if self.movie_input(|pending| keys.iter().for_each(|&k| *pending |= Joypad::mask(k))) {
+            return;
+        }
         for b in keys {
             self.joypad.press(b);
         }
     }
 
-    fn handle_keyup(&mut self, keys: Vec<KeyCode>) {
+    pub(crate) fn handle_keyup(&mut self, keys: Vec<KeyCode>) {
+        if self.movie_input(|pending| keys.iter().for_each(|&k| *pending &= !Joypad::mask(k))) {
+            return;
+        }
         for b in keys {
             self.joypad.release(b);
         }
@@ -321,3 +760,77 @@ impl Default for Device {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc::channel, time::Duration};
+
+    use super::{Device, DeviceState};
+    use crate::{
+        constants::ROM_BANK_SIZE,
+        types::{HeaderFix, MemoryBankControllerType},
+        Address, Byte, Event,
+    };
+
+    #[test]
+    fn test_breakpoint_state_sent() {
+        // JP $C000, spins forever
+        let mut d = Device::new();
+        d.poke(Address(0xC000), Byte(0xC3));
+        d.poke(Address(0xC001), Byte(0x00));
+        d.poke(Address(0xC002), Byte(0xC0));
+        d.cpu.pc = Address(0xC000);
+        d.breakpoints.insert(Address(0xC000));
+        d.state = DeviceState::Running;
+
+        // The frontend only hears about the pause from the core
+        let (states, receiver) = channel();
+        d.set_state_sender(states);
+        let (buffer, _frames) = channel();
+        let (events, event_receiver) = channel();
+        let core = std::thread::spawn(move || d.run(buffer, event_receiver));
+        let state = receiver.recv_timeout(Duration::from_secs(5));
+        events.send(Event::Exit).unwrap();
+        core.join().unwrap();
+        assert_eq!(state, Ok(DeviceState::Paused));
+    }
+
+    #[test]
+    fn test_mapper_owns_ram() {
+        let mut rom = vec![0; ROM_BANK_SIZE * 2];
+        HeaderFix {
+            mapper: Some(MemoryBankControllerType::MBC1_RAM),
+            ram_size: Some(0x03),
+            ..HeaderFix::default()
+        }
+        .apply(&mut rom)
+        .unwrap();
+        let mut d = Device::new();
+        d.load_rom(&rom).unwrap();
+        assert!(d.eram.is_empty());
+        assert_eq!(d.mbc.as_ref().unwrap().ram().len(), 0x8000);
+    }
+
+    #[test]
+    fn test_failed_load_keeps_paths() {
+        let dir = std::env::temp_dir().join("chlorosis_failed_load_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut rom = vec![0; ROM_BANK_SIZE * 2];
+        HeaderFix::default().apply(&mut rom).unwrap();
+        let good = dir.join("game.gb");
+        std::fs::write(&good, &rom).unwrap();
+        let bad = dir.join("bad.gb");
+        std::fs::write(&bad, vec![0; ROM_BANK_SIZE * 2]).unwrap();
+        let patch = dir.join("broken.ips");
+        std::fs::write(&patch, b"PATCH\x00").unwrap();
+
+        // A reset should go back to the last cartrige that loaded, not the one that failed
+        let mut d = Device::new();
+        d.load_cartrige(&good).unwrap();
+        assert!(d.load_patched_cartrige(&good, Some(&patch)).is_err());
+        assert!(d.load_cartrige(&bad).is_err());
+        assert_eq!(d.rom_path, Some(good));
+        assert_eq!(d.patch_path, None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
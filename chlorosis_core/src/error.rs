@@ -0,0 +1,51 @@
+use std::{fmt::Display, io};
+
+use crate::HeaderError;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    BadRom(HeaderError),
+    UnsupportedMapper(u8),       // Cartridge type byte
+    Unimplemented(&'static str), // Hardware the emulator doesn't cover yet
+    InvalidState(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::BadRom(e) => write!(f, "Bad ROM: {e}"),
+            Self::UnsupportedMapper(code) => {
+                write!(f, "Unsupported cartridge type 0x{code:02X}")
+            }
+            Self::Unimplemented(what) => write!(f, "Unimplemented: {what}"),
+            Self::InvalidState(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::BadRom(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<HeaderError> for Error {
+    fn from(e: HeaderError) -> Self {
+        match e {
+            HeaderError::UnknownMapper(code) => Self::UnsupportedMapper(code),
+            e => Self::BadRom(e),
+        }
+    }
+}
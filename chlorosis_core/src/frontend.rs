@@ -1,5 +1,8 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
+use crate::{constants::FRAME_DURATION, Palette, SearchFilter, SearchSize, TraceOptions};
+
+// Output from the core goes over the channels set on `Device` instead, see the README
 pub trait Frontend {
     fn draw(&self, buffer: &[u32]);
     fn get_input(&self) -> Event;
@@ -11,14 +14,51 @@ pub enum Event {
     KeyDown(Vec<KeyCode>),
     KeyUp(Vec<KeyCode>),
     LoadFile(PathBuf),
+    LoadPatchedFile(PathBuf, PathBuf), // ROM, IPS, UPS or BPS patch
     SaveState(PathBuf),
     LoadState(PathBuf),
+    WritePalette(Palette, u8, u16), // Palette, colour index, shade or RGB555 value
+    StartTrace(TraceOptions),
+    StopTrace,
+    Rewind,                     // Steps back one snapshot, sent repeatedly while held
+    RecordMovie(PathBuf, bool), // Path, start from power on rather than the current state
+    PlayMovie(PathBuf),
+    StopMovie,
+    AddCheat(String), // Game Genie, GameShark or raw bank:address=value code
+    RemoveCheat(String),
+    ListCheats,
+    LoadCheats(PathBuf),
+    StartSearch(SearchSize),
+    FilterSearch(SearchFilter),
+    ListSearch,
+    SetSpeed(Speed),
     Run,
     Pause,
     Reset,
     Exit,
 }
 
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub enum Speed {
+    #[default]
+    Normal,
+    FastForward(u32), // Frames emulated per real frame
+    SlowMotion(u32),  // Real frames per emulated frame
+    Uncapped,
+}
+
+impl Speed {
+    // Wall time each emulated frame should take, none when uncapped
+    pub fn frame_duration(self) -> Option<Duration> {
+        match self {
+            Self::Normal => Some(FRAME_DURATION),
+            Self::FastForward(n) => Some(FRAME_DURATION / n.max(1)),
+            Self::SlowMotion(n) => Some(FRAME_DURATION * n.max(1)),
+            Self::Uncapped => None,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum KeyCode {
     Up,
@@ -0,0 +1,547 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+};
+
+use crate::{
+    constants::*,
+    device::{Device, DeviceState},
+    Address, Byte,
+};
+
+// GDB remote serial protocol stub, see https://sourceware.org/gdb/onlinedocs/gdb/Remote-Protocol.html
+// Registers are numbered A F B C D E H L SP PC, matching TARGET_XML
+
+const REGISTER_COUNT: usize = 10;
+// Largest packet we accept or send, advertised to GDB in hex
+const PACKET_SIZE: usize = 0x4000;
+const SIGINT: u8 = 2;
+pub const SIGILL: u8 = 4;
+pub const SIGTRAP: u8 = 5;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chlorosis.sm83.core">
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="f" bitsize="8" type="uint8"/>
+    <reg name="b" bitsize="8" type="uint8"/>
+    <reg name="c" bitsize="8" type="uint8"/>
+    <reg name="d" bitsize="8" type="uint8"/>
+    <reg name="e" bitsize="8" type="uint8"/>
+    <reg name="h" bitsize="8" type="uint8"/>
+    <reg name="l" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+#[derive(Debug)]
+pub struct GdbStub {
+    listener: TcpListener,
+    stream: Option<TcpStream>,
+    buffer: Vec<u8>,
+    no_ack: bool,
+    running: bool, // A stop reply is owed once the target halts
+}
+
+impl GdbStub {
+    fn bind(addr: impl ToSocketAddrs) -> Result<Self, io::Error> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            stream: None,
+            buffer: vec![],
+            no_ack: false,
+            running: false,
+        })
+    }
+
+    fn send(&mut self, data: &str) {
+        if let Some(s) = &mut self.stream {
+            if let Err(e) = s.write_all(encode_packet(data).as_bytes()) {
+                eprintln!("GDB connection lost: {e}");
+                self.stream = None;
+            }
+        }
+    }
+
+    // Reads whatever is available without blocking, returns false once the connection closes
+    fn receive(&mut self) -> bool {
+        let Some(s) = &mut self.stream else {
+            return false;
+        };
+        let mut buf = [0; 1024];
+        loop {
+            match s.read(&mut buf) {
+                Ok(0) => {
+                    self.stream = None;
+                    return false;
+                }
+                Ok(n) => self.buffer.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return true,
+                Err(e) => {
+                    eprintln!("GDB connection lost: {e}");
+                    self.stream = None;
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+pub fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0, |a, b| a.wrapping_add(b))
+}
+
+pub fn encode_packet(data: &str) -> String {
+    format!("${data}#{:02x}", checksum(data))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Input {
+    Packet(String),
+    Interrupt,
+    Invalid,
+}
+
+// Takes the next complete packet or interrupt from the buffer, acknowledgements are dropped
+fn next_input(buffer: &mut Vec<u8>) -> Option<Input> {
+    loop {
+        match buffer.first()? {
+            0x03 => {
+                buffer.remove(0);
+                return Some(Input::Interrupt);
+            }
+            b'$' => break,
+            _ => {
+                buffer.remove(0);
+            }
+        }
+    }
+    let end = buffer.iter().position(|&b| b == b'#')?;
+    if buffer.len() < end + 3 {
+        return None;
+    }
+    let packet: Vec<u8> = buffer.drain(..end + 3).collect();
+    let data = String::from_utf8_lossy(&packet[1..end]).into_owned();
+    let expected = std::str::from_utf8(&packet[end + 1..])
+        .ok()
+        .and_then(|c| u8::from_str_radix(c, 16).ok());
+    if expected == Some(checksum(&data)) {
+        Some(Input::Packet(data))
+    } else {
+        Some(Input::Invalid)
+    }
+}
+
+// 8 bit registers followed by SP and PC, little endian
+const fn register_range(n: usize) -> std::ops::Range<usize> {
+    if n < 8 {
+        n..n + 1
+    } else {
+        8 + (n - 8) * 2..10 + (n - 8) * 2
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| s.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect()
+}
+
+fn parse_address_length(s: &str) -> Option<(u16, usize)> {
+    let (addr, len) = s.split_once(',')?;
+    Some((
+        u16::from_str_radix(addr, 16).ok()?,
+        usize::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+impl Device {
+    // Accepts a single debugger at a time, polled from the run loop
+    pub fn listen_gdb(&mut self, addr: impl ToSocketAddrs) -> Result<(), io::Error> {
+        let stub = GdbStub::bind(addr)?;
+        eprintln!("GDB server listening on {}", stub.listener.local_addr()?);
+        self.gdb = Some(stub);
+        Ok(())
+    }
+
+    pub(crate) fn poll_gdb(&mut self) {
+        let Some(gdb) = &mut self.gdb else {
+            return;
+        };
+
+        if gdb.stream.is_none() {
+            match gdb.listener.accept() {
+                Ok((stream, addr)) => {
+                    if let Err(e) = stream.set_nonblocking(true) {
+                        eprintln!("Failed to accept GDB connection: {e}");
+                        return;
+                    }
+                    eprintln!("GDB connected from {addr}");
+                    gdb.stream = Some(stream);
+                    gdb.no_ack = false;
+                    gdb.running = false;
+                    gdb.buffer.clear();
+                    self.state = DeviceState::Paused;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    eprintln!("Failed to accept GDB connection: {e}");
+                    return;
+                }
+            }
+        }
+
+        if !gdb.receive() {
+            eprintln!("GDB disconnected");
+            return;
+        }
+
+        while let Some(input) = self.gdb.as_mut().and_then(|g| next_input(&mut g.buffer)) {
+            match input {
+                Input::Interrupt => {
+                    self.state = DeviceState::Paused;
+                    self.gdb_stopped(SIGINT);
+                }
+                Input::Invalid => self.gdb_write_raw(b"-"),
+                Input::Packet(p) => {
+                    if !self.gdb.as_ref().is_some_and(|g| g.no_ack) {
+                        self.gdb_write_raw(b"+");
+                    }
+                    if let Some(reply) = self.handle_gdb_packet(&p) {
+                        if let Some(g) = &mut self.gdb {
+                            g.send(&reply);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) fn is_gdb_connected(&self) -> bool {
+        self.gdb.as_ref().is_some_and(|g| g.stream.is_some())
+    }
+
+    // Sends the stop reply owed for a continue, if any
+    pub(crate) fn gdb_stopped(&mut self, signal: u8) {
+        if let Some(g) = &mut self.gdb {
+            if g.running {
+                g.running = false;
+                g.send(&format!("S{signal:02x}"));
+            }
+        }
+    }
+
+    fn gdb_write_raw(&mut self, data: &[u8]) {
+        if let Some(s) = self.gdb.as_mut().and_then(|g| g.stream.as_mut()) {
+            let _ = s.write_all(data);
+        }
+    }
+
+    // Returns the reply, or None when it is deferred until the target stops
+    fn handle_gdb_packet(&mut self, packet: &str) -> Option<String> {
+        let (command, args) = packet.split_at(packet.len().min(1));
+        let reply = match command {
+            "?" => format!("S{SIGTRAP:02x}"),
+            "g" => to_hex(&self.gdb_registers()),
+            "G" => match from_hex(args) {
+                Some(b) if b.len() == 12 => {
+                    for n in 0..REGISTER_COUNT {
+                        self.gdb_write_register(n, &b[register_range(n)]);
+                    }
+                    "OK".to_owned()
+                }
+                _ => "E01".to_owned(),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(n) if n < REGISTER_COUNT => to_hex(&self.gdb_registers()[register_range(n)]),
+                _ => "E01".to_owned(),
+            },
+            "P" => {
+                let parsed = args
+                    .split_once('=')
+                    .and_then(|(n, v)| Some((usize::from_str_radix(n, 16).ok()?, from_hex(v)?)));
+                match parsed {
+                    Some((n, v)) if n < REGISTER_COUNT => {
+                        self.gdb_write_register(n, &v);
+                        "OK".to_owned()
+                    }
+                    _ => "E01".to_owned(),
+                }
+            }
+            "m" => match parse_address_length(args) {
+                Some((addr, len)) => {
+                    // Nothing past 0xFFFF, and no more than fits in a reply at two digits a byte
+                    let len = len.min(0x10000 - addr as usize).min(PACKET_SIZE / 2);
+                    let bytes: Vec<u8> = (0..len)
+                        .map(|i| self.peek(Address(addr.wrapping_add(i as u16))).0)
+                        .collect();
+                    to_hex(&bytes)
+                }
+                None => "E01".to_owned(),
+            },
+            "M" => {
+                let parsed = args
+                    .split_once(':')
+                    .and_then(|(a, d)| Some((parse_address_length(a)?, from_hex(d)?)));
+                match parsed {
+                    Some(((addr, len), data)) if data.len() == len => {
+                        for (i, b) in data.iter().enumerate() {
+                            self.poke(Address(addr.wrapping_add(i as u16)), Byte(*b));
+                        }
+                        "OK".to_owned()
+                    }
+                    _ => "E01".to_owned(),
+                }
+            }
+            "c" => {
+                self.gdb_resume();
+                return None;
+            }
+            "s" => match self.step_instruction() {
+                Ok(()) => format!("S{SIGTRAP:02x}"),
+                Err(e) => {
+                    self.report(e);
+                    format!("S{SIGILL:02x}")
+                }
+            },
+            "Z" | "z" => {
+                // Software (0) and hardware (1) breakpoints are the same thing in an emulator
+                let mut parts = args.split(',');
+                let kind = parts.next();
+                let addr = parts.next().and_then(|a| u16::from_str_radix(a, 16).ok());
+                match (kind, addr) {
+                    (Some("0" | "1"), Some(addr)) => {
+                        if command == "Z" {
+                            self.breakpoints.insert(Address(addr));
+                        } else {
+                            self.breakpoints.remove(&Address(addr));
+                        }
+                        "OK".to_owned()
+                    }
+                    _ => String::new(),
+                }
+            }
+            "k" => {
+                if let Some(g) = &mut self.gdb {
+                    g.stream = None;
+                }
+                return None;
+            }
+            "D" => {
+                self.breakpoints.clear();
+                self.state = DeviceState::Running;
+                if let Some(g) = &mut self.gdb {
+                    g.send("OK");
+                    g.stream = None;
+                }
+                return None;
+            }
+            "H" => "OK".to_owned(),
+            "q" | "Q" => self.handle_gdb_query(packet),
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    fn handle_gdb_query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            format!("PacketSize={PACKET_SIZE:x};qXfer:features:read+;QStartNoAckMode+")
+        } else if packet == "QStartNoAckMode" {
+            if let Some(g) = &mut self.gdb {
+                g.send("OK");
+                g.no_ack = true;
+            }
+            String::new()
+        } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            match parse_address_length(range) {
+                Some((offset, len)) => {
+                    let start = (offset as usize).min(TARGET_XML.len());
+                    let end = start.saturating_add(len).min(TARGET_XML.len());
+                    let prefix = if end == TARGET_XML.len() { 'l' } else { 'm' };
+                    format!("{prefix}{}", &TARGET_XML[start..end])
+                }
+                None => "E01".to_owned(),
+            }
+        } else {
+            match packet {
+                "qAttached" => "1".to_owned(),
+                "qC" => "QC1".to_owned(),
+                "qfThreadInfo" => "m1".to_owned(),
+                "qsThreadInfo" => "l".to_owned(),
+                _ => String::new(),
+            }
+        }
+    }
+
+    const fn gdb_resume(&mut self) {
+        if let Some(g) = &mut self.gdb {
+            g.running = true;
+        }
+        self.state = DeviceState::Running;
+    }
+
+    fn gdb_registers(&self) -> [u8; 12] {
+        let cpu = &self.cpu;
+        let (sp, pc) = (cpu.sp.0.to_le_bytes(), cpu.pc.0.to_le_bytes());
+        [
+            cpu.a.0,
+            cpu.read_f().0,
+            cpu.b.0,
+            cpu.c.0,
+            cpu.d.0,
+            cpu.e.0,
+            cpu.h.0,
+            cpu.l.0,
+            sp[0],
+            sp[1],
+            pc[0],
+            pc[1],
+        ]
+    }
+
+    fn gdb_write_register(&mut self, n: usize, value: &[u8]) {
+        let byte = Byte(value.first().copied().unwrap_or(0));
+        let pair = Address(u16::from_le_bytes([
+            byte.0,
+            value.get(1).copied().unwrap_or(0),
+        ]));
+        let cpu = &mut self.cpu;
+        match n {
+            0 => cpu.a = byte,
+            1 => cpu.write_f(byte),
+            2 => cpu.b = byte,
+            3 => cpu.c = byte,
+            4 => cpu.d = byte,
+            5 => cpu.e = byte,
+            6 => cpu.h = byte,
+            7 => cpu.l = byte,
+            8 => cpu.sp = pair,
+            9 => cpu.pc = pair,
+            _ => {}
+        }
+    }
+
+    // Debugger memory access, regions that would panic or are unimplemented read as 0xFF and ignore writes
+    pub fn peek(&mut self, address: Address) -> Byte {
+        match address.0 {
+            VRAM_START..=VRAM_END => {
+                self.ppu.vram[(address.0 - VRAM_START) as usize
+                    + VRAM_BANK_SIZE * (self.ppu.vram_bank.0 as usize & 1)]
+            }
+            OAM_START..=OAM_END => self.ppu.oam[(address.0 - OAM_START) as usize],
+            _ if Self::is_debug_accessible(address) => self.read(address),
+            _ => Byte(0xFF),
+        }
+    }
+
+    pub fn poke(&mut self, address: Address, value: Byte) {
+        match address.0 {
+            VRAM_START..=VRAM_END => {
+                self.ppu.vram[(address.0 - VRAM_START) as usize
+                    + VRAM_BANK_SIZE * (self.ppu.vram_bank.0 as usize & 1)] = value
+            }
+            OAM_START..=OAM_END => self.ppu.oam[(address.0 - OAM_START) as usize] = value,
+            ERAM_START..=ERAM_END => match &mut self.mbc {
+                Some(mbc) => mbc.poke_ram(address, value),
+                None => self.write(address, value),
+            },
+            _ if Self::is_debug_accessible(address) => self.write(address, value),
+            _ => {}
+        }
+    }
+
+    const fn is_debug_accessible(address: Address) -> bool {
+        !matches!(
+            address.0,
+            DEADZONE_0_START..=DEADZONE_0_END
+                | DEADZONE_1_START..=DEADZONE_1_END
+                | 0xFF03
+                | 0xFF08..=0xFF0E
+                | 0xFF10..=0xFF3F
+                | 0xFF4C
+                | 0xFF4E
+                | 0xFF50
+                | 0xFF51..=0xFF54 // Write only HDMA source and destination
+                | 0xFF56..=0xFF67
+                | 0xFF6D..=0xFF6F
+                | 0xFF71..=0xFF7F
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_packet, next_input, Input};
+    use crate::{Address, Byte, Device};
+
+    #[test]
+    fn test_packet_framing() {
+        assert_eq!(encode_packet("OK"), "$OK#9a");
+        let mut buffer = b"+$g#67$m0,2#00\x03".to_vec();
+        assert_eq!(next_input(&mut buffer), Some(Input::Packet("g".to_owned())));
+        assert_eq!(next_input(&mut buffer), Some(Input::Invalid));
+        assert_eq!(next_input(&mut buffer), Some(Input::Interrupt));
+        assert_eq!(next_input(&mut buffer), None);
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut d = Device::new();
+        d.cpu.a = Byte(0x12);
+        d.cpu.pc = Address(0x0150);
+        assert_eq!(
+            d.handle_gdb_packet("g").unwrap(),
+            "1200000000000000feff5001"
+        );
+        d.handle_gdb_packet("P9=0002").unwrap();
+        assert_eq!(d.cpu.pc, Address(0x0200));
+        assert_eq!(d.handle_gdb_packet("p8").unwrap(), "feff");
+    }
+
+    #[test]
+    fn test_memory_and_breakpoints() {
+        let mut d = Device::new();
+        assert_eq!(d.handle_gdb_packet("MC000,2:abcd").unwrap(), "OK");
+        assert_eq!(d.handle_gdb_packet("mC000,2").unwrap(), "abcd");
+        assert_eq!(d.handle_gdb_packet("mE000,1").unwrap(), "ff");
+        assert_eq!(d.handle_gdb_packet("mFFFF,ffffffff").unwrap().len(), 2);
+        assert_eq!(d.handle_gdb_packet("mC000,10000").unwrap().len(), 0x4000);
+        assert_eq!(d.handle_gdb_packet("Z0,150,1").unwrap(), "OK");
+        assert!(d.breakpoints.contains(&Address(0x150)));
+        assert_eq!(d.handle_gdb_packet("z0,150,1").unwrap(), "OK");
+        assert!(d.breakpoints.is_empty());
+    }
+
+    #[test]
+    fn test_connection() {
+        use std::io::{Read, Write};
+
+        let mut d = Device::new();
+        d.listen_gdb("127.0.0.1:0").unwrap();
+        let addr = d.gdb.as_ref().unwrap().listener.local_addr().unwrap();
+        let mut client = std::net::TcpStream::connect(addr).unwrap();
+        client.write_all(b"$?#3f").unwrap();
+
+        let mut reply = vec![];
+        while !reply.ends_with(b"#b8") {
+            d.poll_gdb();
+            let mut buf = [0; 64];
+            client.set_nonblocking(true).unwrap();
+            if let Ok(n) = client.read(&mut buf) {
+                reply.extend_from_slice(&buf[..n]);
+            }
+        }
+        assert_eq!(reply, b"+$S05#b8");
+        assert!(d.is_gdb_connected());
+    }
+}
@@ -1,4 +1,9 @@
-use crate::types::Byte;
+use std::io;
+
+use crate::{
+    state::{Snapshot, StateReader, StateWriter},
+    types::Byte,
+};
 
 #[derive(Debug)]
 pub struct Infrared {
@@ -33,3 +38,18 @@ impl Infrared {
         self.read_enabled = value.is_bit_set(7);
     }
 }
+
+impl Snapshot for Infrared {
+    fn save(&self, w: &mut StateWriter) {
+        w.bool(self.read_enabled);
+        w.bool(self.reading);
+        w.bool(self.led_active);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        self.read_enabled = r.bool()?;
+        self.reading = r.bool()?;
+        self.led_active = r.bool()?;
+        Ok(())
+    }
+}
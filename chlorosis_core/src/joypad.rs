@@ -1,4 +1,10 @@
-use crate::{types::Byte, KeyCode};
+use std::io;
+
+use crate::{
+    state::{Snapshot, StateReader, StateWriter},
+    types::Byte,
+    KeyCode,
+};
 
 #[derive(Debug, Default)]
 pub struct Joypad {
@@ -15,7 +21,7 @@ pub struct Joypad {
 }
 
 impl Joypad {
-    pub fn press(&mut self, key: KeyCode) {
+    pub const fn press(&mut self, key: KeyCode) {
         match key {
             KeyCode::Up => self.up = true,
             KeyCode::Down => self.down = true,
@@ -28,7 +34,7 @@ impl Joypad {
         }
     }
 
-    pub fn release(&mut self, key: KeyCode) {
+    pub const fn release(&mut self, key: KeyCode) {
         match key {
             KeyCode::Up => self.up = false,
             KeyCode::Down => self.down = false,
@@ -41,6 +47,51 @@ impl Joypad {
         }
     }
 
+    // One bit per button, A B Select Start Right Left Up Down from bit 0
+    pub const fn buttons(&self) -> u8 {
+        let buttons = [
+            self.a,
+            self.b,
+            self.select,
+            self.start,
+            self.right,
+            self.left,
+            self.up,
+            self.down,
+        ];
+        let mut mask = 0;
+        let mut i = 0;
+        while i < buttons.len() {
+            mask |= (buttons[i] as u8) << i;
+            i += 1;
+        }
+        mask
+    }
+
+    pub const fn mask(key: KeyCode) -> u8 {
+        match key {
+            KeyCode::A => 0x01,
+            KeyCode::B => 0x02,
+            KeyCode::Select => 0x04,
+            KeyCode::Start => 0x08,
+            KeyCode::Right => 0x10,
+            KeyCode::Left => 0x20,
+            KeyCode::Up => 0x40,
+            KeyCode::Down => 0x80,
+        }
+    }
+
+    pub const fn set_buttons(&mut self, mask: u8) {
+        self.a = mask & 0x01 != 0;
+        self.b = mask & 0x02 != 0;
+        self.select = mask & 0x04 != 0;
+        self.start = mask & 0x08 != 0;
+        self.right = mask & 0x10 != 0;
+        self.left = mask & 0x20 != 0;
+        self.up = mask & 0x40 != 0;
+        self.down = mask & 0x80 != 0;
+    }
+
     pub fn read(&self) -> Byte {
         let mut out = Byte(0);
         match (self.actions, self.directions) {
@@ -61,8 +112,45 @@ impl Joypad {
         out
     }
 
-    pub fn write(&mut self, value: Byte) {
+    pub const fn write(&mut self, value: Byte) {
         self.directions = value.is_bit_set(4);
         self.actions = value.is_bit_set(5);
     }
 }
+
+impl Snapshot for Joypad {
+    fn save(&self, w: &mut StateWriter) {
+        for b in [
+            self.a,
+            self.b,
+            self.start,
+            self.select,
+            self.up,
+            self.down,
+            self.left,
+            self.right,
+            self.actions,
+            self.directions,
+        ] {
+            w.bool(b);
+        }
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        for b in [
+            &mut self.a,
+            &mut self.b,
+            &mut self.start,
+            &mut self.select,
+            &mut self.up,
+            &mut self.down,
+            &mut self.left,
+            &mut self.right,
+            &mut self.actions,
+            &mut self.directions,
+        ] {
+            *b = r.bool()?;
+        }
+        Ok(())
+    }
+}
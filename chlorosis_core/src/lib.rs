@@ -1,22 +1,50 @@
 #![deny(clippy::all)]
 #![deny(clippy::nursery)]
 
+mod archive;
 mod audio;
+mod cheats;
 mod cpu;
+mod dap;
 pub mod device;
+mod error;
 mod frontend;
+mod gdb;
 mod infrared;
 mod joypad;
+mod library;
 mod mbc;
+mod movie;
+mod patch;
 mod ppu;
+mod rewind;
+mod search;
+mod state;
+mod symbols;
 mod timer;
+mod trace;
 mod types;
+pub use archive::extract_rom;
 pub use audio::AudioProcessor;
-pub use cpu::CentralProcessor;
-pub use device::Device;
-pub use frontend::{Event, Frontend, KeyCode};
+pub use cheats::{Cheat, CheatKind, CheatList};
+pub use cpu::{CallFrame, CentralProcessor};
+pub use device::{Device, DeviceState};
+pub use error::Error;
+pub use frontend::{Event, Frontend, KeyCode, Speed};
 pub use infrared::Infrared;
 pub use joypad::Joypad;
-pub use ppu::PixelProcessor;
+pub use library::{Dat, DatEntry, Library, LibraryEntry, CACHE_NAME as LIBRARY_CACHE_NAME};
+pub use movie::{Movie, MovieStart};
+pub use ppu::{
+    MapTile, ObjectAttribute, ObjectSize, Palette, Pixel, PixelProcessor, Tile, TileAttributes,
+    OBJECTS_PER_LINE, OBJECT_COUNT,
+};
+pub use search::{SearchFilter, SearchResult, SearchSize};
+pub use symbols::SymbolTable;
 pub use timer::Timer;
+pub use trace::{TraceFormat, TraceOptions};
 pub(crate) use types::{constants, Address, Byte, SignedByte};
+pub use types::{
+    ram_size_code, rom_size_code, CartrigeHeader, ColorMode, Destination, HeaderError, HeaderFix,
+    MemoryBankControllerType, RomSizeCheck, SgbSupport,
+};
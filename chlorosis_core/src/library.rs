@@ -0,0 +1,438 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use serde_json::{json, Value};
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::{extract_rom, CartrigeHeader, Error};
+
+// A ROM library is every ROM under a directory, identified by hash against a No-Intro style DAT
+// the user supplies. Hashing is the slow part, so the index is cached as JSON and files whose
+// size and modification time haven't changed are not read again
+
+pub const CACHE_NAME: &str = ".chlorosis-library.json";
+const CACHE_VERSION: u64 = 1;
+const EXTENSIONS: [&str; 5] = ["gb", "gbc", "sgb", "zip", "gz"];
+const REGIONS: [&str; 20] = [
+    "World",
+    "USA",
+    "Europe",
+    "Japan",
+    "Asia",
+    "Australia",
+    "Brazil",
+    "Canada",
+    "China",
+    "France",
+    "Germany",
+    "Hong Kong",
+    "Italy",
+    "Korea",
+    "Netherlands",
+    "Spain",
+    "Sweden",
+    "Taiwan",
+    "UK",
+    "Unknown",
+];
+
+const fn invalid(message: String) -> Error {
+    Error::InvalidState(message)
+}
+
+// One game from the DAT, names follow the No-Intro convention, e.g. "Tetris (World) (Rev 1)"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatEntry {
+    pub name: String,
+    pub region: Option<String>,
+    pub revision: Option<String>,
+    pub size: u64,
+    pub crc32: u32,
+    pub sha1: Option<String>, // Lowercase hex
+}
+
+impl DatEntry {
+    fn new(name: String, size: u64, crc32: u32, sha1: Option<String>) -> Self {
+        let tags: Vec<&str> = name
+            .split('(')
+            .skip(1)
+            .filter_map(|t| t.split_once(')').map(|(t, _)| t))
+            .collect();
+        let region = tags
+            .iter()
+            .find(|t| t.split(", ").all(|r| REGIONS.contains(&r)))
+            .map(|t| t.to_string());
+        let revision = tags
+            .iter()
+            .find_map(|t| t.strip_prefix("Rev "))
+            .map(str::to_owned);
+        Self {
+            name,
+            region,
+            revision,
+            size,
+            crc32,
+            sha1: sha1.map(|s| s.to_ascii_lowercase()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Dat {
+    entries: Vec<DatEntry>,
+    by_sha1: HashMap<String, usize>,
+    by_crc32: HashMap<u32, usize>,
+}
+
+impl Dat {
+    // Logiqx XML, each <game name=".."> holds a <rom size=".." crc=".." sha1=".."/>
+    pub fn parse(reader: impl Read) -> Result<Self, Error> {
+        let mut dat = Self::default();
+        let mut game = None;
+        for event in EventReader::new(reader) {
+            let event = event.map_err(|e| invalid(format!("Invalid DAT: {e}")))?;
+            let XmlEvent::StartElement {
+                name, attributes, ..
+            } = event
+            else {
+                continue;
+            };
+            let attribute = |key: &str| {
+                attributes
+                    .iter()
+                    .find(|a| a.name.local_name == key)
+                    .map(|a| a.value.clone())
+            };
+            match name.local_name.as_str() {
+                "game" | "machine" => game = attribute("name"),
+                "rom" => {
+                    let (Some(name), Some(crc)) = (&game, attribute("crc")) else {
+                        continue;
+                    };
+                    let crc32 = u32::from_str_radix(&crc, 16)
+                        .map_err(|_| invalid(format!("Invalid CRC {crc} for {name}")))?;
+                    let size = attribute("size").and_then(|s| s.parse().ok()).unwrap_or(0);
+                    dat.push(DatEntry::new(name.clone(), size, crc32, attribute("sha1")));
+                }
+                _ => {}
+            }
+        }
+        Ok(dat)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::parse(BufReader::new(File::open(path)?))
+    }
+
+    fn push(&mut self, entry: DatEntry) {
+        let i = self.entries.len();
+        if let Some(sha1) = &entry.sha1 {
+            self.by_sha1.entry(sha1.clone()).or_insert(i);
+        }
+        self.by_crc32.entry(entry.crc32).or_insert(i);
+        self.entries.push(entry);
+    }
+
+    // SHA-1 when the DAT has it, the CRC alone is only trusted when it doesn't
+    pub fn identify(&self, crc32: u32, sha1: &str) -> Option<&DatEntry> {
+        self.by_sha1
+            .get(sha1)
+            .or_else(|| {
+                self.by_crc32
+                    .get(&crc32)
+                    .filter(|&&i| self.entries[i].sha1.is_none())
+            })
+            .map(|&i| &self.entries[i])
+    }
+
+    pub fn entries(&self) -> &[DatEntry] {
+        &self.entries
+    }
+
+    pub const fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibraryEntry {
+    pub path: PathBuf,
+    pub size: u64,     // Of the file, which may be an archive
+    pub modified: u64, // Seconds since the epoch
+    pub crc32: u32,    // Of the ROM itself
+    pub sha1: String,
+    pub title: Option<String>, // From the header, none when it doesn't parse
+    pub game: Option<DatEntry>,
+}
+
+impl LibraryEntry {
+    fn read(path: PathBuf, size: u64, modified: u64) -> Result<Self, Error> {
+        let (rom, _) = extract_rom(std::fs::read(&path)?)?;
+        let title = CartrigeHeader::parse(&rom)
+            .ok()
+            .map(|h| h.title().to_owned());
+        Ok(Self {
+            path,
+            size,
+            modified,
+            crc32: crc32fast::hash(&rom),
+            sha1: sha1_smol::Sha1::from(&rom).digest().to_string(),
+            title,
+            game: None,
+        })
+    }
+
+    // The canonical name if known, then the header title, then the file name
+    pub fn name(&self) -> String {
+        self.game
+            .as_ref()
+            .map(|g| g.name.clone())
+            .or_else(|| self.title.clone().filter(|t| !t.is_empty()))
+            .unwrap_or_else(|| {
+                self.path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "path": self.path,
+            "size": self.size,
+            "modified": self.modified,
+            "crc32": format!("{:08x}", self.crc32),
+            "sha1": self.sha1,
+            "title": self.title,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            path: value["path"].as_str()?.into(),
+            size: value["size"].as_u64()?,
+            modified: value["modified"].as_u64()?,
+            crc32: u32::from_str_radix(value["crc32"].as_str()?, 16).ok()?,
+            sha1: value["sha1"].as_str()?.to_owned(),
+            title: value["title"].as_str().map(str::to_owned),
+            game: None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Library {
+    entries: Vec<LibraryEntry>,
+    skipped: Vec<(PathBuf, String)>, // Files that couldn't be read, and why
+    cache_error: Option<String>,     // Why the cache was ignored, if it was
+}
+
+impl Library {
+    // Finds every ROM under the directory, the cache is read first if there is one and then
+    // rewritten. Unreadable files and a bad cache don't fail the scan, they're left for the
+    // caller to report
+    pub fn scan(
+        dir: impl AsRef<Path>,
+        dat: Option<&Dat>,
+        cache: Option<&Path>,
+    ) -> Result<Self, Error> {
+        let mut cache_error = None;
+        let cached = match cache.filter(|c| c.exists()).map(Self::load_cache) {
+            Some(Ok(library)) => library.entries,
+            Some(Err(e)) => {
+                cache_error = Some(e.to_string());
+                vec![]
+            }
+            None => vec![],
+        };
+        let mut cached: HashMap<PathBuf, LibraryEntry> =
+            cached.into_iter().map(|e| (e.path.clone(), e)).collect();
+
+        let mut paths = vec![];
+        find_roms(dir.as_ref(), &mut paths)?;
+
+        let mut entries = vec![];
+        let mut skipped = vec![];
+        for path in paths {
+            let metadata = std::fs::metadata(&path)?;
+            let size = metadata.len();
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs());
+            let entry = match cached.remove(&path) {
+                Some(e) if e.size == size && e.modified == modified => e,
+                _ => match LibraryEntry::read(path.clone(), size, modified) {
+                    Ok(e) => e,
+                    Err(e) => {
+                        skipped.push((path, e.to_string()));
+                        continue;
+                    }
+                },
+            };
+            entries.push(entry);
+        }
+
+        let mut library = Self {
+            entries,
+            skipped,
+            cache_error,
+        };
+        if let Some(dat) = dat {
+            library.identify(dat);
+        }
+        library
+            .entries
+            .sort_by_cached_key(|e| e.name().to_lowercase());
+        if let Some(cache) = cache {
+            library.save_cache(cache)?;
+        }
+        Ok(library)
+    }
+
+    pub fn identify(&mut self, dat: &Dat) {
+        for entry in &mut self.entries {
+            entry.game = dat.identify(entry.crc32, &entry.sha1).cloned();
+        }
+    }
+
+    // DAT matches aren't cached, the DAT may have changed since
+    pub fn load_cache(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let value: Value = serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|e| invalid(format!("Invalid library cache: {e}")))?;
+        if value["version"].as_u64() != Some(CACHE_VERSION) {
+            return Err(invalid("Unsupported library cache version".to_owned()));
+        }
+        let entries = value["entries"]
+            .as_array()
+            .ok_or_else(|| invalid("Library cache has no entries".to_owned()))?
+            .iter()
+            .filter_map(LibraryEntry::from_json)
+            .collect();
+        Ok(Self {
+            entries,
+            ..Self::default()
+        })
+    }
+
+    pub fn save_cache(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let value = json!({
+            "version": CACHE_VERSION,
+            "entries": self.entries.iter().map(LibraryEntry::to_json).collect::<Vec<_>>(),
+        });
+        std::fs::write(path, value.to_string())?;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[LibraryEntry] {
+        &self.entries
+    }
+
+    pub fn skipped(&self) -> &[(PathBuf, String)] {
+        &self.skipped
+    }
+
+    pub fn cache_error(&self) -> Option<&str> {
+        self.cache_error.as_deref()
+    }
+}
+
+fn find_roms(dir: &Path, out: &mut Vec<PathBuf>) -> Result<(), io::Error> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|e| e.path());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            find_roms(&path, out)?;
+        } else if path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| EXTENSIONS.iter().any(|r| e.eq_ignore_ascii_case(r)))
+        {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Dat, Library, CACHE_NAME};
+
+    const DAT: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE datafile PUBLIC "-//Logiqx//DTD ROM Management Datafile//EN" "http://www.logiqx.com/dtds/datafile.dtd">
+<datafile>
+    <header><name>Nintendo - Game Boy</name></header>
+    <game name="Demo &amp; Friends (USA, Europe) (Rev 2)">
+        <description>Demo &amp; Friends (USA, Europe) (Rev 2)</description>
+        <rom name="Demo &amp; Friends (USA, Europe) (Rev 2).gb" size="4" crc="B63CFBCD" sha1="12dada1fff4d4787ade3333147202c3b443e376f"/>
+    </game>
+    <game name="Other (Japan)">
+        <rom name="Other (Japan).gb" size="4" crc="8BB98613"/>
+    </game>
+</datafile>"#;
+
+    #[test]
+    fn test_dat() {
+        let dat = Dat::parse(DAT.as_bytes()).unwrap();
+        assert_eq!(dat.len(), 2);
+        let game = dat
+            .identify(0xB63CFBCD, "12dada1fff4d4787ade3333147202c3b443e376f")
+            .unwrap();
+        assert_eq!(game.name, "Demo & Friends (USA, Europe) (Rev 2)");
+        assert_eq!(game.region.as_deref(), Some("USA, Europe"));
+        assert_eq!(game.revision.as_deref(), Some("2"));
+
+        // A CRC collision doesn't count when the DAT has a SHA-1 to check
+        assert_eq!(dat.identify(0xB63CFBCD, "00"), None);
+        let other = dat.identify(0x8BB98613, "00").unwrap();
+        assert_eq!(other.region.as_deref(), Some("Japan"));
+        assert_eq!(other.revision, None);
+    }
+
+    #[test]
+    fn test_scan() {
+        let dir = std::env::temp_dir().join("chlorosis_library_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub/demo.gb"), [1, 2, 3, 4]).unwrap();
+        std::fs::write(dir.join("notes.txt"), "Not a ROM").unwrap();
+        std::fs::write(dir.join("broken.zip"), b"PK\x03\x04").unwrap();
+
+        // Problems are handed back rather than failing the scan
+        let dat = Dat::parse(DAT.as_bytes()).unwrap();
+        let cache = dir.join(CACHE_NAME);
+        std::fs::write(&cache, "{").unwrap();
+        let library = Library::scan(&dir, Some(&dat), Some(&cache)).unwrap();
+        assert!(library.cache_error().is_some());
+        assert_eq!(library.skipped().len(), 1);
+        assert_eq!(library.skipped()[0].0, dir.join("broken.zip"));
+        assert_eq!(library.entries().len(), 1);
+        let entry = &library.entries()[0];
+        assert_eq!(entry.crc32, 0xB63CFBCD);
+        assert_eq!(entry.sha1, "12dada1fff4d4787ade3333147202c3b443e376f");
+        assert_eq!(entry.title, None);
+        assert_eq!(entry.name(), "Demo & Friends (USA, Europe) (Rev 2)");
+
+        // The cache is used once written, the DAT match is made again
+        let cached = Library::load_cache(&cache).unwrap();
+        assert_eq!(cached.entries()[0].sha1, entry.sha1);
+        assert_eq!(cached.entries()[0].game, None);
+        let rescanned = Library::scan(&dir, Some(&dat), Some(&cache)).unwrap();
+        assert_eq!(rescanned.entries(), library.entries());
+        assert_eq!(rescanned.cache_error(), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,123 +1,185 @@
-use super::Memory;
-use crate::types::{Address, Byte};
+use std::{io, path::Path};
 
+use super::{
+    load_ram, ram_offset, rom_byte, save_ram, wrap_bank, Memory, PersistentMemory, RAM_BANK_SIZE,
+};
+use crate::{
+    state::{Snapshot, StateReader, StateWriter},
+    types::{Address, Byte, NINTENDO_LOGO},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum BankingMode {
     RAMBank,
     ROMBank,
 }
 
+#[derive(Debug)]
 pub struct MBC1 {
     rom_data: Vec<u8>,
     rom_bank: usize,
-    lower_rom_register: usize,
-    upper_rom_register: usize,
     ram_bank: usize,
     ram_enabled: bool,
     mode: BankingMode,
     ram_data: Vec<u8>,
+    // MBC1M compilation carts only wire 4 bits of the ROM bank register
+    multicart: bool,
 }
 
-const BANK_MAX: usize = 125;
+// Each game of a multicart is 256 KB with its own header
+const MULTICART_GAME_SIZE: usize = 0x40000;
+const MULTICART_ROM_SIZE: usize = 0x100000;
 
-impl Memory for MBC1 {
-    fn from_bytes(bytes: Vec<u8>) -> Self {
+impl MBC1 {
+    pub fn new(bytes: Vec<u8>, ram_size: usize) -> Self {
         Self {
+            multicart: Self::is_multicart(&bytes),
             rom_data: bytes,
-            ram_data: vec![0; 0x8000],
+            ram_data: vec![0; ram_size],
             rom_bank: 1,
             ram_bank: 0,
-            upper_rom_register: 0,
-            lower_rom_register: 1,
             ram_enabled: false,
             mode: BankingMode::ROMBank,
         }
     }
 
-    fn read(&self, addr: Address) -> Byte {
-        match addr.0 {
-            0x0000..=0x3FFF => {
-                let offset = match self.mode {
-                    BankingMode::ROMBank => addr.0 as usize,
-                    BankingMode::RAMBank => addr.0 as usize + (self.upper_rom_register << 19),
-                };
-                Byte(self.rom_data[offset])
-            }
+    // 1 MB carts with the logo repeated in at least one more game are multicarts
+    fn is_multicart(rom: &[u8]) -> bool {
+        rom.len() == MULTICART_ROM_SIZE
+            && (1..4).any(|game| {
+                let logo = game * MULTICART_GAME_SIZE + 0x104;
+                rom[logo..logo + NINTENDO_LOGO.len()] == NINTENDO_LOGO
+            })
+    }
 
-            // TODO: mask bits for smaller sized carts
-            0x4000..=0x7FFF => {
-                let offset = addr.0 as usize
-                    + (self.lower_rom_register << 14)
-                    + (self.upper_rom_register << 19);
-                assert!(offset < self.rom_data.len());
-                Byte(self.rom_data[offset])
-            }
+    const fn bank_shift(&self) -> usize {
+        if self.multicart {
+            4
+        } else {
+            5
+        }
+    }
 
-            0xA000..=0xBFFF => {
-                if self.ram_enabled {
-                    let offset = match self.mode {
-                        BankingMode::ROMBank => addr.0 as usize,
-                        BankingMode::RAMBank => addr.0 as usize + (self.upper_rom_register << 13),
-                    };
-                    Byte(self.ram_data[offset])
-                } else {
-                    Byte(0xFF)
-                }
-            }
+    // Mode 1 maps the upper bank bits into 0x0000-0x3FFF as well
+    const fn lower_bank(&self) -> usize {
+        match self.mode {
+            BankingMode::RAMBank => self.ram_bank << self.bank_shift(),
+            BankingMode::ROMBank => 0,
+        }
+    }
+
+    const fn upper_bank(&self) -> usize {
+        let mask = (1 << self.bank_shift()) - 1;
+        (self.ram_bank << self.bank_shift()) | (self.rom_bank & mask)
+    }
+
+    fn ram_offset(&self, addr: Address) -> Option<usize> {
+        ram_offset(&self.ram_data, self.ram_bank(), addr)
+    }
+}
 
-            _ => unreachable!(),
+impl Memory for MBC1 {
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self::new(bytes, RAM_BANK_SIZE * 4)
+    }
+
+    fn read(&self, addr: Address) -> Byte {
+        match addr.0 {
+            0x0000..=0x3FFF => rom_byte(&self.rom_data, self.lower_bank(), addr),
+            0x4000..=0x7FFF => rom_byte(&self.rom_data, self.upper_bank(), addr),
+            0xA000..=0xBFFF if self.ram_enabled => self
+                .ram_offset(addr)
+                .map_or(Byte(0xFF), |offset| Byte(self.ram_data[offset])),
+            _ => Byte(0xFF),
         }
     }
 
     fn write(&mut self, addr: Address, val: Byte) {
         match addr.0 {
             0x0000..=0x1FFF => self.ram_enabled = (val.0 & 0x0F) == 0x0A,
-            0x2000..=0x3FFF => {
-                if val == Byte::ZERO {
-                    self.rom_bank = 1;
+            // Zero is checked on all 5 bits, even on multicarts where only 4 reach the ROM
+            0x2000..=0x3FFF => self.rom_bank = (val.0 as usize & 0b0001_1111).max(1),
+            0x4000..=0x5FFF => self.ram_bank = val.0 as usize & 0b0000_0011,
+            0x6000..=0x7FFF => {
+                self.mode = if val.0 & 1 == 1 {
+                    BankingMode::RAMBank
                 } else {
-                    self.rom_bank = (0b0001_1111 & val.0) as usize;
-                    assert!(self.rom_bank <= BANK_MAX);
-                }
-            }
-            0x4000..=0x5FFF => {
-                let val = val.0 as usize & 0b0000_0011;
-                match self.mode {
-                    BankingMode::RAMBank => self.ram_bank = val,
-                    BankingMode::ROMBank => {
-                        self.rom_bank = (self.rom_bank & 0b0001_1111) + (val << 5);
-                        assert!(self.rom_bank <= BANK_MAX);
-                    }
-                }
-            }
-            0x6000..=0x7FFF => match val.0 {
-                0x00 => self.mode = BankingMode::ROMBank,
-                0x01 => self.mode = BankingMode::RAMBank,
-                _ => {}
-            }, // TODO - RAM Bank 00h can be used during Mode 0, and only ROM Banks 00-1Fh can be used during Mode 1
-
-            0xA000..=0xBFFF => {
-                if self.ram_enabled {
-                    let offset = match self.mode {
-                        BankingMode::ROMBank => addr.0 as usize,
-                        BankingMode::RAMBank => addr.0 as usize + (self.upper_rom_register << 13),
-                    };
-                    self.ram_data[offset] = val.0;
+                    BankingMode::ROMBank
                 }
             }
+            0xA000..=0xBFFF if self.ram_enabled => self.poke_ram(addr, val),
+            _ => {}
+        }
+    }
 
-            _ => unreachable!(),
+    fn poke_ram(&mut self, addr: Address, val: Byte) {
+        if let Some(offset) = self.ram_offset(addr) {
+            self.ram_data[offset] = val.0;
         }
     }
+
+    fn ram_bank(&self) -> usize {
+        match self.mode {
+            BankingMode::RAMBank => self.ram_bank,
+            BankingMode::ROMBank => 0,
+        }
+    }
+
+    fn rom_bank(&self) -> usize {
+        wrap_bank(&self.rom_data, self.upper_bank())
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram_data
+    }
+
+    fn persistent(&mut self) -> Option<&mut dyn PersistentMemory> {
+        Some(self)
+    }
+}
+
+impl PersistentMemory for MBC1 {
+    fn save(&self, file: &Path) -> Result<(), io::Error> {
+        save_ram(&self.ram_data, file)
+    }
+
+    fn load(&mut self, file: &Path) -> Result<(), io::Error> {
+        load_ram(&mut self.ram_data, file)
+    }
+}
+
+impl Snapshot for MBC1 {
+    fn save(&self, w: &mut StateWriter) {
+        w.u8(self.rom_bank as u8);
+        w.u8(self.ram_bank as u8);
+        w.bool(self.ram_enabled);
+        w.bool(self.mode == BankingMode::RAMBank);
+        w.raw(&self.ram_data);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        self.rom_bank = r.u8()? as usize;
+        self.ram_bank = r.u8()? as usize;
+        self.ram_enabled = r.bool()?;
+        self.mode = if r.bool()? {
+            BankingMode::RAMBank
+        } else {
+            BankingMode::ROMBank
+        };
+        let len = self.ram_data.len();
+        self.ram_data.copy_from_slice(r.raw(len)?);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod test {
     use crate::{
-        mbc::Memory,
-        types::{Address, Byte},
+        mbc::{tests::numbered_rom, Memory, PersistentMemory, ROM_BANK_SIZE},
+        types::{Address, Byte, NINTENDO_LOGO},
     };
 
-    use super::MBC1;
+    use super::{MBC1, MULTICART_GAME_SIZE, MULTICART_ROM_SIZE};
 
     #[test]
     fn test_ram_enable() {
@@ -136,4 +198,90 @@ mod test {
         mbc.write(Address(0x2000), Byte(0xE1));
         assert_eq!(mbc.rom_bank, 1);
     }
+
+    #[test]
+    fn test_banking() {
+        let mut mbc = MBC1::from_bytes(numbered_rom(MULTICART_ROM_SIZE));
+        assert!(!mbc.multicart);
+        mbc.write(Address(0x2000), Byte(0x00));
+        assert_eq!(mbc.read(Address(0x4000)), Byte(1));
+        mbc.write(Address(0x2000), Byte(0x12));
+        mbc.write(Address(0x4000), Byte(0x01));
+        assert_eq!(mbc.read(Address(0x4000)), Byte(0x32));
+        assert_eq!(mbc.read(Address(0x0000)), Byte(0x00));
+
+        mbc.write(Address(0x6000), Byte(0x01));
+        assert_eq!(mbc.read(Address(0x0000)), Byte(0x20));
+    }
+
+    #[test]
+    fn test_ram_size() {
+        // 2 KB repeats across the window
+        let mut mbc = MBC1::new(numbered_rom(ROM_BANK_SIZE * 4), 0x800);
+        mbc.write(Address(0x0000), Byte(0x0A));
+        mbc.write(Address(0xA001), Byte(0x42));
+        assert_eq!(mbc.read(Address(0xA801)), Byte(0x42));
+        assert_eq!(mbc.read(Address(0xB801)), Byte(0x42));
+        mbc.write(Address(0x0000), Byte(0x00));
+        assert_eq!(mbc.read(Address(0xA001)), Byte(0xFF));
+
+        // Bank 5 of a 4 bank ROM is bank 1
+        mbc.write(Address(0x2000), Byte(0x05));
+        assert_eq!(mbc.read(Address(0x4000)), Byte(1));
+        assert_eq!(mbc.rom_bank(), 1);
+
+        let mut mbc = MBC1::new(vec![], 0);
+        mbc.write(Address(0x0000), Byte(0x0A));
+        mbc.write(Address(0xA000), Byte(0x42));
+        assert_eq!(mbc.read(Address(0xA000)), Byte(0xFF));
+    }
+
+    #[test]
+    fn test_battery() {
+        let mut mbc = MBC1::new(vec![], 0x8000);
+        mbc.write(Address(0x0000), Byte(0x0A));
+        mbc.write(Address(0x6000), Byte(0x01));
+        mbc.write(Address(0x4000), Byte(0x03));
+        mbc.write(Address(0xA123), Byte(0x42));
+
+        let path = std::env::temp_dir().join("chlorosis_mbc1.sav");
+        PersistentMemory::save(&mbc, &path).unwrap();
+        let mut restored = MBC1::new(vec![], 0x8000);
+        PersistentMemory::load(&mut restored, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(restored.ram_data[0x6123], 0x42);
+
+        // A save for a different amount of RAM is refused
+        let mut small = MBC1::new(vec![], 0x2000);
+        PersistentMemory::save(&mbc, &path).unwrap();
+        assert!(PersistentMemory::load(&mut small, &path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_multicart() {
+        let mut rom = numbered_rom(MULTICART_ROM_SIZE);
+        for game in 0..4 {
+            let logo = game * MULTICART_GAME_SIZE + 0x104;
+            rom[logo..logo + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+        }
+        let mut mbc = MBC1::from_bytes(rom);
+        assert!(mbc.multicart);
+
+        // Bit 4 of the bank number doesn't reach the ROM
+        mbc.write(Address(0x2000), Byte(0x13));
+        mbc.write(Address(0x4000), Byte(0x02));
+        assert_eq!(mbc.read(Address(0x4000)), Byte(0x23));
+        assert_eq!(mbc.rom_bank(), 0x23);
+
+        // Mode 1 boots the selected game from 0x0000
+        assert_eq!(mbc.read(Address(0x0000)), Byte(0x00));
+        mbc.write(Address(0x6000), Byte(0x01));
+        assert_eq!(mbc.read(Address(0x0000)), Byte(0x20));
+        assert_eq!(mbc.read(Address(0x0104)), Byte(NINTENDO_LOGO[0]));
+
+        // 0x10 is not zero, so it selects the game's bank 0 rather than bank 1
+        mbc.write(Address(0x2000), Byte(0x10));
+        assert_eq!(mbc.read(Address(0x4000)), Byte(0x20));
+    }
 }
@@ -1,14 +1,21 @@
-use super::Memory;
-use crate::types::{Address, Byte};
+use std::{io, path::Path};
 
+use super::{rom_byte, wrap_bank, Memory, PersistentMemory};
+use crate::{
+    state::{Snapshot, StateReader, StateWriter},
+    types::{Address, Byte},
+};
+
+// MBC2 has 512 half-bytes of RAM built in, the upper nibble isn't wired and reads as 1s
+#[derive(Debug)]
 pub struct MBC2 {
     data: Vec<u8>,
     rom_bank: usize,
     ram_enabled: bool,
+    ram_data: Vec<u8>,
 }
 
-const BANK_MAX: usize = 16;
-const BANK_SIZE: usize = 0x4000;
+const RAM_SIZE: usize = 0x200;
 
 impl Memory for MBC2 {
     fn from_bytes(bytes: Vec<u8>) -> Self {
@@ -16,53 +23,134 @@ impl Memory for MBC2 {
             data: bytes,
             rom_bank: 1,
             ram_enabled: false,
+            ram_data: vec![0; RAM_SIZE],
         }
     }
 
     fn read(&self, addr: Address) -> Byte {
         match addr.0 {
-            0x0000..=0x3FFF => Byte(self.data[addr.0 as usize]),
-            0x4000..=0x7FFF => Byte(self.data[addr.0 as usize + BANK_SIZE * self.rom_bank]),
-
-            0xA000..=0xBFFF => {
-                if self.ram_enabled {
-                    unimplemented!("READ RAM");
-                } else {
-                    panic!("Attempted to read RAM but NOT ENABLED");
-                }
+            0x0000..=0x3FFF => rom_byte(&self.data, 0, addr),
+            0x4000..=0x7FFF => rom_byte(&self.data, self.rom_bank, addr),
+            // Only the low 9 address bits are decoded, so the RAM repeats across 0xA000-0xBFFF
+            0xA000..=0xBFFF if self.ram_enabled => {
+                Byte(0xF0 | self.ram_data[addr.0 as usize & (RAM_SIZE - 1)])
             }
-
-            _ => unreachable!(),
+            _ => Byte(0xFF),
         }
     }
 
     fn write(&mut self, addr: Address, val: Byte) {
         match addr.0 {
-            0x0000..=0x1FFF => {
-                let (a, _) = addr.split();
-                if a.is_bit_set(0) {
-                } else {
-                    self.ram_enabled = (val.0 & 0x0F) == 0x0A;
-                }
-            }
-            0x2000..=0x3FFF => {
-                let (a, _) = addr.split();
-                if !a.is_bit_set(0) {
-                } else {
-                    self.rom_bank = 0b0000_1111 & val.0 as usize;
-                    assert!(self.rom_bank <= BANK_MAX);
-                }
+            // Address bit 8 picks the register, set for the ROM bank and clear for RAM enable
+            0x0000..=0x3FFF if addr.0 & 0x0100 != 0 => {
+                self.rom_bank = (val.0 as usize & 0b0000_1111).max(1)
             }
+            0x0000..=0x3FFF => self.ram_enabled = (val.0 & 0x0F) == 0x0A,
+            0xA000..=0xBFFF if self.ram_enabled => self.poke_ram(addr, val),
+            _ => {}
+        }
+    }
 
-            0xA000..=0xBFFF => {
-                if self.ram_enabled {
-                    unimplemented!("WRITE RAM");
-                } else {
-                    panic!("Attempted to write RAM but NOT ENABLED");
-                }
-            }
+    fn poke_ram(&mut self, addr: Address, val: Byte) {
+        self.ram_data[addr.0 as usize & (RAM_SIZE - 1)] = val.0 & 0x0F
+    }
+
+    fn rom_bank(&self) -> usize {
+        wrap_bank(&self.data, self.rom_bank)
+    }
 
-            _ => unreachable!(),
+    fn ram(&self) -> &[u8] {
+        &self.ram_data
+    }
+
+    fn persistent(&mut self) -> Option<&mut dyn PersistentMemory> {
+        Some(self)
+    }
+}
+
+// One byte per half-byte, the same layout other emulators use for MBC2 saves
+impl PersistentMemory for MBC2 {
+    fn save(&self, file: &Path) -> Result<(), io::Error> {
+        std::fs::write(file, &self.ram_data)
+    }
+
+    fn load(&mut self, file: &Path) -> Result<(), io::Error> {
+        let data = std::fs::read(file)?;
+        if data.len() != RAM_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("MBC2 saves are {RAM_SIZE} bytes, not {}", data.len()),
+            ));
+        }
+        for (dst, src) in self.ram_data.iter_mut().zip(data) {
+            *dst = src & 0x0F;
         }
+        Ok(())
+    }
+}
+
+impl Snapshot for MBC2 {
+    fn save(&self, w: &mut StateWriter) {
+        w.u8(self.rom_bank as u8);
+        w.bool(self.ram_enabled);
+        w.raw(&self.ram_data);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        self.rom_bank = r.u8()? as usize;
+        self.ram_enabled = r.bool()?;
+        let ram = r.raw(RAM_SIZE)?;
+        self.ram_data.copy_from_slice(ram);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        mbc::{Memory, PersistentMemory, ROM_BANK_SIZE},
+        types::{Address, Byte},
+    };
+
+    use super::MBC2;
+
+    #[test]
+    fn test_registers() {
+        let mut rom = vec![0; ROM_BANK_SIZE * 16];
+        rom[ROM_BANK_SIZE * 5] = 5;
+        let mut mbc = MBC2::from_bytes(rom);
+
+        // Bit 8 clear enables RAM rather than switching banks
+        mbc.write(Address(0x2000), Byte(0x05));
+        assert_eq!(mbc.rom_bank, 1);
+        mbc.write(Address(0x2100), Byte(0xF5));
+        assert_eq!(mbc.read(Address(0x4000)), Byte(5));
+        mbc.write(Address(0x0100), Byte(0x00));
+        assert_eq!(mbc.rom_bank, 1);
+
+        assert_eq!(mbc.read(Address(0xA000)), Byte(0xFF));
+        mbc.write(Address(0x0000), Byte(0x0A));
+        assert!(mbc.ram_enabled);
+    }
+
+    #[test]
+    fn test_ram() {
+        let mut mbc = MBC2::from_bytes(vec![]);
+        mbc.write(Address(0xA000), Byte(0x0C));
+        mbc.write(Address(0x0000), Byte(0x0A));
+        assert_eq!(mbc.read(Address(0xA000)), Byte(0xF0));
+
+        mbc.write(Address(0xA001), Byte(0xAC));
+        assert_eq!(mbc.read(Address(0xA001)), Byte(0xFC));
+        assert_eq!(mbc.read(Address(0xA201)), Byte(0xFC));
+        assert_eq!(mbc.read(Address(0xBE01)), Byte(0xFC));
+
+        let path = std::env::temp_dir().join("chlorosis_mbc2.sav");
+        PersistentMemory::save(&mbc, &path).unwrap();
+        let mut restored = MBC2::from_bytes(vec![]);
+        PersistentMemory::load(&mut restored, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        restored.write(Address(0x0000), Byte(0x0A));
+        assert_eq!(restored.read(Address(0xA001)), Byte(0xFC));
     }
 }
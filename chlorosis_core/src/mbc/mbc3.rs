@@ -1,7 +1,14 @@
-use super::Memory;
-use crate::types::{Address, Byte};
+use std::{io, path::Path};
 
-#[derive(Debug, Copy, Clone)]
+use super::{
+    load_ram, ram_offset, rom_byte, save_ram, wrap_bank, Memory, PersistentMemory, RAM_BANK_SIZE,
+};
+use crate::{
+    state::{Snapshot, StateReader, StateWriter},
+    types::{Address, Byte},
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 enum RTCMode {
     Ram,
     Seconds,
@@ -19,6 +26,7 @@ enum LatchState {
     AttemptingUnlatch,
 }
 
+#[derive(Debug)]
 pub struct MBC3 {
     rom_data: Vec<u8>,
     rom_bank: usize,
@@ -35,25 +43,32 @@ pub struct MBC3 {
     rtc_start_timestamp: std::time::SystemTime,
 }
 
-const ROM_BANK_SIZE: usize = 0x4000;
-const RAM_BANK_SIZE: usize = 0x2000;
-
 impl MBC3 {
-    fn read_ram(&self, addr: Address) -> Byte {
-        let offset: usize = (addr.0 - 0xA000) as usize + RAM_BANK_SIZE * self.ram_bank;
-        Byte(self.ram_data[offset])
+    pub fn new(bytes: Vec<u8>, ram_size: usize) -> Self {
+        Self {
+            rom_data: bytes,
+            rom_bank: 1,
+            ram_data: vec![0; ram_size],
+            ram_bank: 0,
+            ram_enabled: false,
+            rtc_selected: RTCMode::Ram,
+            seconds: 0,
+            minutes: 0,
+            hours: 0,
+            lower_day: 0,
+            upper_day: 0,
+            latch_state: LatchState::Unlatched,
+            rtc_start_timestamp: std::time::SystemTime::now(),
+        }
     }
 
-    fn write_ram(&mut self, addr: Address, val: Byte) {
-        let offset: usize = (addr.0 - 0xA000) as usize + RAM_BANK_SIZE * self.ram_bank;
-        self.ram_data[offset] = val.0;
+    fn read_ram(&self, addr: Address) -> Byte {
+        ram_offset(&self.ram_data, self.ram_bank, addr)
+            .map_or(Byte(0xFF), |offset| Byte(self.ram_data[offset]))
     }
 
     fn latch_time(&mut self) {
-        let elapsed = self
-            .rtc_start_timestamp
-            .elapsed()
-            .expect("Time drift error");
+        let elapsed = self.rtc_start_timestamp.elapsed().unwrap_or_default();
         self.seconds = (elapsed.as_secs() % 60) as u8;
         self.minutes = ((elapsed.as_secs() / 60) % 60) as u8;
         self.hours = ((elapsed.as_secs() / (60 * 60)) % 24) as u8;
@@ -64,43 +79,24 @@ impl MBC3 {
 }
 
 impl Memory for MBC3 {
+    // The largest MBC3 RAM, 4 banks
     fn from_bytes(bytes: Vec<u8>) -> Self {
-        Self {
-            rom_data: bytes,
-            rom_bank: 1,
-            ram_data: vec![0; RAM_BANK_SIZE * 4],
-            ram_bank: 0,
-            ram_enabled: false,
-            rtc_selected: RTCMode::Ram,
-            seconds: 0,
-            minutes: 0,
-            hours: 0,
-            lower_day: 0,
-            upper_day: 0,
-            latch_state: LatchState::Unlatched,
-            rtc_start_timestamp: std::time::SystemTime::now(),
-        }
+        Self::new(bytes, RAM_BANK_SIZE * 4)
     }
 
     fn read(&self, addr: Address) -> Byte {
         match addr.0 {
-            0x0000..=0x3FFF => Byte(self.rom_data[addr.0 as usize]),
-            0x4000..=0x7FFF => Byte(self.rom_data[addr.0 as usize + ROM_BANK_SIZE * self.rom_bank]),
-            0xA000..=0xBFFF => {
-                if self.ram_enabled {
-                    match self.rtc_selected {
-                        RTCMode::Ram => self.read_ram(addr),
-                        RTCMode::Seconds => Byte(self.seconds),
-                        RTCMode::Minutes => Byte(self.minutes),
-                        RTCMode::Hours => Byte(self.hours),
-                        RTCMode::LowerDay => Byte(self.lower_day),
-                        RTCMode::UpperDay => Byte(self.upper_day),
-                    }
-                } else {
-                    panic!("Attempted RAM/RTC read when disabled");
-                }
-            }
-            _ => unreachable!(),
+            0x0000..=0x3FFF => rom_byte(&self.rom_data, 0, addr),
+            0x4000..=0x7FFF => rom_byte(&self.rom_data, self.rom_bank, addr),
+            0xA000..=0xBFFF if self.ram_enabled => match self.rtc_selected {
+                RTCMode::Ram => self.read_ram(addr),
+                RTCMode::Seconds => Byte(self.seconds),
+                RTCMode::Minutes => Byte(self.minutes),
+                RTCMode::Hours => Byte(self.hours),
+                RTCMode::LowerDay => Byte(self.lower_day),
+                RTCMode::UpperDay => Byte(self.upper_day),
+            },
+            _ => Byte(0xFF),
         }
     }
 
@@ -126,7 +122,7 @@ impl Memory for MBC3 {
                     0x0A => self.rtc_selected = Hours,
                     0x0B => self.rtc_selected = LowerDay,
                     0x0C => self.rtc_selected = UpperDay,
-                    _ => unreachable!(),
+                    _ => {}
                 }
             }
             0x6000..=0x7FFF => {
@@ -144,24 +140,102 @@ impl Memory for MBC3 {
                     (_, _) => {}
                 }
             }
-            0xA000..=0xBFFF => {
-                if self.ram_enabled {
-                    if self.latch_state != LatchState::Latched {
-                        self.latch_time();
-                    }
-                    match self.rtc_selected {
-                        RTCMode::Ram => self.write_ram(addr, val),
-                        RTCMode::Seconds => self.seconds = val.0,
-                        RTCMode::Minutes => self.minutes = val.0,
-                        RTCMode::Hours => self.hours = val.0,
-                        RTCMode::LowerDay => self.lower_day = val.0,
-                        RTCMode::UpperDay => self.upper_day = val.0,
-                    }
-                } else {
-                    panic!("Attempted RAM/RTC write when disabled");
+            0xA000..=0xBFFF if self.ram_enabled => {
+                if self.latch_state != LatchState::Latched {
+                    self.latch_time();
+                }
+                match self.rtc_selected {
+                    RTCMode::Ram => self.poke_ram(addr, val),
+                    RTCMode::Seconds => self.seconds = val.0,
+                    RTCMode::Minutes => self.minutes = val.0,
+                    RTCMode::Hours => self.hours = val.0,
+                    RTCMode::LowerDay => self.lower_day = val.0,
+                    RTCMode::UpperDay => self.upper_day = val.0,
                 }
             }
-            _ => unreachable!(),
+            _ => {}
+        }
+    }
+
+    fn poke_ram(&mut self, addr: Address, val: Byte) {
+        if let Some(offset) = ram_offset(&self.ram_data, self.ram_bank, addr) {
+            self.ram_data[offset] = val.0;
         }
     }
+
+    fn ram_bank(&self) -> usize {
+        self.ram_bank
+    }
+
+    fn rom_bank(&self) -> usize {
+        wrap_bank(&self.rom_data, self.rom_bank)
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram_data
+    }
+
+    fn persistent(&mut self) -> Option<&mut dyn PersistentMemory> {
+        Some(self)
+    }
+}
+
+// Only the RAM goes in the save file, the clock restarts from when the cartridge was loaded
+impl PersistentMemory for MBC3 {
+    fn save(&self, file: &Path) -> Result<(), io::Error> {
+        save_ram(&self.ram_data, file)
+    }
+
+    fn load(&mut self, file: &Path) -> Result<(), io::Error> {
+        load_ram(&mut self.ram_data, file)
+    }
+}
+
+// The RTC keeps counting from when the cartridge was loaded, only its registers are saved
+impl Snapshot for MBC3 {
+    fn save(&self, w: &mut StateWriter) {
+        w.u8(self.rom_bank as u8);
+        w.u8(self.ram_bank as u8);
+        w.bool(self.ram_enabled);
+        w.u8(self.rtc_selected as u8);
+        for v in [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.lower_day,
+            self.upper_day,
+        ] {
+            w.u8(v);
+        }
+        w.u8(self.latch_state as u8);
+        w.raw(&self.ram_data);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        use RTCMode::*;
+        self.rom_bank = r.u8()? as usize;
+        self.ram_bank = r.u8()? as usize;
+        self.ram_enabled = r.bool()?;
+        self.rtc_selected = [Ram, Seconds, Minutes, Hours, LowerDay, UpperDay]
+            .get(r.u8()? as usize)
+            .copied()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Bad RTC register"))?;
+        self.seconds = r.u8()?;
+        self.minutes = r.u8()?;
+        self.hours = r.u8()?;
+        self.lower_day = r.u8()?;
+        self.upper_day = r.u8()?;
+        self.latch_state = [
+            LatchState::Unlatched,
+            LatchState::AttemptingLatch,
+            LatchState::Latched,
+            LatchState::AttemptingUnlatch,
+        ]
+        .get(r.u8()? as usize)
+        .copied()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Bad RTC latch"))?;
+        let len = self.ram_data.len();
+        self.ram_data.copy_from_slice(r.raw(len)?);
+        Ok(())
+    }
 }
@@ -1,97 +1,187 @@
-use super::Memory;
-use crate::types::{Address, Byte};
+use std::{io, path::Path};
 
-// enum BankingMode {
-//     RAMBank,
-//     ROMBank,
-// }
+use super::{
+    load_ram, ram_offset, rom_byte, save_ram, wrap_bank, Memory, PersistentMemory, RAM_BANK_SIZE,
+};
+use crate::{
+    state::{Snapshot, StateReader, StateWriter},
+    types::{Address, Byte},
+};
 
+#[derive(Debug)]
 pub struct MBC5 {
     rom_data: Vec<u8>,
     rom_bank: usize,
     ram_bank: usize,
     ram_enabled: bool,
     ram_data: Vec<u8>,
+    // Rumble carts drive the motor from bit 3 of the RAM bank register instead
+    rumble: Option<bool>,
 }
 
-const BANK_MAX: usize = 512;
-const ROM_BANK_SIZE: usize = 0x4000;
-const RAM_BANK_SIZE: usize = 0x2000;
+const RAM_BANKS: usize = 16;
+const RUMBLE_BIT: u8 = 0b0000_1000;
 
-impl Memory for MBC5 {
-    fn from_bytes(bytes: Vec<u8>) -> Self {
-        if bytes.len() > (BANK_MAX * ROM_BANK_SIZE) {
-            panic!("ERROR: Cartrige size greater than expected ROM size");
-        }
+impl MBC5 {
+    pub fn new(bytes: Vec<u8>, ram_size: usize, rumble: bool) -> Self {
         Self {
             rom_data: bytes,
-            ram_data: vec![0; 0x8000],
+            ram_data: vec![0; ram_size],
             rom_bank: 1,
             ram_bank: 0,
             ram_enabled: false,
+            rumble: rumble.then_some(false),
         }
     }
+}
+
+impl Memory for MBC5 {
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self::new(bytes, RAM_BANK_SIZE * RAM_BANKS, false)
+    }
 
     fn read(&self, addr: Address) -> Byte {
         match addr.0 {
-            0x0000..=0x3FFF => Byte(self.rom_data[addr.0 as usize]),
-
-            0x4000..=0x7FFF => {
-                let offset = addr.0 as usize - 0x4000 + (self.rom_bank * ROM_BANK_SIZE);
-                Byte(self.rom_data[offset])
-            }
-
-            0xA000..=0xBFFF => {
-                if self.ram_enabled {
-                    let offset = addr.0 as usize - 0xA000 + self.ram_bank * RAM_BANK_SIZE;
-                    Byte(self.ram_data[offset])
-                } else {
-                    Byte(0xFF)
-                }
-            }
-
-            _ => unreachable!(),
+            0x0000..=0x3FFF => rom_byte(&self.rom_data, 0, addr),
+            0x4000..=0x7FFF => rom_byte(&self.rom_data, self.rom_bank, addr),
+            0xA000..=0xBFFF if self.ram_enabled => ram_offset(&self.ram_data, self.ram_bank, addr)
+                .map_or(Byte(0xFF), |offset| Byte(self.ram_data[offset])),
+            _ => Byte(0xFF),
         }
     }
 
     fn write(&mut self, addr: Address, val: Byte) {
         match addr.0 {
             0x0000..=0x1FFF => self.ram_enabled = (val.0 & 0x0F) == 0x0A,
-            0x2000..=0x2FFF => {
-                self.ram_bank = (self.ram_bank & 0xFF00) + val.0 as usize;
-            }
-            0x3000..=0x3FFF => {
-                self.ram_bank = (self.ram_bank & 0xFF) + (((val.0 as usize) & 0x1) << 8);
-            }
-            0x4000..=0x5FFF => {
-                self.ram_bank = val.0 as usize & 0x0F;
-            }
-            0xA000..=0xBFFF => {
-                if self.ram_enabled {
-                    let offset = self.ram_bank * RAM_BANK_SIZE - 0xA000;
-                    self.ram_data[offset] = val.0;
+            // 9 bit bank number, unlike the older MBCs bank 0 can be mapped here too
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | val.0 as usize,
+            0x3000..=0x3FFF => self.rom_bank = (self.rom_bank & 0xFF) | ((val.0 as usize & 1) << 8),
+            0x4000..=0x5FFF => match self.rumble {
+                Some(_) => {
+                    self.rumble = Some(val.0 & RUMBLE_BIT != 0);
+                    self.ram_bank = val.0 as usize & 0b0000_0111;
                 }
-            }
-            _ => unreachable!(),
+                None => self.ram_bank = val.0 as usize & 0b0000_1111,
+            },
+            0xA000..=0xBFFF if self.ram_enabled => self.poke_ram(addr, val),
+            _ => {}
         }
     }
+
+    fn poke_ram(&mut self, addr: Address, val: Byte) {
+        if let Some(offset) = ram_offset(&self.ram_data, self.ram_bank, addr) {
+            self.ram_data[offset] = val.0;
+        }
+    }
+
+    fn ram_bank(&self) -> usize {
+        self.ram_bank
+    }
+
+    fn rom_bank(&self) -> usize {
+        wrap_bank(&self.rom_data, self.rom_bank)
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram_data
+    }
+
+    fn persistent(&mut self) -> Option<&mut dyn PersistentMemory> {
+        Some(self)
+    }
+
+    fn rumble(&self) -> bool {
+        self.rumble == Some(true)
+    }
+}
+
+impl PersistentMemory for MBC5 {
+    fn save(&self, file: &Path) -> Result<(), io::Error> {
+        save_ram(&self.ram_data, file)
+    }
+
+    fn load(&mut self, file: &Path) -> Result<(), io::Error> {
+        load_ram(&mut self.ram_data, file)
+    }
+}
+
+impl Snapshot for MBC5 {
+    fn save(&self, w: &mut StateWriter) {
+        w.u16(self.rom_bank as u16);
+        w.u8(self.ram_bank as u8);
+        w.bool(self.ram_enabled);
+        w.bool(self.rumble());
+        w.raw(&self.ram_data);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        self.rom_bank = r.u16()? as usize;
+        self.ram_bank = r.u8()? as usize;
+        self.ram_enabled = r.bool()?;
+        let rumble = r.bool()?;
+        self.rumble = self.rumble.map(|_| rumble);
+        let len = self.ram_data.len();
+        self.ram_data.copy_from_slice(r.raw(len)?);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod test {
     use crate::{
-        mbc::Memory,
+        mbc::{Memory, RAM_BANK_SIZE, ROM_BANK_SIZE},
         types::{Address, Byte},
     };
 
     use super::MBC5;
 
     #[test]
-    fn test_ram_bank_set() {
-        let mut b = MBC5::from_bytes(vec![]);
+    fn test_rom_bank_set() {
+        let mut rom = vec![0; ROM_BANK_SIZE * 0x120];
+        rom[ROM_BANK_SIZE * 0x110] = 0x42;
+        let mut b = MBC5::from_bytes(rom);
         b.write(Address(0x2111), Byte(0x10));
-        assert_eq!(b.ram_bank, 0x10);
+        assert_eq!(b.rom_bank, 0x10);
         b.write(Address(0x3000), Byte(0x11));
-        assert_eq!(b.ram_bank, 0x110);
+        assert_eq!(b.rom_bank, 0x110);
+        assert_eq!(b.read(Address(0x4000)), Byte(0x42));
+
+        b.write(Address(0x2000), Byte(0x00));
+        b.write(Address(0x3000), Byte(0x00));
+        assert_eq!(b.rom_bank, 0);
+    }
+
+    #[test]
+    fn test_ram_banks() {
+        let mut b = MBC5::from_bytes(vec![]);
+        b.write(Address(0x0000), Byte(0x0A));
+        b.write(Address(0x4000), Byte(0x0F));
+        b.write(Address(0xA000), Byte(0x99));
+        assert_eq!(b.ram_data[RAM_BANK_SIZE * 15], 0x99);
+        assert!(!b.rumble());
+    }
+
+    #[test]
+    fn test_64k_ram() {
+        let mut b = MBC5::new(vec![], RAM_BANK_SIZE * 8, false);
+        b.write(Address(0x0000), Byte(0x0A));
+        b.write(Address(0x4000), Byte(0x07));
+        b.write(Address(0xA000), Byte(0x77));
+        b.write(Address(0x4000), Byte(0x08));
+        b.write(Address(0xA000), Byte(0x88));
+        assert_eq!(b.ram_data[0], 0x88);
+        assert_eq!(b.ram_data[RAM_BANK_SIZE * 7], 0x77);
+    }
+
+    #[test]
+    fn test_rumble() {
+        let mut b = MBC5::new(vec![], RAM_BANK_SIZE * 16, true);
+        b.write(Address(0x0000), Byte(0x0A));
+        b.write(Address(0x4000), Byte(0x0B));
+        assert!(b.rumble());
+        b.write(Address(0xA000), Byte(0x99));
+        assert_eq!(b.ram_data[RAM_BANK_SIZE * 3], 0x99);
+        b.write(Address(0x4000), Byte(0x03));
+        assert!(!b.rumble());
     }
 }
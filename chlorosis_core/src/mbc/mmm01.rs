@@ -0,0 +1,281 @@
+use std::{io, path::Path};
+
+use super::{load_ram, ram_offset, rom_byte, save_ram, wrap_bank, Memory, PersistentMemory};
+use crate::{
+    state::{Snapshot, StateReader, StateWriter},
+    types::{Address, Byte, CartrigeHeader, MemoryBankControllerType},
+};
+
+// Multi-game mapper. It powers on unmapped with the menu in the last 32 KB, the menu then
+// writes which game to run, which bank bits the game may change, and maps it in. From then on
+// it behaves like an MBC1 until reset. Multiplexing (bit 6 of 0x6000) isn't emulated
+#[derive(Debug)]
+pub struct MMM01 {
+    rom_data: Vec<u8>,
+    ram_data: Vec<u8>,
+    mapped: bool,
+    rom_low: usize,  // 5 bits, the game's bank register
+    rom_mid: usize,  // 2 bits, set by the menu
+    rom_high: usize, // 2 bits, set by the menu
+    rom_mask: usize, // Bits 1-4 of rom_low the menu keeps
+    ram_enabled: bool,
+    ram_low: usize,  // 2 bits, the game's bank register
+    ram_high: usize, // 2 bits, set by the menu
+    ram_mask: usize, // Bits of ram_low the menu keeps
+    mode: bool,
+    mode_locked: bool,
+}
+
+const MENU_SIZE: usize = 0x8000;
+const MAP_ENABLE: u8 = 0b0100_0000;
+
+impl MMM01 {
+    pub fn new(bytes: Vec<u8>, ram_size: usize) -> Self {
+        Self {
+            rom_data: bytes,
+            ram_data: vec![0; ram_size],
+            mapped: false,
+            rom_low: 0,
+            rom_mid: 0,
+            rom_high: 0,
+            rom_mask: 0,
+            ram_enabled: false,
+            ram_low: 0,
+            ram_high: 0,
+            ram_mask: 0,
+            mode: false,
+            mode_locked: false,
+        }
+    }
+
+    // Dumps start with the first game, the menu's header at the end is the one naming MMM01
+    pub fn menu_header(rom: &[u8]) -> Option<CartrigeHeader> {
+        use MemoryBankControllerType as Type;
+        let menu = rom.len().checked_sub(MENU_SIZE)?;
+        CartrigeHeader::parse(&rom[menu..]).ok().filter(|h| {
+            h.verify_boot().is_ok()
+                && matches!(
+                    h.mapper(),
+                    Type::MMM01 | Type::MMM01_RAM | Type::MMM01_RAM_BATTERY
+                )
+        })
+    }
+
+    // Bank bits the menu chose, the game can't change these
+    const fn game_base(&self) -> usize {
+        (self.rom_high << 7) | (self.rom_mid << 5) | (self.rom_low & (self.rom_mask << 1))
+    }
+
+    const fn lower_bank(&self) -> usize {
+        if self.mapped {
+            self.game_base()
+        } else {
+            0x1FE
+        }
+    }
+
+    const fn upper_bank(&self) -> usize {
+        if !self.mapped {
+            return 0x1FF;
+        }
+        // Like MBC1, zero in the game's bits selects 1
+        let game = self.rom_low & 0b0001_1111 & !(self.rom_mask << 1);
+        self.game_base() | if game == 0 { 1 } else { game }
+    }
+
+    // Writes to a register the menu has partly locked only change the game's bits
+    const fn masked(old: usize, new: usize, keep: usize) -> usize {
+        (old & keep) | (new & !keep)
+    }
+}
+
+impl Memory for MMM01 {
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self::new(bytes, 0)
+    }
+
+    fn read(&self, addr: Address) -> Byte {
+        match addr.0 {
+            0x0000..=0x3FFF => rom_byte(&self.rom_data, self.lower_bank(), addr),
+            0x4000..=0x7FFF => rom_byte(&self.rom_data, self.upper_bank(), addr),
+            0xA000..=0xBFFF if self.ram_enabled => {
+                ram_offset(&self.ram_data, self.ram_bank(), addr)
+                    .map_or(Byte(0xFF), |offset| Byte(self.ram_data[offset]))
+            }
+            _ => Byte(0xFF),
+        }
+    }
+
+    fn write(&mut self, addr: Address, val: Byte) {
+        let val = val.0 as usize;
+        let mapped = self.mapped;
+        match addr.0 {
+            0x0000..=0x1FFF => {
+                self.ram_enabled = val & 0x0F == 0x0A;
+                if !mapped {
+                    self.ram_mask = (val >> 4) & 0b11;
+                    self.mapped = val & MAP_ENABLE as usize != 0;
+                }
+            }
+            0x2000..=0x3FFF if mapped => {
+                self.rom_low = Self::masked(self.rom_low, val & 0b1_1111, self.rom_mask << 1)
+            }
+            0x2000..=0x3FFF => {
+                self.rom_low = val & 0b1_1111;
+                self.rom_mid = (val >> 5) & 0b11;
+            }
+            0x4000..=0x5FFF if mapped => {
+                self.ram_low = Self::masked(self.ram_low, val & 0b11, self.ram_mask)
+            }
+            0x4000..=0x5FFF => {
+                self.ram_low = val & 0b11;
+                self.ram_high = (val >> 2) & 0b11;
+                self.rom_high = (val >> 4) & 0b11;
+                self.mode_locked = val & 0b0100_0000 != 0;
+            }
+            0x6000..=0x7FFF => {
+                if !self.mode_locked {
+                    self.mode = val & 1 != 0;
+                }
+                if !mapped {
+                    self.rom_mask = (val >> 2) & 0b1111;
+                }
+            }
+            0xA000..=0xBFFF if self.ram_enabled => self.poke_ram(addr, Byte(val as u8)),
+            _ => {}
+        }
+    }
+
+    fn poke_ram(&mut self, addr: Address, val: Byte) {
+        if let Some(offset) = ram_offset(&self.ram_data, self.ram_bank(), addr) {
+            self.ram_data[offset] = val.0;
+        }
+    }
+
+    fn ram_bank(&self) -> usize {
+        let game = if self.mode {
+            self.ram_low & !self.ram_mask
+        } else {
+            0
+        };
+        (self.ram_high << 2) | (self.ram_low & self.ram_mask) | game
+    }
+
+    fn rom_bank(&self) -> usize {
+        wrap_bank(&self.rom_data, self.upper_bank())
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram_data
+    }
+
+    fn persistent(&mut self) -> Option<&mut dyn PersistentMemory> {
+        Some(self)
+    }
+}
+
+impl PersistentMemory for MMM01 {
+    fn save(&self, file: &Path) -> Result<(), io::Error> {
+        save_ram(&self.ram_data, file)
+    }
+
+    fn load(&mut self, file: &Path) -> Result<(), io::Error> {
+        load_ram(&mut self.ram_data, file)
+    }
+}
+
+impl Snapshot for MMM01 {
+    fn save(&self, w: &mut StateWriter) {
+        w.bool(self.mapped);
+        for v in [
+            self.rom_low,
+            self.rom_mid,
+            self.rom_high,
+            self.rom_mask,
+            self.ram_low,
+            self.ram_high,
+            self.ram_mask,
+        ] {
+            w.u8(v as u8);
+        }
+        w.bool(self.ram_enabled);
+        w.bool(self.mode);
+        w.bool(self.mode_locked);
+        w.raw(&self.ram_data);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        self.mapped = r.bool()?;
+        self.rom_low = r.u8()? as usize;
+        self.rom_mid = r.u8()? as usize;
+        self.rom_high = r.u8()? as usize;
+        self.rom_mask = r.u8()? as usize;
+        self.ram_low = r.u8()? as usize;
+        self.ram_high = r.u8()? as usize;
+        self.ram_mask = r.u8()? as usize;
+        self.ram_enabled = r.bool()?;
+        self.mode = r.bool()?;
+        self.mode_locked = r.bool()?;
+        let len = self.ram_data.len();
+        self.ram_data.copy_from_slice(r.raw(len)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        mbc::{tests::numbered_rom, Memory, ROM_BANK_SIZE},
+        types::{Address, Byte},
+    };
+
+    use super::{MAP_ENABLE, MMM01};
+
+    fn rom() -> Vec<u8> {
+        numbered_rom(ROM_BANK_SIZE * 32)
+    }
+
+    #[test]
+    fn test_boot_sequence() {
+        let mut mbc = MMM01::new(rom(), 0x2000);
+
+        // The menu runs from the last 32 KB
+        assert_eq!(mbc.read(Address(0x0000)), Byte(30));
+        assert_eq!(mbc.read(Address(0x4000)), Byte(31));
+
+        // Select the game at bank 8 and lock bits 1-4 of the bank number, then map it
+        mbc.write(Address(0x2000), Byte(0x08));
+        mbc.write(Address(0x6000), Byte(0b0011_1100));
+        assert_eq!(mbc.read(Address(0x0000)), Byte(30));
+        mbc.write(Address(0x0000), Byte(MAP_ENABLE));
+        assert!(mbc.mapped);
+        assert_eq!(mbc.read(Address(0x0000)), Byte(8));
+        assert_eq!(mbc.read(Address(0x4000)), Byte(9));
+
+        // The game's writes can't leave its banks, and the menu's registers are locked
+        mbc.write(Address(0x2000), Byte(0x1F));
+        assert_eq!(mbc.read(Address(0x4000)), Byte(9));
+        mbc.write(Address(0x6000), Byte(0x00));
+        mbc.write(Address(0x0000), Byte(0x00));
+        assert!(mbc.mapped);
+        assert_eq!(mbc.read(Address(0x0000)), Byte(8));
+    }
+
+    #[test]
+    fn test_mapped_game() {
+        let mut mbc = MMM01::new(rom(), 0x2000);
+
+        // A 64 KB game at bank 16, it only keeps bits 2-4
+        mbc.write(Address(0x2000), Byte(0x10));
+        mbc.write(Address(0x6000), Byte(0b0011_1000));
+        mbc.write(Address(0x0000), Byte(MAP_ENABLE | 0x0A));
+        assert_eq!(mbc.read(Address(0x4000)), Byte(17));
+        mbc.write(Address(0x2000), Byte(0x03));
+        assert_eq!(mbc.read(Address(0x4000)), Byte(19));
+        mbc.write(Address(0x2000), Byte(0x00));
+        assert_eq!(mbc.read(Address(0x4000)), Byte(17));
+
+        mbc.write(Address(0xA000), Byte(0x42));
+        assert_eq!(mbc.read(Address(0xA000)), Byte(0x42));
+    }
+}
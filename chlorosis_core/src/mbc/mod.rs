@@ -1,19 +1,271 @@
-use std::{io, path::PathBuf};
+use std::{fmt::Debug, io, path::Path};
 
-use crate::types::{Address, Byte};
+use crate::{
+    device::Device,
+    state::Snapshot,
+    types::{Address, Byte, CartrigeHeader, MemoryBankControllerType},
+};
 
 mod mbc1;
 mod mbc2;
 mod mbc3;
 mod mbc5;
+mod mmm01;
+mod rom;
 
-trait Memory {
-    fn from_bytes(bytes: Vec<u8>) -> Self;
+pub use mbc1::MBC1;
+pub use mbc2::MBC2;
+pub use mbc3::MBC3;
+pub use mbc5::MBC5;
+pub use mmm01::MMM01;
+pub use rom::RomOnly;
+
+pub use crate::constants::{ERAM_BANK_SIZE as RAM_BANK_SIZE, ROM_BANK_SIZE};
+
+// Everything on the cartridge bus, 0x0000-0x7FFF and 0xA000-0xBFFF
+// Save states cover the bank registers and RAM, never the ROM itself
+pub trait Memory: Snapshot + Debug + Send {
+    fn from_bytes(bytes: Vec<u8>) -> Self
+    where
+        Self: Sized;
     fn read(&self, addr: Address) -> Byte;
     fn write(&mut self, addr: Address, val: Byte);
+
+    // Bank currently mapped at 0x4000-0x7FFF
+    fn rom_bank(&self) -> usize;
+    fn ram(&self) -> &[u8];
+
+    // Bank currently mapped at 0xA000-0xBFFF
+    fn ram_bank(&self) -> usize {
+        0
+    }
+
+    // Writes cartridge RAM even while the game has it disabled, for cheats and debuggers
+    fn poke_ram(&mut self, addr: Address, val: Byte);
+
+    // Mappers that know how to keep their RAM in a file, only used when the cart has a battery
+    fn persistent(&mut self) -> Option<&mut dyn PersistentMemory> {
+        None
+    }
+
+    // Whether the cartridge's rumble motor is on
+    fn rumble(&self) -> bool {
+        false
+    }
+}
+
+pub trait PersistentMemory {
+    fn save(&self, file: &Path) -> Result<(), io::Error>;
+    fn load(&mut self, file: &Path) -> Result<(), io::Error>;
 }
 
-trait PersistentMemory {
-    fn save(file: impl Into<PathBuf>) -> Result<(), io::Error>;
-    fn load(file: impl Into<PathBuf>) -> Result<(), io::Error>;
+// Picks the mapper named by the header with as much RAM as the header says is fitted, mappers
+// that aren't emulated yet stay on the device's flat ROM. MBC2's RAM is built into the mapper,
+// so its header always says none. An MMM01 menu header (see `MMM01::menu_header`) wins
+pub fn from_header(
+    header: &CartrigeHeader,
+    menu: Option<&CartrigeHeader>,
+    rom: Vec<u8>,
+) -> Option<Box<dyn Memory>> {
+    use MemoryBankControllerType as Type;
+    if let Some(menu) = menu {
+        return Some(Box::new(MMM01::new(rom, menu.ram_size() as usize)));
+    }
+    let ram = header.ram_size() as usize;
+    match header.mapper() {
+        Type::ROM_ONLY => Some(Box::new(RomOnly::from_bytes(rom))),
+        Type::ROM_RAM | Type::ROM_RAM_BATTERY => Some(Box::new(RomOnly::new(rom, ram))),
+        Type::MMM01 | Type::MMM01_RAM | Type::MMM01_RAM_BATTERY => {
+            Some(Box::new(MMM01::new(rom, ram)))
+        }
+        Type::MBC1 | Type::MBC1_RAM | Type::MBC1_RAM_BATTERY => Some(Box::new(MBC1::new(rom, ram))),
+        Type::MBC2 | Type::MBC2_BATTERY => Some(Box::new(MBC2::from_bytes(rom))),
+        Type::MBC3
+        | Type::MBC3_RAM
+        | Type::MBC3_RAM_BATTERY
+        | Type::MBC3_TIMER_BATTERY
+        | Type::MBC3_TIMER_RAM_BATTERY => Some(Box::new(MBC3::new(rom, ram))),
+        Type::MBC5 | Type::MBC5_RAM | Type::MBC5_RAM_BATTERY => {
+            Some(Box::new(MBC5::new(rom, ram, false)))
+        }
+        Type::MBC5_RUMBLE | Type::MBC5_RUMBLE_RAM | Type::MBC5_RUMBLE_RAM_BATTERY => {
+            Some(Box::new(MBC5::new(rom, ram, true)))
+        }
+        _ => None,
+    }
+}
+
+// Bank numbers wrap at the size of the ROM, the register bits past it aren't wired
+fn wrap_bank(rom: &[u8], bank: usize) -> usize {
+    bank % (rom.len() / ROM_BANK_SIZE).max(1)
+}
+
+// Byte of a banked ROM, nothing drives the bus past the end of the image
+fn rom_byte(rom: &[u8], bank: usize, addr: Address) -> Byte {
+    let offset = wrap_bank(rom, bank) * ROM_BANK_SIZE + (addr.0 as usize & (ROM_BANK_SIZE - 1));
+    rom.get(offset).map_or(Byte(0xFF), |b| Byte(*b))
+}
+
+// Offset into cartridge RAM, none without any. A 2 KB chip repeats across the 8 KB window and
+// banks wrap at the size of the RAM
+fn ram_offset(ram: &[u8], bank: usize, addr: Address) -> Option<usize> {
+    let offset = bank * RAM_BANK_SIZE + (addr.0 as usize & (RAM_BANK_SIZE - 1));
+    (!ram.is_empty()).then(|| offset % ram.len())
+}
+
+// Battery saves are a plain dump of the RAM
+fn save_ram(ram: &[u8], file: &Path) -> Result<(), io::Error> {
+    std::fs::write(file, ram)
+}
+
+fn load_ram(ram: &mut [u8], file: &Path) -> Result<(), io::Error> {
+    let data = std::fs::read(file)?;
+    if data.len() != ram.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Expected {} bytes of RAM, not {}", ram.len(), data.len()),
+        ));
+    }
+    ram.copy_from_slice(&data);
+    Ok(())
+}
+
+impl Device {
+    // Battery backed RAM is kept next to the ROM, e.g. game.sav
+    pub(crate) fn load_battery_for(&mut self, rom: &Path) -> Result<(), io::Error> {
+        self.battery_path = None;
+        if !self.cartrige_has_battery() {
+            return Ok(());
+        }
+        let Some(memory) = self.mbc.as_mut().and_then(|m| m.persistent()) else {
+            return Ok(());
+        };
+        let path = rom.with_extension("sav");
+        if path.exists() {
+            memory.load(&path)?;
+            eprintln!("Loaded battery RAM from {}", path.display());
+        }
+        self.battery_path = Some(path);
+        Ok(())
+    }
+
+    pub fn save_battery(&mut self) -> Result<(), io::Error> {
+        let memory = self.mbc.as_mut().and_then(|m| m.persistent());
+        if let (Some(path), Some(memory)) = (&self.battery_path, memory) {
+            memory.save(path)?;
+        }
+        Ok(())
+    }
+
+    // MMM01 carts are described by the menu's header rather than the first game's
+    fn cartrige_has_battery(&self) -> bool {
+        self.menu_header
+            .as_ref()
+            .or_else(|| self.get_cartridge_header())
+            .is_some_and(|h| h.mapper().has_battery())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use super::{MMM01, ROM_BANK_SIZE};
+    use crate::{
+        device::DeviceState,
+        types::{HeaderFix, MemoryBankControllerType},
+        Address, Byte, Device, Event,
+    };
+
+    // Every bank starts with its own number
+    pub fn numbered_rom(len: usize) -> Vec<u8> {
+        let mut rom = vec![0; len];
+        for (i, bank) in rom.chunks_mut(ROM_BANK_SIZE).enumerate() {
+            bank[0] = i as u8;
+        }
+        rom
+    }
+
+    fn fixed(mut rom: Vec<u8>, mapper: MemoryBankControllerType) -> Vec<u8> {
+        HeaderFix {
+            mapper: Some(mapper),
+            ram_size: Some(0x02),
+            ..HeaderFix::default()
+        }
+        .apply(&mut rom)
+        .unwrap();
+        rom
+    }
+
+    #[test]
+    fn test_from_header_mmm01() {
+        // The first game's header is at the start, the menu's is in the last 32 KB
+        let mut rom = numbered_rom(ROM_BANK_SIZE * 32);
+        let menu_start = rom.len() - ROM_BANK_SIZE * 2;
+        let menu = fixed(
+            rom[menu_start..].to_vec(),
+            MemoryBankControllerType::MMM01_RAM_BATTERY,
+        );
+        rom[menu_start..].copy_from_slice(&menu);
+        let rom = fixed(rom, MemoryBankControllerType::MBC1);
+
+        let mut d = Device::new();
+        d.load_rom(&rom).unwrap();
+        assert!(d.menu_header.is_some());
+        assert!(d.cartrige_has_battery());
+        assert_eq!(d.read(Address(0x0000)), Byte(30), "boots into the menu");
+        assert_eq!(d.mbc.as_ref().unwrap().ram().len(), 0x2000);
+    }
+
+    #[test]
+    fn test_from_header_mbc1() {
+        // Small enough that its own header is where a menu's would be
+        for banks in [2, 32] {
+            let rom = fixed(
+                numbered_rom(ROM_BANK_SIZE * banks),
+                MemoryBankControllerType::MBC1_RAM,
+            );
+            assert!(MMM01::menu_header(&rom).is_none());
+            let mut d = Device::new();
+            d.load_rom(&rom).unwrap();
+            assert!(d.menu_header.is_none());
+            assert!(!d.cartrige_has_battery());
+            assert_eq!(d.read(Address(0x0000)), Byte(0));
+            assert_eq!(d.read(Address(0x4000)), Byte(1));
+        }
+    }
+
+    #[test]
+    fn test_battery_saved_on_exit() {
+        let dir = std::env::temp_dir().join("chlorosis_battery_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut rom = numbered_rom(ROM_BANK_SIZE * 4);
+        HeaderFix {
+            mapper: Some(MemoryBankControllerType::MBC1_RAM_BATTERY),
+            rom_size: Some(0x01),
+            ram_size: Some(0x02),
+            ..HeaderFix::default()
+        }
+        .apply(&mut rom)
+        .unwrap();
+        std::fs::write(dir.join("game.gb"), rom).unwrap();
+
+        let mut d = Device::new();
+        d.load_cartrige(dir.join("game.gb")).unwrap();
+        d.state = DeviceState::Paused;
+        d.write(Address(0x0000), Byte(0x0A));
+        d.write(Address(0xA000), Byte(0x42));
+
+        // Exit is the last thing the core sees, the save has to be written before it returns
+        let (buffer, _frames) = channel();
+        let (events, receiver) = channel();
+        let core = std::thread::spawn(move || d.run(buffer, receiver));
+        events.send(Event::Exit).unwrap();
+        core.join().unwrap();
+
+        let save = std::fs::read(dir.join("game.sav")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(save.len(), 0x2000);
+        assert_eq!(save[0], 0x42);
+    }
 }
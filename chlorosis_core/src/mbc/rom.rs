@@ -0,0 +1,109 @@
+use std::{io, path::Path};
+
+use super::{load_ram, ram_offset, rom_byte, save_ram, Memory, PersistentMemory};
+use crate::{
+    state::{Snapshot, StateReader, StateWriter},
+    types::{Address, Byte},
+};
+
+// 32 KB of ROM and up to 8 KB of RAM wired straight to the bus, the RAM is always enabled
+#[derive(Debug)]
+pub struct RomOnly {
+    rom_data: Vec<u8>,
+    ram_data: Vec<u8>,
+}
+
+impl RomOnly {
+    pub fn new(bytes: Vec<u8>, ram_size: usize) -> Self {
+        Self {
+            rom_data: bytes,
+            ram_data: vec![0; ram_size],
+        }
+    }
+}
+
+impl Memory for RomOnly {
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self::new(bytes, 0)
+    }
+
+    fn read(&self, addr: Address) -> Byte {
+        match addr.0 {
+            0x0000..=0x3FFF => rom_byte(&self.rom_data, 0, addr),
+            0x4000..=0x7FFF => rom_byte(&self.rom_data, 1, addr),
+            0xA000..=0xBFFF => ram_offset(&self.ram_data, 0, addr)
+                .map_or(Byte(0xFF), |offset| Byte(self.ram_data[offset])),
+            _ => Byte(0xFF),
+        }
+    }
+
+    // The RAM is always enabled
+    fn write(&mut self, addr: Address, val: Byte) {
+        if let 0xA000..=0xBFFF = addr.0 {
+            self.poke_ram(addr, val);
+        }
+    }
+
+    fn poke_ram(&mut self, addr: Address, val: Byte) {
+        if let Some(offset) = ram_offset(&self.ram_data, 0, addr) {
+            self.ram_data[offset] = val.0;
+        }
+    }
+
+    fn rom_bank(&self) -> usize {
+        1
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram_data
+    }
+
+    fn persistent(&mut self) -> Option<&mut dyn PersistentMemory> {
+        Some(self)
+    }
+}
+
+impl PersistentMemory for RomOnly {
+    fn save(&self, file: &Path) -> Result<(), io::Error> {
+        save_ram(&self.ram_data, file)
+    }
+
+    fn load(&mut self, file: &Path) -> Result<(), io::Error> {
+        load_ram(&mut self.ram_data, file)
+    }
+}
+
+impl Snapshot for RomOnly {
+    fn save(&self, w: &mut StateWriter) {
+        w.raw(&self.ram_data);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        let len = self.ram_data.len();
+        self.ram_data.copy_from_slice(r.raw(len)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        mbc::Memory,
+        types::{Address, Byte},
+    };
+
+    use super::RomOnly;
+
+    #[test]
+    fn test_ram() {
+        let mut mbc = RomOnly::new(vec![0; 0x8000], 0x2000);
+        mbc.write(Address(0xA123), Byte(0x42));
+        assert_eq!(mbc.read(Address(0xA123)), Byte(0x42));
+        mbc.write(Address(0x2000), Byte(0x42));
+        assert_eq!(mbc.read(Address(0x2000)), Byte(0x00));
+
+        let mut mbc = RomOnly::from_bytes(vec![0; 0x8000]);
+        mbc.write(Address(0xA123), Byte(0x42));
+        assert_eq!(mbc.read(Address(0xA123)), Byte(0xFF));
+    }
+}
@@ -0,0 +1,399 @@
+use std::{
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    constants::DOTS_PER_FRAME,
+    device::Device,
+    state::{StateReader, StateWriter},
+    Error,
+};
+
+// Input movies record the joypad as a button mask (see `Joypad::buttons`) each time it changes,
+// keyed by emulated frame. Changes only ever take effect at the start of a frame, while
+// recording as well as during playback, so a replay sees exactly the same input timing.
+// Version 2 added the frame recording stopped at, version 1 movies end at their last change
+
+const MAGIC: &[u8; 4] = b"CHLM";
+const VERSION: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieStart {
+    PowerOn,
+    State(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub checksum: u16, // Global checksum of the ROM it was recorded on
+    pub start: MovieStart,
+    pub inputs: Vec<(u64, u8)>, // Frame, buttons held from then on
+    pub end: u64,               // Frame recording stopped at
+}
+
+impl Movie {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = StateWriter::default();
+        w.raw(MAGIC);
+        w.u8(VERSION);
+        w.u16(self.checksum);
+        match &self.start {
+            MovieStart::PowerOn => w.u8(0),
+            MovieStart::State(state) => {
+                w.u8(1);
+                w.u32(state.len() as u32);
+                w.raw(state);
+            }
+        }
+        w.u32(self.inputs.len() as u32);
+        for &(frame, buttons) in &self.inputs {
+            w.u64(frame);
+            w.u8(buttons);
+        }
+        w.u64(self.end);
+        w.finish()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, io::Error> {
+        let mut r = StateReader::new(data);
+        if r.raw(4)? != MAGIC {
+            return Err(io::Error::new(ErrorKind::InvalidData, "Not a movie"));
+        }
+        let version = r.u8()?;
+        if !(1..=VERSION).contains(&version) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Unsupported movie version {version}"),
+            ));
+        }
+        let checksum = r.u16()?;
+        let start = match r.u8()? {
+            0 => MovieStart::PowerOn,
+            1 => {
+                let len = r.u32()? as usize;
+                MovieStart::State(r.raw(len)?.to_vec())
+            }
+            s => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Unknown movie start {s}"),
+                ))
+            }
+        };
+        let inputs: Vec<_> = (0..r.u32()?)
+            .map(|_| Ok((r.u64()?, r.u8()?)))
+            .collect::<Result<_, io::Error>>()?;
+        let end = match version {
+            1 => inputs.last().map_or(0, |&(f, _)| f),
+            _ => r.u64()?,
+        };
+        Ok(Self {
+            checksum,
+            start,
+            inputs,
+            end,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), io::Error> {
+        std::fs::write(path, self.to_bytes())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, io::Error> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+#[derive(Debug)]
+pub enum MoviePlayer {
+    Recording {
+        movie: Movie,
+        path: PathBuf,
+        pending: u8, // Live input waiting for the next frame
+    },
+    Playing {
+        movie: Movie,
+        next: usize,
+    },
+}
+
+impl Device {
+    pub const fn frame(&self) -> u64 {
+        self.cycles / DOTS_PER_FRAME
+    }
+
+    // Power on recordings reset the device first, otherwise the current state is embedded
+    pub fn record_movie(&mut self, path: impl Into<PathBuf>, power_on: bool) -> Result<(), Error> {
+        self.stop_movie();
+        let start = if power_on {
+            self.reset()?;
+            MovieStart::PowerOn
+        } else {
+            MovieStart::State(self.save_state())
+        };
+        let buttons = self.joypad.buttons();
+        let path = path.into();
+        eprintln!("Recording movie to {}", path.display());
+        self.movie = Some(MoviePlayer::Recording {
+            movie: Movie {
+                checksum: self.rom_checksum(),
+                start,
+                inputs: vec![(self.frame(), buttons)],
+                end: self.frame(),
+            },
+            path,
+            pending: buttons,
+        });
+        Ok(())
+    }
+
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), Error> {
+        self.stop_movie();
+        if movie.checksum != self.rom_checksum() {
+            return Err(Error::InvalidState(
+                "Movie was recorded on a different ROM".to_owned(),
+            ));
+        }
+        match &movie.start {
+            MovieStart::PowerOn => self.reset()?,
+            MovieStart::State(state) => self.load_state(state)?,
+        }
+        self.joypad.set_buttons(0);
+        self.movie = Some(MoviePlayer::Playing { movie, next: 0 });
+        self.movie_frame();
+        Ok(())
+    }
+
+    // Recordings are written out when stopped, ending at the current frame
+    pub fn stop_movie(&mut self) {
+        let frame = self.frame();
+        match self.movie.take() {
+            Some(MoviePlayer::Recording {
+                mut movie, path, ..
+            }) => {
+                movie.end = frame;
+                match movie.save(&path) {
+                    Ok(()) => eprintln!("Saved movie to {}", path.display()),
+                    Err(e) => eprintln!("Failed to save movie to {}: {e}", path.display()),
+                }
+            }
+            Some(MoviePlayer::Playing { .. }) => eprintln!("Stopped movie playback"),
+            None => {}
+        }
+    }
+
+    // Live input is buffered while recording and ignored during playback
+    pub(crate) fn movie_input(&mut self, buttons: impl FnOnce(&mut u8)) -> bool {
+        match &mut self.movie {
+            Some(MoviePlayer::Recording { pending, .. }) => {
+                buttons(pending);
+                true
+            }
+            Some(MoviePlayer::Playing { .. }) => true,
+            None => false,
+        }
+    }
+
+    // Loading a state while recording goes back in time, so what was recorded after it is
+    // dropped and recorded again. A state from before the recording started ends it.
+    // During playback the movie picks up again from the loaded frame
+    pub(crate) fn movie_restored(&mut self) {
+        let frame = self.frame();
+        let buttons = self.joypad.buttons();
+        let movie = match &mut self.movie {
+            Some(MoviePlayer::Recording { movie, .. }) => movie,
+            Some(MoviePlayer::Playing { movie, next }) => {
+                // The start of the loaded frame has already gone by, so its change is in effect
+                *next = movie.inputs.partition_point(|&(f, _)| f <= frame);
+                let buttons = next.checked_sub(1).map_or(0, |i| movie.inputs[i].1);
+                self.joypad.set_buttons(buttons);
+                return;
+            }
+            None => return,
+        };
+        if movie.inputs.first().is_some_and(|&(f, _)| frame < f) {
+            eprintln!("Loaded a state from before the movie started");
+            self.stop_movie();
+            return;
+        }
+        movie.inputs.retain(|&(f, _)| f < frame);
+        if movie.inputs.last().map(|&(_, b)| b) != Some(buttons) {
+            movie.inputs.push((frame, buttons));
+        }
+    }
+
+    // Called at the start of every frame
+    pub(crate) fn movie_frame(&mut self) {
+        let frame = self.frame();
+        match &mut self.movie {
+            Some(MoviePlayer::Recording { movie, pending, .. })
+                if self.joypad.buttons() != *pending =>
+            {
+                self.joypad.set_buttons(*pending);
+                movie.inputs.push((frame, *pending));
+            }
+            Some(MoviePlayer::Playing { movie, next }) => {
+                while let Some(&(f, buttons)) = movie.inputs.get(*next) {
+                    if f > frame {
+                        break;
+                    }
+                    self.joypad.set_buttons(buttons);
+                    *next += 1;
+                }
+                if frame >= movie.end {
+                    eprintln!("Movie finished");
+                    self.movie = None;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Movie, MoviePlayer, MovieStart};
+    use crate::{constants::DOTS_PER_FRAME, Address, Byte, Device, KeyCode};
+
+    #[test]
+    fn test_serialise() {
+        let movie = Movie {
+            checksum: 0x1234,
+            start: MovieStart::State(vec![1, 2, 3]),
+            inputs: vec![(0, 0), (10, 0x09), (12, 0)],
+            end: 20,
+        };
+        assert_eq!(Movie::from_bytes(&movie.to_bytes()).unwrap(), movie);
+
+        // Version 1 had no end frame and stopped at the last change
+        let mut v1 = movie.to_bytes();
+        v1[4] = 1;
+        v1.truncate(v1.len() - 8);
+        let end = Movie::from_bytes(&v1).unwrap().end;
+        assert_eq!(end, 12);
+        assert!(Movie::from_bytes(b"CHLS").is_err());
+    }
+
+    #[test]
+    fn test_record_and_play() {
+        // JP $C000, spins forever
+        let mut d = Device::new();
+        d.poke(Address(0xC000), Byte(0xC3));
+        d.poke(Address(0xC001), Byte(0x00));
+        d.poke(Address(0xC002), Byte(0xC0));
+        d.cpu.pc = Address(0xC000);
+
+        let path = std::env::temp_dir().join("chlorosis_record_and_play.movie");
+        d.record_movie(&path, false).unwrap();
+        let run_frames = |d: &mut Device, n: u64| {
+            for _ in 0..n * DOTS_PER_FRAME {
+                d.tick().unwrap();
+            }
+        };
+        run_frames(&mut d, 2);
+        d.handle_keydown(vec![KeyCode::Start]);
+        assert_eq!(d.joypad.buttons(), 0, "input waits for the next frame");
+        run_frames(&mut d, 3);
+        d.handle_keyup(vec![KeyCode::Start]);
+        run_frames(&mut d, 3);
+        d.stop_movie();
+
+        let movie = Movie::load(&path).unwrap();
+        assert_eq!(movie.inputs, [(0, 0), (3, 0x08), (6, 0)]);
+        assert_eq!(movie.end, 8);
+
+        let mut replay = Device::new();
+        replay.play_movie(movie).unwrap();
+        assert_eq!(replay.cpu.pc, Address(0xC000));
+        run_frames(&mut replay, 3);
+        replay.handle_keydown(vec![KeyCode::A]); // Ignored
+        replay.tick().unwrap();
+        assert_eq!(replay.joypad.buttons(), 0x08);
+        run_frames(&mut replay, 3);
+        assert_eq!(replay.joypad.buttons(), 0);
+        assert!(replay.movie.is_some(), "plays on past the last change");
+        run_frames(&mut replay, 2);
+        assert!(replay.movie.is_none());
+    }
+
+    #[test]
+    fn test_play_across_load() {
+        let mut d = Device::new();
+        d.poke(Address(0xC000), Byte(0xC3));
+        d.poke(Address(0xC001), Byte(0x00));
+        d.poke(Address(0xC002), Byte(0xC0));
+        d.cpu.pc = Address(0xC000);
+        let run_frames = |d: &mut Device, n: u64| {
+            for _ in 0..n * DOTS_PER_FRAME {
+                d.tick().unwrap();
+            }
+        };
+        let start = d.save_state();
+        run_frames(&mut d, 4);
+        let late = d.save_state();
+
+        let movie = Movie {
+            checksum: d.rom_checksum(),
+            start: MovieStart::State(start),
+            inputs: vec![(0, 0), (2, 0x08), (6, 0x01)],
+            end: 8,
+        };
+        d.play_movie(movie).unwrap();
+        run_frames(&mut d, 1);
+
+        // Jumping ahead skips the inputs in between, rewinding replays them
+        d.load_state(&late).unwrap();
+        assert_eq!(d.joypad.buttons(), 0x08);
+        run_frames(&mut d, 2);
+        assert_eq!(d.joypad.buttons(), 0x01);
+        d.load_state(&late).unwrap();
+        assert_eq!(d.joypad.buttons(), 0x08);
+        run_frames(&mut d, 2);
+        assert_eq!(d.joypad.buttons(), 0x01);
+        run_frames(&mut d, 2);
+        assert!(d.movie.is_none());
+    }
+
+    #[test]
+    fn test_record_across_load() {
+        let mut d = Device::new();
+        d.poke(Address(0xC000), Byte(0xC3));
+        d.poke(Address(0xC001), Byte(0x00));
+        d.poke(Address(0xC002), Byte(0xC0));
+        d.cpu.pc = Address(0xC000);
+        let run_frames = |d: &mut Device, n: u64| {
+            for _ in 0..n * DOTS_PER_FRAME {
+                d.tick().unwrap();
+            }
+        };
+
+        let path = std::env::temp_dir().join("chlorosis_record_across_load.movie");
+        let before = d.save_state();
+        run_frames(&mut d, 1);
+        d.record_movie(&path, false).unwrap();
+        run_frames(&mut d, 1);
+        let state = d.save_state();
+        d.handle_keydown(vec![KeyCode::Start]);
+        run_frames(&mut d, 3);
+        d.handle_keyup(vec![KeyCode::Start]);
+        run_frames(&mut d, 2);
+
+        // Everything after the loaded frame is recorded again, in order
+        d.load_state(&state).unwrap();
+        d.handle_keydown(vec![KeyCode::A]);
+        run_frames(&mut d, 2);
+        d.handle_keyup(vec![KeyCode::A]);
+        run_frames(&mut d, 1);
+        match &d.movie {
+            Some(MoviePlayer::Recording { movie, .. }) => {
+                assert_eq!(movie.inputs, [(1, 0), (3, 0x01), (5, 0)])
+            }
+            _ => panic!("still recording"),
+        }
+
+        d.load_state(&before).unwrap();
+        assert!(d.movie.is_none());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,387 @@
+use std::{
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use crate::constants::MAX_ROM_SIZE;
+
+// ROM patches in the three common formats. IPS carries no checksums, UPS and BPS end with the
+// CRC32 of the source, the target and the patch itself, all of which are verified
+
+const EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_owned())
+}
+
+// A patch next to the ROM with the same stem, e.g. game.gb and game.ips
+pub fn find_patch(rom: &Path) -> Option<PathBuf> {
+    EXTENSIONS
+        .iter()
+        .map(|e| rom.with_extension(e))
+        .find(|p| p.exists())
+}
+
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, io::Error> {
+    match patch.get(..5) {
+        Some(b"PATCH") => apply_ips(rom, patch),
+        _ => match patch.get(..4) {
+            Some(b"UPS1") => apply_ups(rom, patch),
+            Some(b"BPS1") => apply_bps(rom, patch),
+            _ => Err(invalid("Unknown patch format")),
+        },
+    }
+}
+
+struct PatchReader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    const fn new(data: &'a [u8], at: usize) -> Self {
+        Self { data, at }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], io::Error> {
+        let bytes = self
+            .at
+            .checked_add(len)
+            .and_then(|end| self.data.get(self.at..end))
+            .ok_or_else(|| invalid("Patch is truncated"))?;
+        self.at += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, io::Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn be(&mut self, len: usize) -> Result<usize, io::Error> {
+        Ok(self
+            .bytes(len)?
+            .iter()
+            .fold(0, |v, &b| (v << 8) | b as usize))
+    }
+
+    // UPS and BPS numbers, 7 bits at a time with an implicit +1 per continuation
+    fn varint(&mut self) -> Result<usize, io::Error> {
+        let (mut value, mut shift) = (0usize, 1usize);
+        loop {
+            let x = self.u8()?;
+            value = (x as usize & 0x7F)
+                .checked_mul(shift)
+                .and_then(|v| v.checked_add(value))
+                .ok_or_else(|| invalid("Patch number overflows"))?;
+            if x & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift
+                .checked_mul(0x80)
+                .ok_or_else(|| invalid("Patch number overflows"))?;
+            value = value
+                .checked_add(shift)
+                .ok_or_else(|| invalid("Patch number overflows"))?;
+        }
+    }
+
+    // The output is allocated up front, so no patch may ask for more than the largest cartridge
+    fn target_size(&mut self) -> Result<usize, io::Error> {
+        let size = self.varint()?;
+        if size > MAX_ROM_SIZE {
+            return Err(invalid("Patched ROM is larger than any cartridge"));
+        }
+        Ok(size)
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let mut out = rom.to_vec();
+    let mut r = PatchReader::new(patch, 5);
+    loop {
+        if r.data.get(r.at..r.at + 3) == Some(b"EOF") {
+            r.at += 3;
+            // Some patches append the length to truncate the ROM to
+            if r.data.len() == r.at + 3 {
+                out.truncate(r.be(3)?);
+            }
+            return Ok(out);
+        }
+        let offset = r.be(3)?;
+        let (len, fill) = match r.be(2)? {
+            0 => (r.be(2)?, Some(r.u8()?)),
+            len => (len, None),
+        };
+        if offset + len > MAX_ROM_SIZE {
+            return Err(invalid("Patched ROM is larger than any cartridge"));
+        }
+        if out.len() < offset + len {
+            out.resize(offset + len, 0);
+        }
+        match fill {
+            Some(b) => out[offset..offset + len].fill(b),
+            None => out[offset..offset + len].copy_from_slice(r.bytes(len)?),
+        }
+    }
+}
+
+// Splits off the source, target and patch CRCs, checking the last
+fn footer(patch: &[u8]) -> Result<(&[u8], u32, u32), io::Error> {
+    if patch.len() < 16 {
+        return Err(invalid("Patch is truncated"));
+    }
+    let (body, footer) = patch.split_at(patch.len() - 12);
+    let crc = |i: usize| u32::from_le_bytes(footer[i..i + 4].try_into().unwrap());
+    if crc32fast::hash(&patch[..patch.len() - 4]) != crc(8) {
+        return Err(invalid("Patch checksum mismatch, the patch is corrupt"));
+    }
+    Ok((body, crc(0), crc(4)))
+}
+
+fn check_crc(data: &[u8], expected: u32, which: &str) -> Result<(), io::Error> {
+    if crc32fast::hash(data) != expected {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("{which} checksum mismatch, the patch is for a different ROM"),
+        ));
+    }
+    Ok(())
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let (body, source_crc, target_crc) = footer(patch)?;
+    check_crc(rom, source_crc, "Source")?;
+
+    let mut r = PatchReader::new(body, 4);
+    let source_size = r.varint()?;
+    let target_size = r.target_size()?;
+    if source_size != rom.len() {
+        return Err(invalid(
+            "Source size mismatch, the patch is for a different ROM",
+        ));
+    }
+    let mut out = rom.to_vec();
+    out.resize(target_size, 0);
+
+    // Runs of XOR bytes, each ended by a zero, separated by the number of bytes to skip
+    let mut at = 0usize;
+    while r.at < body.len() {
+        at = at
+            .checked_add(r.varint()?)
+            .filter(|&at| at <= out.len())
+            .ok_or_else(|| invalid("Patch writes outside the ROM"))?;
+        loop {
+            let x = r.u8()?;
+            if let Some(b) = out.get_mut(at) {
+                *b ^= x;
+            }
+            at += 1;
+            if x == 0 {
+                break;
+            }
+        }
+    }
+
+    check_crc(&out, target_crc, "Target")?;
+    Ok(out)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let (body, source_crc, target_crc) = footer(patch)?;
+    check_crc(rom, source_crc, "Source")?;
+
+    let mut r = PatchReader::new(body, 4);
+    let source_size = r.varint()?;
+    let target_size = r.target_size()?;
+    let metadata = r.varint()?;
+    r.bytes(metadata)?;
+    if source_size != rom.len() {
+        return Err(invalid(
+            "Source size mismatch, the patch is for a different ROM",
+        ));
+    }
+
+    let mut out = Vec::with_capacity(target_size);
+    let (mut source_at, mut target_at) = (0usize, 0usize);
+    let relative = |r: &mut PatchReader, at: &mut usize| -> Result<(), io::Error> {
+        let v = r.varint()?;
+        let offset = v >> 1;
+        *at = if v & 1 == 0 {
+            at.checked_add(offset)
+        } else {
+            at.checked_sub(offset)
+        }
+        .ok_or_else(|| invalid("Patch copies from outside the ROM"))?;
+        Ok(())
+    };
+    while r.at < body.len() {
+        let action = r.varint()?;
+        let len = (action >> 2) + 1;
+        let end = out
+            .len()
+            .checked_add(len)
+            .filter(|&end| end <= target_size)
+            .ok_or_else(|| invalid("Patch writes past the end of the ROM"))?;
+        match action & 3 {
+            // Source read, the same bytes as the source at this position
+            0 => out.extend_from_slice(
+                rom.get(out.len()..end)
+                    .ok_or_else(|| invalid("Patch reads outside the ROM"))?,
+            ),
+            // Target read, bytes from the patch
+            1 => out.extend_from_slice(r.bytes(len)?),
+            // Source copy, from anywhere in the source
+            2 => {
+                relative(&mut r, &mut source_at)?;
+                let from = source_at
+                    .checked_add(len)
+                    .and_then(|end| rom.get(source_at..end))
+                    .ok_or_else(|| invalid("Patch copies from outside the ROM"))?;
+                out.extend_from_slice(from);
+                source_at += len;
+            }
+            // Target copy, from earlier output, one byte at a time since the runs may overlap
+            _ => {
+                relative(&mut r, &mut target_at)?;
+                for _ in 0..len {
+                    let b = *out
+                        .get(target_at)
+                        .ok_or_else(|| invalid("Patch copies from outside the output"))?;
+                    out.push(b);
+                    target_at += 1;
+                }
+            }
+        }
+    }
+    if out.len() != target_size {
+        return Err(invalid("Patched ROM is the wrong size"));
+    }
+
+    check_crc(&out, target_crc, "Target")?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::apply_patch;
+
+    fn varint(mut v: usize, out: &mut Vec<u8>) {
+        loop {
+            let x = (v & 0x7F) as u8;
+            v >>= 7;
+            if v == 0 {
+                out.push(0x80 | x);
+                return;
+            }
+            out.push(x);
+            v -= 1;
+        }
+    }
+
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_ips() {
+        let rom = vec![0u8; 8];
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0, 0, 1, 0, 2, 0xAA, 0xBB]); // Two bytes at 1
+        patch.extend_from_slice(&[0, 0, 6, 0, 0, 0, 4, 0xCC]); // Four 0xCC at 6
+        patch.extend_from_slice(b"EOF");
+        assert_eq!(
+            apply_patch(&rom, &patch).unwrap(),
+            [0, 0xAA, 0xBB, 0, 0, 0, 0xCC, 0xCC, 0xCC, 0xCC]
+        );
+        patch.extend_from_slice(&[0, 0, 3]);
+        assert_eq!(apply_patch(&rom, &patch).unwrap(), [0, 0xAA, 0xBB]);
+    }
+
+    #[test]
+    fn test_ups() {
+        let source = [1, 2, 3, 4, 5];
+        let target = [1, 9, 3, 4, 5, 6];
+        let mut patch = b"UPS1".to_vec();
+        varint(source.len(), &mut patch);
+        varint(target.len(), &mut patch);
+        varint(1, &mut patch);
+        patch.extend_from_slice(&[2 ^ 9, 0]);
+        varint(2, &mut patch);
+        patch.extend_from_slice(&[6, 0]);
+        let patch = with_footer(patch, &source, &target);
+        assert_eq!(apply_patch(&source, &patch).unwrap(), target);
+
+        // Wrong ROM, then a corrupt patch
+        assert!(apply_patch(&[1, 2, 3, 4, 6], &patch).is_err());
+        let mut corrupt = patch;
+        corrupt[8] ^= 1;
+        assert!(apply_patch(&source, &corrupt).is_err());
+    }
+
+    #[test]
+    fn test_bps() {
+        let source = [10, 11, 12, 13, 14, 15];
+        let target = [10, 11, 99, 14, 15, 15, 15, 15];
+        let mut patch = b"BPS1".to_vec();
+        varint(source.len(), &mut patch);
+        varint(target.len(), &mut patch);
+        varint(0, &mut patch);
+        varint((2 - 1) << 2, &mut patch); // Source read 2
+        varint(1, &mut patch); // Target read 1, (1 - 1) << 2 | 1
+        patch.push(99);
+        varint((2 - 1) << 2 | 2, &mut patch); // Source copy 2 from +4
+        varint(4 << 1, &mut patch);
+        varint((3 - 1) << 2 | 3, &mut patch); // Target copy 3 from +4, overlapping
+        varint(4 << 1, &mut patch);
+        let target_copy = with_footer(patch, &source, &target);
+        assert_eq!(apply_patch(&source, &target_copy).unwrap(), target);
+        assert!(apply_patch(&source[1..], &target_copy).is_err());
+    }
+
+    #[test]
+    fn test_hostile_sizes() {
+        let source = [1, 2, 3, 4];
+        let error = |patch: Vec<u8>| {
+            apply_patch(&source, &with_footer(patch, &source, &source))
+                .unwrap_err()
+                .to_string()
+        };
+
+        // A target bigger than any cartridge is refused before anything is allocated
+        let mut huge = b"UPS1".to_vec();
+        varint(source.len(), &mut huge);
+        varint(usize::MAX >> 8, &mut huge);
+        assert_eq!(error(huge), "Patched ROM is larger than any cartridge");
+        let mut fill = b"PATCH".to_vec();
+        fill.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0, 0, 0xFF, 0xFF, 0x42]);
+        fill.extend_from_slice(b"EOF");
+        assert_eq!(
+            apply_patch(&source, &fill).unwrap_err().to_string(),
+            "Patched ROM is larger than any cartridge"
+        );
+
+        // Skips that overflow, and varints longer than a usize
+        let mut skip = b"UPS1".to_vec();
+        varint(source.len(), &mut skip);
+        varint(source.len(), &mut skip);
+        varint(2, &mut skip);
+        skip.push(0);
+        varint(usize::MAX - 1, &mut skip);
+        skip.push(0);
+        assert_eq!(error(skip), "Patch writes outside the ROM");
+        let mut long = b"BPS1".to_vec();
+        long.extend_from_slice(&[0; 12]);
+        assert_eq!(error(long), "Patch number overflows");
+
+        // An action whose length wraps around
+        let mut wrap = b"BPS1".to_vec();
+        varint(source.len(), &mut wrap);
+        varint(source.len(), &mut wrap);
+        varint(0, &mut wrap);
+        varint(usize::MAX & !3, &mut wrap);
+        assert_eq!(error(wrap), "Patch writes past the end of the ROM");
+    }
+}
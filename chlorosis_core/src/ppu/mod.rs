@@ -1,17 +1,29 @@
 mod oam;
+mod palette;
 mod pixel;
 mod registers;
 mod tile;
 
-use self::{
+pub use self::registers::ObjectSize;
+use self::registers::{StatusMode, TileAddressingMode};
+pub use self::{
+    oam::{ObjectAttribute, OBJECTS_PER_LINE, OBJECT_COUNT},
+    palette::Palette,
     pixel::Pixel,
-    registers::{StatusMode, TileAddressingMode},
-    tile::Tile,
+    tile::{MapTile, Tile, TileAttributes},
 };
-use crate::{constants::*, Address, Byte};
-use std::collections::VecDeque;
+use crate::{
+    constants::*,
+    state::{Snapshot, StateReader, StateWriter},
+    Address, Byte, Error,
+};
+use std::{collections::VecDeque, io, ops::RangeInclusive};
+
+fn blocked(access: &str, address: Address) -> Error {
+    Error::InvalidState(format!("{access} at {address}"))
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(non_snake_case)]
 pub struct PixelProcessor {
     pub buffer: Option<[u32; 160 * 144]>,
@@ -86,7 +98,7 @@ impl Default for PixelProcessor {
 }
 
 impl PixelProcessor {
-    pub fn step(&mut self) {
+    pub fn step(&mut self) -> Result<(), Error> {
         // Step PPU one dot, runs at 4.194 MHz
         // One frame is 16.74 ms or 70224 dots
 
@@ -110,22 +122,17 @@ impl PixelProcessor {
                     self.update_line_dot_count();
                 }
             }
-            StatusMode::OAM => self.step_oam(),
-            StatusMode::Draw => self.step_draw(),
+            StatusMode::OAM => {}
+            StatusMode::Draw => return self.step_draw(),
         }
+        Ok(())
     }
 
-    fn step_oam(&mut self) {
-        if self.LY == self.SCY {
-            println!("Draw window");
-        }
-    }
-
-    fn step_draw(&mut self) {
+    fn step_draw(&mut self) -> Result<(), Error> {
         self.bg_fifo.clear();
         self.obj_fifo.clear();
 
-        unimplemented!();
+        Err(Error::Unimplemented("PPU pixel transfer"))
     }
 
     fn update_line_dot_count(&mut self) {
@@ -137,104 +144,159 @@ impl PixelProcessor {
         }
     }
 
-    pub fn read_vram(&self, address: Address) -> Byte {
+    pub fn read_vram(&self, address: Address) -> Result<Byte, Error> {
         if !self.read_lcdc_enabled() {
-            self.vram[address.0 as usize + (VRAM_BANK_SIZE * self.vram_bank.0 as usize)
-                - VRAM_START as usize]
+            Ok(
+                self.vram[address.0 as usize + (VRAM_BANK_SIZE * self.vram_bank.0 as usize)
+                    - VRAM_START as usize],
+            )
         } else {
-            panic!("cannot access VRAM while LCD enabled")
+            Err(blocked("VRAM read while LCD enabled", address))
         }
     }
 
-    pub fn write_vram(&mut self, address: Address, value: Byte) {
+    pub fn write_vram(&mut self, address: Address, value: Byte) -> Result<(), Error> {
         if self.read_stat_mode() == StatusMode::VBlank
             || self.read_stat_mode() == StatusMode::HBlank
         {
             self.vram[address.0 as usize + (VRAM_BANK_SIZE * self.vram_bank.0 as usize)
                 - VRAM_START as usize] = value;
+            Ok(())
         } else {
-            panic!("Attempted VRAM write during render")
+            Err(blocked("VRAM write during render", address))
         }
     }
 
-    pub fn read_oam(&self, address: Address) -> Byte {
+    pub fn read_oam(&self, address: Address) -> Result<Byte, Error> {
         if self.read_stat_mode() == StatusMode::VBlank
             || self.read_stat_mode() == StatusMode::HBlank
         {
-            self.oam[address.0 as usize - OAM_START as usize]
+            Ok(self.oam[address.0 as usize - OAM_START as usize])
         } else {
-            panic!("Attempted OAM access during render")
+            Err(blocked("OAM read during render", address))
         }
     }
 
-    pub fn write_oam(&mut self, address: Address, value: Byte) {
+    pub fn write_oam(&mut self, address: Address, value: Byte) -> Result<(), Error> {
         if self.read_stat_mode() == StatusMode::VBlank
             || self.read_stat_mode() == StatusMode::HBlank
         {
             self.oam[address.0 as usize - OAM_START as usize] = value;
+            Ok(())
         } else {
-            panic!("Attempted OAM write during render time")
+            Err(blocked("OAM write during render", address))
         }
     }
 
     // Tiles stored in VRAM, each bank holds 384 tiles (16 bytes each)
     // Split into 3 blocks of 128 tiles
     pub fn get_tile(&self, index: Byte, mode: TileAddressingMode) -> Tile {
+        self.read_tile_data(self.vram_bank.0 as usize, mode.tile_data_index(index))
+    }
+
+    // Reads a tile by its position in VRAM (0-383), ignoring addressing mode and selected bank
+    pub fn read_tile_data(&self, bank: usize, index: usize) -> Tile {
         let mut b = [Byte(0); 16];
+        let start = bank * VRAM_BANK_SIZE + index * TILE_SIZE;
+        b.copy_from_slice(&self.vram[start..start + TILE_SIZE]);
+        Tile(b)
+    }
 
-        match mode {
-            TileAddressingMode::Unsigned => {
-                for (i, x) in self
-                    .vram
-                    .iter()
-                    .skip(index.0 as usize * TILE_SIZE + self.vram_bank.0 as usize * VRAM_BANK_SIZE)
-                    .take(TILE_SIZE)
-                    .enumerate()
-                {
-                    b[i] = *x;
-                }
+    // Tile map entries are stored in bank 0, with their CGB attributes at the same offset in bank 1
+    pub fn get_tile_map(&self, area: RangeInclusive<u16>) -> Vec<MapTile> {
+        let mode = self.read_tile_addressing_mode();
+        area.map(|addr| {
+            let offset = (addr - VRAM_START) as usize;
+            let index = self.vram[offset];
+            let attributes = TileAttributes::from(self.vram[VRAM_BANK_SIZE + offset]);
+            MapTile {
+                index: index.0,
+                attributes,
+                tile: self
+                    .read_tile_data(attributes.vram_bank as usize, mode.tile_data_index(index)),
             }
-            TileAddressingMode::Signed => {
-                if index.0 > 127 {
-                    for (i, x) in self
-                        .vram
-                        .iter()
-                        .skip(
-                            0x0800
-                                + index.0 as usize * TILE_SIZE
-                                + self.vram_bank.0 as usize * VRAM_BANK_SIZE,
-                        )
-                        .take(TILE_SIZE)
-                        .enumerate()
-                    {
-                        b[i] = *x;
-                    }
-                } else {
-                    for (i, x) in self
-                        .vram
-                        .iter()
-                        .skip(
-                            index.0 as usize * TILE_SIZE
-                                + self.vram_bank.0 as usize * VRAM_BANK_SIZE,
-                        )
-                        .take(TILE_SIZE)
-                        .enumerate()
-                    {
-                        b[i] = *x;
-                    }
-                }
+        })
+        .collect()
+    }
+}
+
+// The frame buffer is output rather than state, so it is not saved
+impl Snapshot for PixelProcessor {
+    fn save(&self, w: &mut StateWriter) {
+        w.bytes(&self.vram);
+        w.byte(self.vram_bank);
+        w.bytes(&self.oam);
+        w.bytes(&self.bcram);
+        w.bytes(&self.ocram);
+        w.u32(self.line_dot_counter);
+        w.u32(self.frame_dot_counter);
+        for fifo in [&self.bg_fifo, &self.obj_fifo] {
+            w.u8(fifo.len() as u8);
+            for p in fifo {
+                w.u8(p.r);
+                w.u8(p.g);
+                w.u8(p.b);
             }
         }
-        Tile(b)
+        for r in [
+            self.LCDC, self.STAT, self.SCY, self.SCX, self.LY, self.LYC, self.DMA, self.BGP,
+            self.OBP0, self.OBP1, self.WY, self.WX, self.KEY1, self.HDMA1, self.HDMA2, self.HDMA3,
+            self.HDMA4, self.HDMA5, self.BCPS, self.OCPS, self.OPRI,
+        ] {
+            w.byte(r);
+        }
     }
 
-    fn get_tile_map(&self) -> Vec<Tile> {
-        let mut out = Vec::with_capacity(32 * 32);
-        let mode = self.read_tile_addressing_mode();
-        for i in self.read_background_tile_map_area() {
-            let index = self.vram[i as usize]; // TODO: may need to implement bank switch
-            out.push(self.get_tile(index, mode))
+    fn load(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        r.bytes(&mut self.vram)?;
+        self.vram_bank = r.byte()?;
+        r.bytes(&mut self.oam)?;
+        r.bytes(&mut self.bcram)?;
+        r.bytes(&mut self.ocram)?;
+        self.line_dot_counter = r.u32()?;
+        self.frame_dot_counter = r.u32()?;
+        for fifo in [&mut self.bg_fifo, &mut self.obj_fifo] {
+            fifo.clear();
+            for _ in 0..r.u8()? {
+                fifo.push_back(Pixel {
+                    r: r.u8()?,
+                    g: r.u8()?,
+                    b: r.u8()?,
+                });
+            }
+        }
+        for reg in self.registers_mut() {
+            *reg = r.byte()?;
         }
-        out
+        Ok(())
+    }
+}
+
+impl PixelProcessor {
+    // Same order as saved
+    const fn registers_mut(&mut self) -> [&mut Byte; 21] {
+        [
+            &mut self.LCDC,
+            &mut self.STAT,
+            &mut self.SCY,
+            &mut self.SCX,
+            &mut self.LY,
+            &mut self.LYC,
+            &mut self.DMA,
+            &mut self.BGP,
+            &mut self.OBP0,
+            &mut self.OBP1,
+            &mut self.WY,
+            &mut self.WX,
+            &mut self.KEY1,
+            &mut self.HDMA1,
+            &mut self.HDMA2,
+            &mut self.HDMA3,
+            &mut self.HDMA4,
+            &mut self.HDMA5,
+            &mut self.BCPS,
+            &mut self.OCPS,
+            &mut self.OPRI,
+        ]
     }
 }
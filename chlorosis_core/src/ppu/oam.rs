@@ -1,28 +1,91 @@
-use crate::types::Byte;
+use crate::{types::Byte, PixelProcessor};
 
+use super::registers::ObjectSize;
+
+pub const OBJECT_COUNT: usize = 40;
+pub const OBJECTS_PER_LINE: usize = 10;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct ObjectAttribute {
-    y: Byte,
-    x: Byte,
-    tile_index: Byte,
-    is_occluded: bool,
-    xflip: bool,
-    yflip: bool,
-    // dmp_pallete
-    vram_bank: bool, // false = 0, true = 1
-    pallete: u8,
+    pub y: u8, // Screen position + 16
+    pub x: u8, // Screen position + 8
+    pub tile_index: u8,
+    pub is_occluded: bool, // BG and window colours 1-3 drawn over the object
+    pub yflip: bool,
+    pub xflip: bool,
+    pub dmg_palette: bool, // false = OBP0, true = OBP1
+    pub vram_bank: bool,   // false = 0, true = 1
+    pub palette: u8,
 }
 
 impl ObjectAttribute {
     pub const fn new(a: Byte, b: Byte, c: Byte, d: Byte) -> Self {
         Self {
-            y: a,
-            x: b,
-            tile_index: c,
+            y: a.0,
+            x: b.0,
+            tile_index: c.0,
             is_occluded: d.is_bit_set(7),
-            xflip: d.is_bit_set(6),
-            yflip: d.is_bit_set(5),
+            yflip: d.is_bit_set(6),
+            xflip: d.is_bit_set(5),
+            dmg_palette: d.is_bit_set(4),
             vram_bank: d.is_bit_set(3),
-            pallete: d.0 & 0b0000_0111,
+            palette: d.0 & 0b0000_0111,
+        }
+    }
+}
+
+impl PixelProcessor {
+    pub fn read_object_attributes(&self) -> Vec<ObjectAttribute> {
+        self.oam
+            .chunks_exact(4)
+            .map(|o| ObjectAttribute::new(o[0], o[1], o[2], o[3]))
+            .collect()
+    }
+
+    // OAM scan selects the first 10 objects in OAM order that overlap the line,
+    // returns the indices of the selected objects and those dropped by the limit
+    pub fn scan_line_objects(&self, line: u8) -> (Vec<usize>, Vec<usize>) {
+        let height = match self.read_obj_size() {
+            ObjectSize::Square => 8,
+            ObjectSize::Tall => 16,
+        };
+        let line = line as usize + 16;
+        let mut on_line: Vec<usize> = self
+            .read_object_attributes()
+            .iter()
+            .enumerate()
+            .filter(|(_, o)| line >= o.y as usize && line < o.y as usize + height)
+            .map(|(i, _)| i)
+            .collect();
+        let dropped = on_line.split_off(on_line.len().min(OBJECTS_PER_LINE));
+        (on_line, dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Byte, PixelProcessor};
+
+    #[test]
+    fn test_object_attribute_flags() {
+        let o = super::ObjectAttribute::new(Byte(0x10), Byte(0x08), Byte(0x2A), Byte(0b0101_1010));
+        assert!(!o.is_occluded);
+        assert!(o.yflip);
+        assert!(!o.xflip);
+        assert!(o.dmg_palette);
+        assert!(o.vram_bank);
+        assert_eq!(o.palette, 0b010);
+    }
+
+    #[test]
+    fn test_scan_line_limit() {
+        let mut ppu = PixelProcessor::default();
+        for i in 0..12 {
+            ppu.oam[i * 4] = Byte(16);
         }
+        ppu.oam[12 * 4] = Byte(40);
+        let (selected, dropped) = ppu.scan_line_objects(0);
+        assert_eq!(selected, (0..10).collect::<Vec<_>>());
+        assert_eq!(dropped, vec![10, 11]);
     }
 }
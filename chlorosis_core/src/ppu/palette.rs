@@ -0,0 +1,174 @@
+use super::pixel::Pixel;
+use crate::{Byte, PixelProcessor};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Palette {
+    Grey,
+    Background,        // BGP
+    Object0,           // OBP0
+    Object1,           // OBP1
+    CgbBackground(u8), // BG palette 0-7 in BCRAM
+    CgbObject(u8),     // OBJ palette 0-7 in OCRAM
+}
+
+impl Palette {
+    pub const ALL: [Self; 20] = [
+        Self::Grey,
+        Self::Background,
+        Self::Object0,
+        Self::Object1,
+        Self::CgbBackground(0),
+        Self::CgbBackground(1),
+        Self::CgbBackground(2),
+        Self::CgbBackground(3),
+        Self::CgbBackground(4),
+        Self::CgbBackground(5),
+        Self::CgbBackground(6),
+        Self::CgbBackground(7),
+        Self::CgbObject(0),
+        Self::CgbObject(1),
+        Self::CgbObject(2),
+        Self::CgbObject(3),
+        Self::CgbObject(4),
+        Self::CgbObject(5),
+        Self::CgbObject(6),
+        Self::CgbObject(7),
+    ];
+}
+
+impl std::fmt::Display for Palette {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Grey => write!(f, "Grey"),
+            Self::Background => write!(f, "BGP"),
+            Self::Object0 => write!(f, "OBP0"),
+            Self::Object1 => write!(f, "OBP1"),
+            Self::CgbBackground(n) => write!(f, "BG{n}"),
+            Self::CgbObject(n) => write!(f, "OBJ{n}"),
+        }
+    }
+}
+
+impl PixelProcessor {
+    pub fn read_palette(&self, palette: Palette) -> [Pixel; 4] {
+        match palette {
+            Palette::Grey => Pixel::SHADES,
+            Palette::Background => dmg_palette(self.BGP),
+            Palette::Object0 => dmg_palette(self.OBP0),
+            Palette::Object1 => dmg_palette(self.OBP1),
+            Palette::CgbBackground(n) => cgb_palette(&self.bcram, n),
+            Palette::CgbObject(n) => cgb_palette(&self.ocram, n),
+        }
+    }
+
+    // Raw value of a single colour, the shade (0-3) for DMG palettes or RGB555 for CGB palettes
+    pub const fn read_palette_value(&self, palette: Palette, colour: u8) -> u16 {
+        let shift = (colour & 0b11) * 2;
+        match palette {
+            Palette::Grey => colour as u16 & 0b11,
+            Palette::Background => ((self.BGP.0 >> shift) & 0b11) as u16,
+            Palette::Object0 => ((self.OBP0.0 >> shift) & 0b11) as u16,
+            Palette::Object1 => ((self.OBP1.0 >> shift) & 0b11) as u16,
+            Palette::CgbBackground(n) => cgb_value(&self.bcram, n, colour),
+            Palette::CgbObject(n) => cgb_value(&self.ocram, n, colour),
+        }
+    }
+
+    // A debugger poke, CGB colours go straight into palette RAM even while the PPU is drawing
+    pub fn write_palette_value(&mut self, palette: Palette, colour: u8, value: u16) {
+        let shift = (colour & 0b11) * 2;
+        let shade = (value as u8 & 0b11) << shift;
+        let mask = !(0b11 << shift);
+        match palette {
+            Palette::Grey => {}
+            Palette::Background => self.BGP = (self.BGP & mask) + Byte(shade),
+            Palette::Object0 => self.OBP0 = (self.OBP0 & mask) + Byte(shade),
+            Palette::Object1 => self.OBP1 = (self.OBP1 & mask) + Byte(shade),
+            Palette::CgbBackground(n) => write_cgb_value(&mut self.bcram, n, colour, value),
+            Palette::CgbObject(n) => write_cgb_value(&mut self.ocram, n, colour, value),
+        }
+    }
+}
+
+// Each pair of bits maps a colour index to one of the four DMG shades
+fn dmg_palette(value: Byte) -> [Pixel; 4] {
+    let mut out = [Pixel::WHITE; 4];
+    for (i, p) in out.iter_mut().enumerate() {
+        *p = Pixel::SHADES[((value.0 >> (i * 2)) & 0b11) as usize];
+    }
+    out
+}
+
+// Each palette is 8 bytes, 4 colours of 2 bytes each
+const fn cgb_index(n: u8, colour: u8) -> u8 {
+    (n & 0b111) * 8 + (colour & 0b11) * 2
+}
+
+const fn cgb_value(ram: &[Byte; 64], n: u8, colour: u8) -> u16 {
+    let i = cgb_index(n, colour) as usize;
+    ((ram[i + 1].0 as u16) << 8) | ram[i].0 as u16
+}
+
+const fn write_cgb_value(ram: &mut [Byte; 64], n: u8, colour: u8, value: u16) {
+    let i = cgb_index(n, colour) as usize;
+    ram[i] = Byte(value as u8);
+    ram[i + 1] = Byte((value >> 8) as u8);
+}
+
+fn cgb_palette(ram: &[Byte; 64], n: u8) -> [Pixel; 4] {
+    let mut out = [Pixel::WHITE; 4];
+    for (i, p) in out.iter_mut().enumerate() {
+        *p = Pixel::from_rgb555(cgb_value(ram, n, i as u8));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Palette;
+    use crate::{
+        ppu::{pixel::Pixel, registers::StatusMode},
+        Byte, PixelProcessor,
+    };
+
+    #[test]
+    fn test_dmg_palette() {
+        let ppu = PixelProcessor {
+            BGP: Byte(0b1110_0100),
+            ..Default::default()
+        };
+        assert_eq!(ppu.read_palette(Palette::Background), Pixel::SHADES);
+    }
+
+    #[test]
+    fn test_cgb_palette() {
+        let mut ppu = PixelProcessor::default();
+        ppu.bcram[8] = Byte(0x1F); // BG1 colour 0, pure red
+        ppu.bcram[9] = Byte(0x00);
+        ppu.bcram[10] = Byte(0x00); // BG1 colour 1, pure blue
+        ppu.bcram[11] = Byte(0x7C);
+        let p = ppu.read_palette(Palette::CgbBackground(1));
+        assert_eq!(p[0], Pixel { r: 255, g: 0, b: 0 });
+        assert_eq!(p[1], Pixel { r: 0, g: 0, b: 255 });
+    }
+
+    #[test]
+    fn test_write_palette_value() {
+        let mut ppu = PixelProcessor {
+            BCPS: Byte(0x05),
+            ..Default::default()
+        };
+        ppu.write_palette_value(Palette::CgbBackground(2), 3, 0x7C1F);
+        assert_eq!(ppu.read_palette_value(Palette::CgbBackground(2), 3), 0x7C1F);
+        assert_eq!(ppu.BCPS, Byte(0x05));
+
+        // The game can't reach palette RAM while the PPU draws, the editor can
+        ppu.write_stat_mode(StatusMode::Draw);
+        ppu.write_palette_value(Palette::CgbObject(7), 0, 0x1234);
+        assert_eq!(ppu.read_palette_value(Palette::CgbObject(7), 0), 0x1234);
+
+        ppu.write_palette_value(Palette::Object1, 2, 0b11);
+        assert_eq!(ppu.OBP1, Byte(0b0011_0000));
+        assert_eq!(ppu.read_palette_value(Palette::Object1, 2), 0b11);
+    }
+}
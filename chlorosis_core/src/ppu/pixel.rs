@@ -12,6 +12,32 @@ impl Pixel {
         g: 255,
         b: 255,
     };
+    pub const LIGHT_GREY: Self = Self {
+        r: 170,
+        g: 170,
+        b: 170,
+    };
+    pub const DARK_GREY: Self = Self {
+        r: 85,
+        g: 85,
+        b: 85,
+    };
+
+    // DMG shades 0-3, lightest to darkest
+    pub const SHADES: [Self; 4] = [Self::WHITE, Self::LIGHT_GREY, Self::DARK_GREY, Self::BLACK];
+
+    // CGB colours are stored little endian as 0bxBBBBBGGGGGRRRRR
+    pub const fn from_rgb555(value: u16) -> Self {
+        const fn expand(c: u16) -> u8 {
+            let c = (c & 0x1F) as u8;
+            (c << 3) | (c >> 2)
+        }
+        Self {
+            r: expand(value),
+            g: expand(value >> 5),
+            b: expand(value >> 10),
+        }
+    }
 }
 
 impl From<Pixel> for u32 {
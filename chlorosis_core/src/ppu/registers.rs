@@ -3,7 +3,7 @@ use std::ops::RangeInclusive;
 use crate::{Address, Byte, PixelProcessor};
 
 impl PixelProcessor {
-    pub fn read_io(&self, address: Address) -> Byte {
+    pub const fn read_io(&self, address: Address) -> Byte {
         match address.0 {
             0xFF40 => self.LCDC,
             0xFF41 => self.STAT,
@@ -25,7 +25,8 @@ impl PixelProcessor {
             0xFF6A => self.OCPS,
             0xFF6B => self.read_ocram(),
             0xFF6C => self.OPRI,
-            _ => unreachable!("Cannot read IO register {address}"),
+            // Write only and unused registers, e.g. the HDMA source and destination
+            _ => Byte(0xFF),
         }
     }
     pub fn write_io(&mut self, address: Address, value: Byte) {
@@ -62,7 +63,8 @@ impl PixelProcessor {
             0xFF6A => self.OCPS = value,
             0xFF6B => self.write_ocpd(value),
             0xFF6C => self.OPRI = value,
-            _ => unreachable!("Cannot write IO register {address}"),
+            // LY is read only, the boot ROM is never mapped and the rest are unused
+            _ => {}
         }
     }
 
@@ -114,9 +116,9 @@ impl PixelProcessor {
 
     pub const fn read_background_tile_map_area(&self) -> RangeInclusive<u16> {
         if self.LCDC.is_bit_set(3) {
-            0x1C00..=0x1FFF
+            0x9C00..=0x9FFF
         } else {
-            0x1800..=0x1BFF
+            0x9800..=0x9BFF
         }
     }
 
@@ -136,6 +138,18 @@ impl PixelProcessor {
         self.LCDC.is_bit_set(0)
     }
 
+    pub const fn read_ly(&self) -> u8 {
+        self.LY.0
+    }
+
+    pub const fn read_scroll(&self) -> (u8, u8) {
+        (self.SCX.0, self.SCY.0)
+    }
+
+    pub const fn read_window_position(&self) -> (u8, u8) {
+        (self.WX.0, self.WY.0)
+    }
+
     pub fn write_bcpd(&mut self, value: Byte) {
         if !(self.read_stat_mode() == StatusMode::Draw) {
             self.bcram[self.BCPS.0 as usize & 0x3F] = value;
@@ -151,8 +165,8 @@ impl PixelProcessor {
             self.ocram[self.OCPS.0 as usize & 0x3F] = value;
         }
 
-        if self.BCPS.is_bit_set(7) {
-            self.BCPS = Byte((((self.BCPS.0 & 0b0011_1111) + 1) & 0b0011_1111) + 0b1000_0000);
+        if self.OCPS.is_bit_set(7) {
+            self.OCPS = Byte((((self.OCPS.0 & 0b0011_1111) + 1) & 0b0011_1111) + 0b1000_0000);
         }
     }
 
@@ -180,6 +194,17 @@ pub enum TileAddressingMode {
     Signed,
 }
 
+impl TileAddressingMode {
+    // Position of the tile in the 384 tile block, 0x8000 based for unsigned and 0x9000 based for signed
+    pub const fn tile_data_index(self, index: Byte) -> usize {
+        match self {
+            Self::Unsigned => index.0 as usize,
+            Self::Signed => (0x100 + index.0 as i8 as isize) as usize,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ObjectSize {
     Square,
     Tall,
@@ -187,7 +212,8 @@ pub enum ObjectSize {
 
 #[cfg(test)]
 mod tests {
-    use crate::{Byte, PixelProcessor};
+    use super::TileAddressingMode;
+    use crate::{Address, Byte, Device, PixelProcessor};
 
     #[test]
     fn test_bcpd_autoincrement() {
@@ -198,4 +224,42 @@ mod tests {
         ppu.write_bcpd(Byte(0x1F));
         assert_eq!(ppu.BCPS.0, 0b1000_0000);
     }
+
+    #[test]
+    fn test_ocpd_autoincrement() {
+        let mut ppu = PixelProcessor {
+            OCPS: Byte(0b1000_0010),
+            ..Default::default()
+        };
+        ppu.write_ocpd(Byte(0x1F));
+        assert_eq!(ppu.OCPS.0, 0b1000_0011);
+        assert_eq!(ppu.ocram[2], Byte(0x1F));
+    }
+
+    #[test]
+    fn test_unmapped_registers() {
+        let mut d = Device::new();
+        for address in [
+            0xFF4C, 0xFF4E, 0xFF50, 0xFF51, 0xFF52, 0xFF53, 0xFF54, 0xFF6D, 0xFF6F,
+        ] {
+            assert_eq!(d.read(Address(address)), Byte(0xFF));
+        }
+
+        d.ppu.LY = Byte(0x12);
+        for address in [0xFF44, 0xFF4C, 0xFF4E, 0xFF50] {
+            d.write(Address(address), Byte(0x34));
+        }
+        assert_eq!(d.read(Address(0xFF44)), Byte(0x12));
+    }
+
+    #[test]
+    fn test_signed_tile_data_index() {
+        assert_eq!(TileAddressingMode::Signed.tile_data_index(Byte(0x00)), 256);
+        assert_eq!(TileAddressingMode::Signed.tile_data_index(Byte(0x7F)), 383);
+        assert_eq!(TileAddressingMode::Signed.tile_data_index(Byte(0x80)), 128);
+        assert_eq!(
+            TileAddressingMode::Unsigned.tile_data_index(Byte(0x80)),
+            128
+        );
+    }
 }
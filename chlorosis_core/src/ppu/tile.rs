@@ -1,13 +1,31 @@
 use crate::types::Byte;
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Tile(pub [Byte; 16]);
 
+impl Tile {
+    // Each row is two bytes, low bits first, with the leftmost pixel in bit 7
+    pub const fn color_index(&self, x: usize, y: usize) -> u8 {
+        let low = self.0[y * 2].is_bit_set(7 - x as u8) as u8;
+        let high = self.0[y * 2 + 1].is_bit_set(7 - x as u8) as u8;
+        (high << 1) | low
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct TileAttributes {
     pub priority: bool,
     pub vflip: bool,
     pub hflip: bool,
-    vram_bank: bool, // false = 0, true = 1
-    palette: u8,
+    pub vram_bank: bool, // false = 0, true = 1
+    pub palette: u8,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MapTile {
+    pub index: u8,
+    pub attributes: TileAttributes,
+    pub tile: Tile,
 }
 
 impl From<Byte> for TileAttributes {
@@ -17,7 +35,26 @@ impl From<Byte> for TileAttributes {
             vflip: value.is_bit_set(6),
             hflip: value.is_bit_set(5),
             vram_bank: value.is_bit_set(3),
-            palette: value.0 & 0b0000_0011,
+            palette: value.0 & 0b0000_0111,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Tile;
+    use crate::Byte;
+
+    #[test]
+    fn test_color_index() {
+        let mut b = [Byte(0); 16];
+        b[0] = Byte(0b1010_0000);
+        b[1] = Byte(0b1100_0000);
+        let t = Tile(b);
+        assert_eq!(t.color_index(0, 0), 3);
+        assert_eq!(t.color_index(1, 0), 2);
+        assert_eq!(t.color_index(2, 0), 1);
+        assert_eq!(t.color_index(3, 0), 0);
+        assert_eq!(t.color_index(0, 1), 0);
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+
+use crate::device::Device;
+
+// Ten seconds at one snapshot per frame
+pub const REWIND_CAPACITY: usize = 600;
+
+// Holds the newest save state in full plus a chain of deltas, each of which turns a state into
+// the one recorded before it. Deltas XOR consecutive states and run length encode the result,
+// which is mostly zeros since little memory changes between frames
+#[derive(Debug)]
+pub struct RewindBuffer {
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+impl Default for RewindBuffer {
+    fn default() -> Self {
+        Self::new(REWIND_CAPACITY)
+    }
+}
+
+impl RewindBuffer {
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            latest: None,
+            deltas: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(latest) = &self.latest {
+            if self.deltas.len() + 1 == self.capacity {
+                self.deltas.pop_front();
+            }
+            self.deltas.push_back(encode_delta(&state, latest));
+        }
+        self.latest = Some(state);
+    }
+
+    // Newest state first, walking back one snapshot per call
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let state = self.latest.take()?;
+        if let Some(delta) = self.deltas.pop_back() {
+            let mut previous = state.clone();
+            apply_delta(&mut previous, &delta);
+            self.latest = Some(previous);
+        }
+        Some(state)
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+    }
+}
+
+// Target length, then pairs of (unchanged run, changed run) lengths each followed by XOR bytes
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let len = from.len().max(to.len());
+    let xor = |i: usize| from.get(i).copied().unwrap_or(0) ^ to.get(i).copied().unwrap_or(0);
+
+    let mut out = (to.len() as u32).to_le_bytes().to_vec();
+    let mut i = 0;
+    while i < len {
+        let start = i;
+        while i < len && xor(i) == 0 {
+            i += 1;
+        }
+        let skip = i - start;
+        let start = i;
+        while i < len && xor(i) != 0 {
+            i += 1;
+        }
+        out.extend_from_slice(&(skip as u32).to_le_bytes());
+        out.extend_from_slice(&((i - start) as u32).to_le_bytes());
+        out.extend((start..i).map(xor));
+    }
+    out
+}
+
+fn apply_delta(state: &mut Vec<u8>, delta: &[u8]) {
+    let word = |at: usize| u32::from_le_bytes(delta[at..at + 4].try_into().unwrap()) as usize;
+
+    let target = word(0);
+    state.resize(state.len().max(target), 0);
+    let (mut i, mut at) = (0, 4);
+    while at < delta.len() {
+        i += word(at);
+        let changed = word(at + 4);
+        at += 8;
+        for (s, d) in state[i..i + changed]
+            .iter_mut()
+            .zip(&delta[at..at + changed])
+        {
+            *s ^= d;
+        }
+        i += changed;
+        at += changed;
+    }
+    state.truncate(target);
+}
+
+impl Device {
+    pub(crate) fn record_rewind(&mut self) {
+        let state = self.save_state();
+        self.rewind.push(state);
+    }
+
+    // Restores the newest snapshot and drops it, so holding rewind walks back through history
+    pub(crate) fn rewind(&mut self) {
+        if let Some(state) = self.rewind.pop() {
+            if let Err(e) = self.load_state(&state) {
+                eprintln!("Failed to rewind: {e}");
+                self.rewind.clear();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_delta, encode_delta, RewindBuffer};
+    use crate::{Address, Byte, Device};
+
+    #[test]
+    fn test_delta() {
+        let a = vec![0, 1, 2, 3, 4, 5, 6, 7];
+        let b = vec![0, 1, 9, 3, 4, 5, 6, 8, 10];
+        let mut state = b.clone();
+        apply_delta(&mut state, &encode_delta(&b, &a));
+        assert_eq!(state, a);
+        apply_delta(&mut state, &encode_delta(&a, &b));
+        assert_eq!(state, b);
+        // Identical states cost one run header
+        assert_eq!(encode_delta(&a, &a).len(), 12);
+    }
+
+    #[test]
+    fn test_ring() {
+        let mut buffer = RewindBuffer::new(3);
+        for i in 0..5u8 {
+            buffer.push(vec![i; 4]);
+        }
+        assert_eq!(buffer.pop(), Some(vec![4; 4]));
+        assert_eq!(buffer.pop(), Some(vec![3; 4]));
+        assert_eq!(buffer.pop(), Some(vec![2; 4]));
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn test_rewind_device() {
+        let mut d = Device::new();
+        for i in 0..3 {
+            d.poke(Address(0xC000), Byte(i));
+            d.record_rewind();
+        }
+        d.poke(Address(0xC000), Byte(0xFF));
+        d.rewind();
+        assert_eq!(d.peek(Address(0xC000)), Byte(2));
+        d.rewind();
+        d.rewind();
+        assert_eq!(d.peek(Address(0xC000)), Byte(0));
+        d.rewind();
+        assert_eq!(d.peek(Address(0xC000)), Byte(0));
+    }
+}
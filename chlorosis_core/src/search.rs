@@ -0,0 +1,270 @@
+use crate::{constants::ERAM_BANK_SIZE, device::Device, Address, Byte};
+
+// RAM search narrows down where a game keeps a value, e.g. lives, by repeatedly comparing memory
+// against the previous snapshot. Work RAM (every bank), cartridge RAM and high RAM are searched,
+// 16 bit values are little endian and never straddle two regions
+
+const LIST_LIMIT: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchSize {
+    Byte,
+    Word,
+}
+
+// Compared against the given value, or the previous snapshot when none
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchFilter {
+    Equal(Option<u16>),
+    NotEqual(Option<u16>),
+    Greater(Option<u16>),
+    Less(Option<u16>),
+    ChangedBy(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchResult {
+    pub bank: usize,
+    pub address: Address,
+    pub value: u16,
+    pub previous: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct RamSearch {
+    size: SearchSize,
+    snapshot: Vec<u8>,
+    regions: [usize; 3], // Lengths of work, cartridge and high RAM within the snapshot
+    candidates: Vec<usize>,
+}
+
+impl RamSearch {
+    fn new(size: SearchSize, snapshot: Vec<u8>, regions: [usize; 3]) -> Self {
+        let width = Self::width(size);
+        let mut candidates = vec![];
+        let mut start = 0;
+        for len in regions {
+            candidates.extend(start..(start + len + 1).saturating_sub(width));
+            start += len;
+        }
+        Self {
+            size,
+            snapshot,
+            regions,
+            candidates,
+        }
+    }
+
+    const fn width(size: SearchSize) -> usize {
+        match size {
+            SearchSize::Byte => 1,
+            SearchSize::Word => 2,
+        }
+    }
+
+    fn value(&self, data: &[u8], offset: usize) -> u16 {
+        match self.size {
+            SearchSize::Byte => data[offset] as u16,
+            SearchSize::Word => u16::from_le_bytes([data[offset], data[offset + 1]]),
+        }
+    }
+
+    // Keeps the candidates that pass and makes the new memory the previous snapshot
+    fn filter(&mut self, snapshot: Vec<u8>, filter: SearchFilter) -> usize {
+        let mask = match self.size {
+            SearchSize::Byte => 0xFF,
+            SearchSize::Word => 0xFFFF,
+        };
+        let candidates = std::mem::take(&mut self.candidates);
+        self.candidates = candidates
+            .into_iter()
+            .filter(|&offset| {
+                let value = self.value(&snapshot, offset);
+                let previous = self.value(&self.snapshot, offset);
+                match filter {
+                    SearchFilter::Equal(v) => value == v.unwrap_or(previous),
+                    SearchFilter::NotEqual(v) => value != v.unwrap_or(previous),
+                    SearchFilter::Greater(v) => value > v.unwrap_or(previous),
+                    SearchFilter::Less(v) => value < v.unwrap_or(previous),
+                    SearchFilter::ChangedBy(n) => {
+                        value.wrapping_sub(previous) & mask == n as u16 & mask
+                    }
+                }
+            })
+            .collect();
+        self.snapshot = snapshot;
+        self.candidates.len()
+    }
+
+    const fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    // Bank and address the way a raw cheat code would name them
+    const fn location(&self, offset: usize) -> (usize, Address) {
+        let [wram, eram, _] = self.regions;
+        if offset < 0x1000 {
+            (0, Address(0xC000 + offset as u16))
+        } else if offset < wram {
+            (offset / 0x1000, Address(0xD000 + (offset % 0x1000) as u16))
+        } else if offset < wram + eram {
+            let offset = offset - wram;
+            (
+                offset / ERAM_BANK_SIZE,
+                Address(0xA000 + (offset % ERAM_BANK_SIZE) as u16),
+            )
+        } else {
+            (0, Address(0xFF80 + (offset - wram - eram) as u16))
+        }
+    }
+}
+
+impl Device {
+    fn search_snapshot(&self) -> (Vec<u8>, [usize; 3]) {
+        let eram: Vec<u8> = self.mbc.as_ref().map_or_else(
+            || self.eram.iter().map(|b| b.0).collect(),
+            |mbc| mbc.ram().to_vec(),
+        );
+        let regions = [self.wram.len(), eram.len(), self.hram.len()];
+        let wram = self.wram.iter().map(|b| b.0);
+        let hram = self.hram.iter().map(|b| b.0);
+        (wram.chain(eram).chain(hram).collect(), regions)
+    }
+
+    // Every location is a candidate to begin with
+    pub fn start_search(&mut self, size: SearchSize) -> usize {
+        let (snapshot, regions) = self.search_snapshot();
+        let search = RamSearch::new(size, snapshot, regions);
+        let len = search.len();
+        self.search = Some(search);
+        len
+    }
+
+    pub fn filter_search(&mut self, filter: SearchFilter) -> Option<usize> {
+        let (snapshot, _) = self.search_snapshot();
+        self.search.as_mut().map(|s| s.filter(snapshot, filter))
+    }
+
+    pub fn search_results(&self) -> Vec<SearchResult> {
+        let Some(search) = &self.search else {
+            return vec![];
+        };
+        let (snapshot, _) = self.search_snapshot();
+        search
+            .candidates
+            .iter()
+            .map(|&offset| {
+                let (bank, address) = search.location(offset);
+                SearchResult {
+                    bank,
+                    address,
+                    value: search.value(&snapshot, offset),
+                    previous: search.value(&search.snapshot, offset),
+                }
+            })
+            .collect()
+    }
+
+    pub(crate) fn list_search(&self) {
+        let Some(search) = &self.search else {
+            println!("No search started");
+            return;
+        };
+        let results = self.search_results();
+        for r in results.iter().take(LIST_LIMIT) {
+            match search.size {
+                SearchSize::Byte => println!(
+                    "{:02X}:{:04X} = {} (was {})",
+                    r.bank,
+                    r.address.0,
+                    Byte(r.value as u8),
+                    Byte(r.previous as u8)
+                ),
+                SearchSize::Word => println!(
+                    "{:02X}:{:04X} = 0x{:04X} (was 0x{:04X})",
+                    r.bank, r.address.0, r.value, r.previous
+                ),
+            }
+        }
+        if results.len() > LIST_LIMIT {
+            println!("... {} more", results.len() - LIST_LIMIT);
+        }
+        println!("{} candidates", results.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SearchFilter, SearchSize};
+    use crate::{
+        constants::ROM_BANK_SIZE,
+        types::{HeaderFix, MemoryBankControllerType},
+        Address, Byte, Device,
+    };
+
+    #[test]
+    fn test_byte_search() {
+        let mut d = Device::new();
+        d.poke(Address(0xC100), Byte(3));
+        d.poke(Address(0xFF90), Byte(3));
+        let all = d.start_search(SearchSize::Byte);
+        assert_eq!(all, d.wram.len() + d.eram.len() + d.hram.len());
+
+        assert_eq!(d.filter_search(SearchFilter::Equal(Some(3))), Some(2));
+        d.poke(Address(0xC100), Byte(2));
+        assert_eq!(d.filter_search(SearchFilter::ChangedBy(-1)), Some(1));
+        let results = d.search_results();
+        assert_eq!(results[0].address, Address(0xC100));
+        assert_eq!(results[0].value, 2);
+        assert_eq!(results[0].previous, 2);
+    }
+
+    #[test]
+    fn test_word_search() {
+        let mut d = Device::new();
+        d.write(Address(0xFF70), Byte(2));
+        d.poke(Address(0xD010), Byte(0xE8));
+        d.poke(Address(0xD011), Byte(0x03));
+        d.start_search(SearchSize::Word);
+        assert_eq!(d.filter_search(SearchFilter::Equal(Some(1000))), Some(1));
+        d.poke(Address(0xD011), Byte(0x04));
+        assert_eq!(d.filter_search(SearchFilter::Greater(None)), Some(1));
+        let results = d.search_results();
+        assert_eq!((results[0].bank, results[0].address), (2, Address(0xD010)));
+        assert_eq!(results[0].value, 0x04E8);
+
+        // The last byte of a region can't start a word, there's no cartridge RAM without a cartridge
+        d.start_search(SearchSize::Word);
+        assert_eq!(
+            d.filter_search(SearchFilter::Equal(None)),
+            Some(d.wram.len() + d.hram.len() - 2)
+        );
+    }
+
+    #[test]
+    fn test_search_ends_on_load() {
+        let mut rom = vec![0; ROM_BANK_SIZE * 2];
+        HeaderFix {
+            mapper: Some(MemoryBankControllerType::MBC1_RAM),
+            ram_size: Some(0x03),
+            ..HeaderFix::default()
+        }
+        .apply(&mut rom)
+        .unwrap();
+        let mut d = Device::new();
+        d.load_rom(&rom).unwrap();
+        d.start_search(SearchSize::Byte);
+
+        // The next cartridge has no RAM, so the old candidates would index past the snapshot
+        HeaderFix {
+            mapper: Some(MemoryBankControllerType::ROM_ONLY),
+            ram_size: Some(0x00),
+            ..HeaderFix::default()
+        }
+        .apply(&mut rom)
+        .unwrap();
+        d.load_rom(&rom).unwrap();
+        assert_eq!(d.filter_search(SearchFilter::Equal(None)), None);
+        assert!(d.search_results().is_empty());
+    }
+}
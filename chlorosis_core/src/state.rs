@@ -0,0 +1,278 @@
+use std::io::{self, ErrorKind};
+
+use crate::{
+    device::Device, Byte, CentralProcessor, Error, Infrared, Joypad, PixelProcessor, Timer,
+};
+
+// Binary save states, each component writes its fields in declaration order through `Snapshot`
+// Everything is little endian with no padding, so consecutive states diff well
+// The cartridge mapper's registers and RAM come last, they are the only part loaded in place
+
+const MAGIC: &[u8; 4] = b"CHLS";
+const VERSION: u8 = 2;
+
+pub trait Snapshot {
+    fn save(&self, w: &mut StateWriter);
+    fn load(&mut self, r: &mut StateReader) -> Result<(), io::Error>;
+}
+
+#[derive(Debug, Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn u8(&mut self, v: u8) {
+        self.data.push(v);
+    }
+
+    pub fn bool(&mut self, v: bool) {
+        self.data.push(v.into());
+    }
+
+    pub fn u16(&mut self, v: u16) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, v: u64) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn byte(&mut self, v: Byte) {
+        self.data.push(v.0);
+    }
+
+    pub fn bytes(&mut self, v: &[Byte]) {
+        self.data.extend(v.iter().map(|b| b.0));
+    }
+
+    pub fn raw(&mut self, v: &[u8]) {
+        self.data.extend_from_slice(v);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+#[derive(Debug)]
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub const fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], io::Error> {
+        let Some((head, tail)) = self.data.split_first_chunk::<N>() else {
+            return Err(ErrorKind::UnexpectedEof.into());
+        };
+        self.data = tail;
+        Ok(*head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, io::Error> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, io::Error> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, io::Error> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, io::Error> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, io::Error> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    pub fn byte(&mut self) -> Result<Byte, io::Error> {
+        Ok(Byte(self.u8()?))
+    }
+
+    pub fn raw(&mut self, len: usize) -> Result<&'a [u8], io::Error> {
+        if self.data.len() < len {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub fn bytes(&mut self, out: &mut [Byte]) -> Result<(), io::Error> {
+        let data = self.raw(out.len())?;
+        for (o, b) in out.iter_mut().zip(data) {
+            *o = Byte(*b);
+        }
+        Ok(())
+    }
+}
+
+impl Device {
+    // The cartridge ROM itself is not saved, only which ROM the state belongs to
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::default();
+        w.raw(MAGIC);
+        w.u8(VERSION);
+        w.u16(self.rom_checksum());
+
+        self.cpu.save(&mut w);
+        self.ppu.save(&mut w);
+        self.joypad.save(&mut w);
+        self.timer.save(&mut w);
+        self.infrared.save(&mut w);
+        w.bytes(&self.wram);
+        w.bytes(&self.eram);
+        w.bytes(&self.hram);
+        w.byte(self.interrupt);
+        w.u32(self.rom_bank as u32);
+        w.byte(self.wram_bank);
+        w.u64(self.cycles);
+        if let Some(mbc) = &self.mbc {
+            mbc.save(&mut w);
+        }
+        w.finish()
+    }
+
+    // The device is left untouched unless the whole state is valid
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Error> {
+        let mut r = StateReader::new(data);
+        if &r.take::<4>()? != MAGIC {
+            return Err(io::Error::new(ErrorKind::InvalidData, "Not a save state").into());
+        }
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Unsupported save state version {version}"),
+            )
+            .into());
+        }
+        if r.u16()? != self.rom_checksum() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "Save state belongs to a different ROM",
+            )
+            .into());
+        }
+
+        let mut cpu = CentralProcessor::default();
+        let mut ppu = PixelProcessor::default();
+        let mut joypad = Joypad::default();
+        let mut timer = Timer::default();
+        let mut infrared = Infrared::default();
+        let mut wram = vec![Byte(0); self.wram.len()];
+        let mut eram = vec![Byte(0); self.eram.len()];
+        let mut hram = vec![Byte(0); self.hram.len()];
+        cpu.load(&mut r)?;
+        ppu.load(&mut r)?;
+        joypad.load(&mut r)?;
+        timer.load(&mut r)?;
+        infrared.load(&mut r)?;
+        r.bytes(&mut wram)?;
+        r.bytes(&mut eram)?;
+        r.bytes(&mut hram)?;
+        let interrupt = r.byte()?;
+        let rom_bank = r.u32()? as usize;
+        let wram_bank = r.byte()?;
+        let cycles = r.u64()?;
+
+        // Put the mapper back the way it was if its part of the state is cut short
+        if let Some(mbc) = &mut self.mbc {
+            let mut backup = StateWriter::default();
+            mbc.save(&mut backup);
+            if let Err(e) = mbc.load(&mut r) {
+                mbc.load(&mut StateReader::new(&backup.finish()))?;
+                return Err(e.into());
+            }
+        }
+
+        self.cpu = cpu;
+        self.ppu = ppu;
+        self.joypad = joypad;
+        self.timer = timer;
+        self.infrared = infrared;
+        self.wram = wram;
+        self.eram = eram;
+        self.hram = hram;
+        self.interrupt = interrupt;
+        self.rom_bank = rom_bank;
+        self.wram_bank = wram_bank;
+        self.cycles = cycles;
+        // Frames from before the load don't describe the restored stack
+        self.call_stack.clear();
+        self.update_rumble();
+        self.movie_restored();
+        Ok(())
+    }
+
+    // Global checksum from the cartridge header
+    pub(crate) fn rom_checksum(&self) -> u16 {
+        ((self.rom[0x014E].0 as u16) << 8) | self.rom[0x014F].0 as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Address, Byte, Device};
+
+    #[test]
+    fn test_round_trip() {
+        let mut d = Device::new();
+        d.cpu.a = Byte(0x42);
+        d.cpu.pc = Address(0xC123);
+        d.poke(Address(0xC000), Byte(0x99));
+        d.poke(Address(0x8000), Byte(0x77));
+        d.poke(Address(0xFF42), Byte(0x10));
+        let state = d.save_state();
+
+        let mut restored = Device::new();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.cpu.a, Byte(0x42));
+        assert_eq!(restored.cpu.pc, Address(0xC123));
+        assert_eq!(restored.peek(Address(0xC000)), Byte(0x99));
+        assert_eq!(restored.peek(Address(0x8000)), Byte(0x77));
+        assert_eq!(restored.peek(Address(0xFF42)), Byte(0x10));
+        assert_eq!(restored.save_state(), state);
+    }
+
+    #[test]
+    fn test_rejects_bad_state() {
+        let mut d = Device::new();
+        let state = d.save_state();
+        assert!(d.load_state(&state[..state.len() - 1]).is_err());
+        assert!(d.load_state(b"nope").is_err());
+
+        d.cpu.a = Byte(1);
+        let mut other = state.clone();
+        other[5] ^= 0xFF; // ROM checksum
+        assert!(d.load_state(&other).is_err());
+        assert_eq!(d.cpu.a, Byte(1));
+    }
+
+    #[test]
+    fn test_clears_call_stack() {
+        let mut d = Device::new();
+        for (i, b) in [0xCD, 0x10, 0xC0].iter().enumerate() {
+            d.poke(Address(0xC000 + i as u16), Byte(*b));
+        }
+        d.cpu.pc = Address(0xC000);
+        let state = d.save_state();
+        d.step_cpu();
+        assert_eq!(d.call_stack().len(), 1);
+
+        d.load_state(&state).unwrap();
+        assert!(d.call_stack().is_empty());
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    path::Path,
+};
+
+use crate::{device::Device, Address};
+
+// Symbol files as written by RGBDS, wla-dx and no$gmb, one `bank:address label` per line
+// e.g. `01:4000 GameLoop`, with `;` comments and `[section]` headers ignored
+// RGBDS map files work too, they list each bank as `ROMX bank #1:` followed by its sections
+// and `$4000 = GameLoop` lines
+
+// Labels never describe an address across one of these boundaries
+const REGIONS: [u16; 11] = [
+    0x0000, 0x4000, 0x8000, 0xA000, 0xC000, 0xD000, 0xE000, 0xFE00, 0xFF00, 0xFF80, 0xFFFF,
+];
+
+#[derive(Debug, Default, Clone)]
+pub struct SymbolTable {
+    symbols: BTreeMap<(usize, u16), String>,
+    names: HashMap<String, (usize, u16)>,
+}
+
+impl SymbolTable {
+    pub fn parse(text: &str) -> Self {
+        let mut table = Self::default();
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.starts_with('[') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let (Some(location), Some(name)) = (parts.next(), parts.next()) else {
+                continue;
+            };
+            let Some((bank, address)) = location.split_once(':') else {
+                continue;
+            };
+            if let (Ok(bank), Ok(address)) = (
+                usize::from_str_radix(bank, 16),
+                u16::from_str_radix(address, 16),
+            ) {
+                table.insert(bank, address, name);
+            }
+        }
+        table
+    }
+
+    pub fn parse_map(text: &str) -> Self {
+        let mut table = Self::default();
+        let mut bank = 0;
+        for line in text.lines() {
+            let line = line.trim();
+            if let Some(area) = line.strip_suffix(':') {
+                bank = area
+                    .split_once(" bank #")
+                    .and_then(|(_, b)| b.parse().ok())
+                    .unwrap_or(0);
+                continue;
+            }
+            let Some((address, name)) = line.split_once(" = ") else {
+                continue;
+            };
+            let address = address.trim().strip_prefix('$').unwrap_or(address);
+            if let Ok(address) = u16::from_str_radix(address, 16) {
+                table.insert(bank, address, name.trim());
+            }
+        }
+        table
+    }
+
+    // Map files are told apart by their extension
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, io::Error> {
+        let text = std::fs::read_to_string(path.as_ref())?;
+        Ok(match path.as_ref().extension() {
+            Some(e) if e.eq_ignore_ascii_case("map") => Self::parse_map(&text),
+            _ => Self::parse(&text),
+        })
+    }
+
+    // The first label at an address is the one shown, all of them can be resolved
+    pub fn insert(&mut self, bank: usize, address: u16, name: &str) {
+        self.symbols
+            .entry((bank, address))
+            .or_insert_with(|| name.to_owned());
+        self.names.insert(name.to_owned(), (bank, address));
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<(usize, Address)> {
+        self.names.get(name).map(|&(bank, a)| (bank, Address(a)))
+    }
+
+    // Nearest label at or before the address in the same bank and memory region
+    pub fn lookup(&self, bank: usize, address: Address) -> Option<(&str, u16)> {
+        let start = REGIONS.iter().rev().find(|&&r| r <= address.0)?;
+        self.symbols
+            .range((bank, *start)..=(bank, address.0))
+            .next_back()
+            .map(|(&(_, a), name)| (name.as_str(), address.0 - a))
+    }
+
+    pub fn format(&self, bank: usize, address: Address) -> String {
+        match self.lookup(bank, address) {
+            Some((name, 0)) => name.to_owned(),
+            Some((name, offset)) => format!("{name}+${offset:X}"),
+            None => address.to_string(),
+        }
+    }
+
+    // Accepts `Label`, `Label+offset`, `bank:address` or a plain address, all in hex
+    pub fn resolve(&self, s: &str) -> Option<Address> {
+        self.resolve_banked(s).map(|(_, address)| address)
+    }
+
+    // Along with the bank the location is in, none for a plain address that means every bank
+    pub fn resolve_banked(&self, s: &str) -> Option<(Option<usize>, Address)> {
+        let s = s.trim();
+        // Labels win over addresses, `Cafe` may well be both
+        if let Some((bank, address)) = self.get(s) {
+            return Some((Some(bank), address));
+        }
+        if let Some((name, offset)) = s.split_once('+') {
+            let (bank, address) = self.get(name.trim())?;
+            let address = Address(address.0.wrapping_add(parse_address(offset)?.0));
+            return Some((Some(bank), address));
+        }
+        match s.split_once(':') {
+            Some((bank, address)) => Some((
+                Some(usize::from_str_radix(bank.trim(), 16).ok()?),
+                parse_address(address)?,
+            )),
+            None => parse_address(s).map(|address| (None, address)),
+        }
+    }
+}
+
+// Accepts 0x0150, $0150 or plain hex
+pub fn parse_address(s: &str) -> Option<Address> {
+    let s = s.trim();
+    let hex = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .or_else(|| s.strip_prefix('$'))
+        .unwrap_or(s);
+    u16::from_str_radix(hex, 16).ok().map(Address)
+}
+
+impl Device {
+    // Looks for a symbol file with the same stem as the ROM, e.g. game.gb and game.sym, falling
+    // back to the linker's map file
+    pub(crate) fn load_symbols_for(&mut self, rom: &Path) -> Result<(), io::Error> {
+        let path = ["sym", "map"]
+            .into_iter()
+            .map(|e| rom.with_extension(e))
+            .find(|p| p.exists());
+        self.symbols = match path {
+            Some(path) => SymbolTable::load(path)?,
+            None => SymbolTable::default(),
+        };
+        Ok(())
+    }
+
+    pub const fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    // Bank currently mapped at the address
+    pub fn bank_of(&self, address: Address) -> usize {
+        match address.0 {
+            0x4000..=0x7FFF => self.cartrige_bank(),
+            0x8000..=0x9FFF => self.ppu.vram_bank.0 as usize & 1,
+            0xA000..=0xBFFF => self.mbc.as_ref().map_or(0, |m| m.ram_bank()),
+            0xD000..=0xDFFF => self.wram_bank.0 as usize,
+            _ => 0,
+        }
+    }
+
+    pub fn symbolize(&self, address: Address) -> String {
+        self.symbols.format(self.bank_of(address), address)
+    }
+
+    pub fn resolve_location(&self, s: &str) -> Option<Address> {
+        self.symbols.resolve(s)
+    }
+
+    // Breakpoints on a plain address stop in every bank, ones on a label only in its own bank
+    pub(crate) fn at_breakpoint(&self) -> bool {
+        let pc = self.cpu.pc;
+        self.breakpoints.contains(&pc) || self.banked_breakpoints.contains(&(self.bank_of(pc), pc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SymbolTable;
+    use crate::{constants::ROM_BANK_SIZE, Address, Byte, Device};
+
+    const SYM: &str = "; File generated by rgblink
+[labels]
+00:0150 Main
+00:0160 Main.loop
+00:3ff0 Tail
+01:4000 GameLoop
+02:4000 Other
+00:c000 wFrameCounter
+";
+
+    #[test]
+    fn test_parse() {
+        let table = SymbolTable::parse(SYM);
+        assert_eq!(table.len(), 6);
+        assert_eq!(table.get("GameLoop"), Some((1, Address(0x4000))));
+        assert_eq!(table.get("Main.loop"), Some((0, Address(0x0160))));
+    }
+
+    const MAP: &str = "ROM0 bank #0:
+	SECTION: $0150-$01ff ($00b0 bytes) [\"Main\"]
+	         $0150 = Main
+	         $0160 = Main.loop
+	EMPTY: $0200-$3fff ($3e00 bytes)
+
+ROMX bank #1:
+	SECTION: $4000-$40ff ($0100 bytes) [\"Game\"]
+	         $4000 = GameLoop
+
+WRAMX bank #2:
+	SECTION: $d000-$d0ff ($0100 bytes) [\"State\"]
+	         $d010 = wScore
+";
+
+    #[test]
+    fn test_parse_map() {
+        let table = SymbolTable::parse_map(MAP);
+        assert_eq!(table.len(), 4);
+        assert_eq!(table.get("Main.loop"), Some((0, Address(0x0160))));
+        assert_eq!(table.get("GameLoop"), Some((1, Address(0x4000))));
+        assert_eq!(table.get("wScore"), Some((2, Address(0xD010))));
+
+        // Without a .sym the .map next to the ROM is used
+        let dir = std::env::temp_dir().join("chlorosis_map_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("game.map"), MAP).unwrap();
+        let mut d = Device::new();
+        d.load_symbols_for(&dir.join("game.gb")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(d.symbols().get("wScore"), Some((2, Address(0xD010))));
+    }
+
+    #[test]
+    fn test_format() {
+        let table = SymbolTable::parse(SYM);
+        assert_eq!(table.format(0, Address(0x0150)), "Main");
+        assert_eq!(table.format(0, Address(0x015A)), "Main+$A");
+        assert_eq!(table.format(2, Address(0x4010)), "Other+$10");
+        assert_eq!(table.format(3, Address(0x4010)), "0x4010");
+        // Tail must not leak into the switchable bank
+        assert_eq!(table.format(0, Address(0x0100)), "0x0100");
+        assert_eq!(table.format(0, Address(0x4000)), "0x4000");
+    }
+
+    #[test]
+    fn test_resolve() {
+        let table = SymbolTable::parse(SYM);
+        assert_eq!(table.resolve("Main"), Some(Address(0x0150)));
+        assert_eq!(table.resolve("Main+$1A"), Some(Address(0x016A)));
+        assert_eq!(table.resolve("wFrameCounter + 2"), Some(Address(0xC002)));
+        assert_eq!(table.resolve("$0200"), Some(Address(0x0200)));
+        assert_eq!(table.resolve("Missing"), None);
+
+        let mut table = SymbolTable::default();
+        table.insert(0, 0x0200, "Cafe");
+        assert_eq!(table.resolve("Cafe"), Some(Address(0x0200)));
+    }
+
+    #[test]
+    fn test_resolve_banked() {
+        let table = SymbolTable::parse(SYM);
+        assert_eq!(
+            table.resolve_banked("Other+4"),
+            Some((Some(2), Address(0x4004)))
+        );
+        assert_eq!(
+            table.resolve_banked("03:4123"),
+            Some((Some(3), Address(0x4123)))
+        );
+        assert_eq!(table.resolve_banked("4123"), Some((None, Address(0x4123))));
+    }
+
+    #[test]
+    fn test_banked_breakpoint() {
+        let mut d = Device::new();
+        d.rom = vec![Byte(0); ROM_BANK_SIZE * 4];
+        d.banked_breakpoints.insert((3, Address(0x4123)));
+        d.cpu.pc = Address(0x4123);
+        d.set_cartrige_bank(2);
+        assert!(!d.at_breakpoint());
+        d.set_cartrige_bank(3);
+        assert!(d.at_breakpoint());
+    }
+}
@@ -1,4 +1,9 @@
-use crate::types::{Address, Byte};
+use std::io;
+
+use crate::{
+    state::{Snapshot, StateReader, StateWriter},
+    types::{Address, Byte},
+};
 
 #[derive(Debug, Default)]
 pub struct Timer {
@@ -108,3 +113,20 @@ enum ClockSpeed {
     C64,
     C256,
 }
+
+impl Snapshot for Timer {
+    fn save(&self, w: &mut StateWriter) {
+        w.byte(self.divider);
+        w.byte(self.counter);
+        w.byte(self.modulo);
+        w.byte(self.read_control());
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        self.divider = r.byte()?;
+        self.counter = r.byte()?;
+        self.modulo = r.byte()?;
+        self.write_control(r.byte()?);
+        Ok(())
+    }
+}
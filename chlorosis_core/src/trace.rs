@@ -0,0 +1,151 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    ops::RangeInclusive,
+    path::PathBuf,
+};
+
+use crate::{Address, Byte, Device};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TraceFormat {
+    #[default]
+    Doctor, // gameboy-doctor: A F B C D E H L SP PC PCMEM
+    Verbose, // Doctor line followed by cycle count, ROM bank, IE, IF and LY
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceOptions {
+    pub path: PathBuf,
+    pub format: TraceFormat,
+    pub pc_range: Option<RangeInclusive<u16>>,
+    pub bank: Option<usize>,
+}
+
+impl TraceOptions {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            format: TraceFormat::default(),
+            pc_range: None,
+            bank: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Tracer {
+    writer: BufWriter<File>,
+    options: TraceOptions,
+}
+
+impl Tracer {
+    pub fn new(options: TraceOptions) -> Result<Self, io::Error> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(&options.path)?),
+            options,
+        })
+    }
+
+    // Bank is None when executing outside of ROM, so only matches an unset bank filter
+    fn matches(&self, pc: Address, bank: Option<usize>) -> bool {
+        self.options
+            .pc_range
+            .as_ref()
+            .is_none_or(|r| r.contains(&pc.0))
+            && self.options.bank.is_none_or(|b| Some(b) == bank)
+    }
+}
+
+impl Device {
+    pub fn start_trace(&mut self, options: TraceOptions) -> Result<(), io::Error> {
+        eprintln!("Tracing to {}", options.path.display());
+        self.tracer = Some(Tracer::new(options)?);
+        Ok(())
+    }
+
+    pub fn stop_trace(&mut self) {
+        if let Some(mut t) = self.tracer.take() {
+            if let Err(e) = t.writer.flush() {
+                eprintln!("Failed to flush trace: {e}");
+            }
+            eprintln!("Stopped tracing to {}", t.options.path.display());
+        }
+    }
+
+    fn pc_bank(&self) -> Option<usize> {
+        match self.cpu.pc.0 {
+            0x0000..=0x3FFF => Some(0),
+            0x4000..=0x7FFF => Some(self.cartrige_bank()),
+            _ => None,
+        }
+    }
+
+    // Called before each instruction is fetched
+    pub(crate) fn trace(&mut self) {
+        let pc = self.cpu.pc;
+        let bank = self.pc_bank();
+        let format = match &self.tracer {
+            Some(t) if t.matches(pc, bank) => t.options.format,
+            _ => return,
+        };
+
+        // Tracing must not fault or wrap past 0xFFFF, so memory is peeked rather than read
+        let pcmem: [Byte; 4] =
+            std::array::from_fn(|i| self.peek(Address(pc.0.wrapping_add(i as u16))));
+        let mut line = self.cpu.trace_state(pcmem);
+        if format == TraceFormat::Verbose {
+            let (ie, flags) = (self.peek(Address(0xFFFF)), self.peek(Address(0xFF0F)));
+            line += &format!(
+                " CY:{} BANK:{:02X} IE:{ie} IF:{flags} LY:{:02X}",
+                self.cycles,
+                bank.unwrap_or(0),
+                self.ppu.read_ly()
+            );
+            if self.symbols.lookup(self.bank_of(pc), pc).is_some() {
+                line += &format!(" SYM:{}", self.symbolize(pc));
+            }
+        }
+
+        if let Some(tracer) = &mut self.tracer {
+            if let Err(e) = writeln!(tracer.writer, "{line}") {
+                eprintln!("Failed to write trace: {e}");
+                self.tracer = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TraceFormat, TraceOptions, Tracer};
+    use crate::{Address, Device};
+
+    #[test]
+    fn test_trace_filter() {
+        let path = std::env::temp_dir().join("chlorosis_trace_filter.log");
+        let mut options = TraceOptions::new(&path);
+        options.pc_range = Some(0x0150..=0x3FFF);
+        options.bank = Some(0);
+        let t = Tracer::new(options).unwrap();
+        assert!(t.matches(Address(0x0150), Some(0)));
+        assert!(!t.matches(Address(0x0100), Some(0)));
+        assert!(!t.matches(Address(0x0150), None));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_trace_end_of_memory() {
+        let path = std::env::temp_dir().join("chlorosis_trace_end.log");
+        let mut options = TraceOptions::new(&path);
+        options.format = TraceFormat::Verbose;
+        let mut d = Device::new();
+        d.start_trace(options).unwrap();
+        d.cpu.pc = Address(0xFFFE);
+        d.trace();
+        d.stop_trace();
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(log.contains("PC:FFFE"), "{log}");
+    }
+}
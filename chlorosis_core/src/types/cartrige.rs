@@ -1,32 +1,88 @@
-use std::{fmt::Display, mem::transmute};
+use std::fmt::Display;
+
+use serde_json::{json, Value};
+
+// Checked by the boot ROM, which locks up unless it matches
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+const HEADER_END: usize = 0x14F;
 
-use crate::Byte;
-#[allow(dead_code)]
 #[derive(Debug)]
 struct CartrigeHeaderRaw {
-    title: [u8; 16],             // 0x134 - 0x143
-    manufacturer_code: [u8; 4],  // 0x13F - 0x142
-    cgb_flag: u8,                // 0x143 (0x80: backwards compatible, 0xC0: CBG only)
-    new_licensee_code: (u8, u8), // 0x144 - 145
-    sgb_flag: u8,                // 0x146
-    mbc_type: u8,                // 0x147 - Memory Bank Controller
-    rom_size: u8,                // 0x148 - 32KB < N
-    ram_size: u8,                // 0x149
-    destination: u8,             // 0x14A
-    old_licensee_code: u8,       // 0x14B
-    version_number: u8,          // 0x14C
-    header_checksum: u8,         // 0x14D - verified at boot
-    global_checksum: (u8, u8),   // 0x14E - 0x14F - not verified
+    logo: [u8; 48],             // 0x104 - 0x133
+    title: [u8; 16],            // 0x134 - 0x143
+    manufacturer_code: [u8; 4], // 0x13F - 0x142
+    cgb_flag: u8,               // 0x143 (0x80: backwards compatible, 0xC0: CBG only)
+    new_licensee_code: [u8; 2], // 0x144 - 145, two ASCII characters
+    sgb_flag: u8,               // 0x146
+    mbc_type: u8,               // 0x147 - Memory Bank Controller
+    rom_size: u8,               // 0x148 - 32KB < N
+    ram_size: u8,               // 0x149
+    destination: u8,            // 0x14A
+    old_licensee_code: u8,      // 0x14B
+    version_number: u8,         // 0x14C
+    header_checksum: u8,        // 0x14D - verified at boot
+    global_checksum: (u8, u8),  // 0x14E - 0x14F - not verified
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HeaderError {
+    TooShort(usize), // ROM length
+    BadLogo,
+    HeaderChecksum { expected: u8, actual: u8 },
+    GlobalChecksum { expected: u16, actual: u16 },
+    UnknownMapper(u8),
+    UnknownRomSize(u8),
+    UnknownRamSize(u8),
+    TooLong(usize), // ROM length, larger than any valid ROM size
+    TitleTooLong { len: usize, max: usize },
+}
+
+impl Display for HeaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooShort(len) => write!(f, "ROM is too short for a header, {len} bytes"),
+            Self::BadLogo => write!(f, "Nintendo logo does not match"),
+            Self::HeaderChecksum { expected, actual } => write!(
+                f,
+                "Header checksum is 0x{actual:02X}, the header says 0x{expected:02X}"
+            ),
+            Self::GlobalChecksum { expected, actual } => write!(
+                f,
+                "Global checksum is 0x{actual:04X}, the header says 0x{expected:04X}"
+            ),
+            Self::UnknownMapper(code) => write!(f, "Unknown cartridge type 0x{code:02X}"),
+            Self::UnknownRomSize(code) => write!(f, "Unknown ROM size 0x{code:02X}"),
+            Self::UnknownRamSize(code) => write!(f, "Unknown RAM size 0x{code:02X}"),
+            Self::TooLong(len) => write!(f, "ROM is too long for any ROM size, {len} bytes"),
+            Self::TitleTooLong { len, max } => {
+                write!(f, "Title is {len} bytes, at most {max} fit")
+            }
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+// How the size of the file compares with the size the header declares
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RomSizeCheck {
+    Matches,
+    Truncated(usize), // File length, a bad dump
+    Overdump(usize),  // File length, usually padding or a repeated image
 }
 
 // TODO - merge old and new licensee codes
-#[allow(dead_code)]
 #[derive(Debug)]
 pub struct CartrigeHeader {
     title: String,
+    manufacturer_code: Option<String>,
     cgb_flag: ColorMode,
     licensee_name: String,
-    licensee_code: u8,
+    licensee_code: String,
     sgb_flag: SgbSupport,
     mbc_type: MemoryBankControllerType,
     rom_size: u64,
@@ -36,101 +92,367 @@ pub struct CartrigeHeader {
     version_number: u8,
     header_checksum: u8,
     global_checksum: u16,
+    size_check: RomSizeCheck,
+    logo_valid: bool,
+    actual_header_checksum: u8,
 }
 
 impl CartrigeHeader {
-    pub fn from_bytes(slice: &[Byte]) -> Self {
-        CartrigeHeaderRaw::from_bytes(slice).into()
+    // Takes the whole ROM and checks that every code is known. What the boot ROM checks is only
+    // recorded, see `verify_boot`, so bad dumps can still be inspected
+    pub fn parse(rom: &[u8]) -> Result<Self, HeaderError> {
+        let raw = CartrigeHeaderRaw::from_bytes(rom)?;
+        let rom_size =
+            get_rom_size(raw.rom_size).ok_or(HeaderError::UnknownRomSize(raw.rom_size))?;
+        let size_check = match rom.len() as u64 {
+            len if len < rom_size => RomSizeCheck::Truncated(rom.len()),
+            len if len > rom_size => RomSizeCheck::Overdump(rom.len()),
+            _ => RomSizeCheck::Matches,
+        };
+        Ok(Self {
+            title: raw.get_title(),
+            manufacturer_code: raw.get_manufacturer_code(),
+            cgb_flag: raw.cgb_flag.into(),
+            licensee_name: raw.get_licensee_name(),
+            licensee_code: raw.get_licensee_code(),
+            sgb_flag: raw.sgb_flag.into(),
+            mbc_type: raw.mbc_type.try_into()?,
+            rom_size,
+            rom_banks: (rom_size / 0x4000) as u16,
+            ram_size: get_ram_size(raw.ram_size)
+                .ok_or(HeaderError::UnknownRamSize(raw.ram_size))?,
+            destination: raw.destination.into(),
+            version_number: raw.version_number,
+            header_checksum: raw.header_checksum,
+            global_checksum: u16::from_be_bytes([raw.global_checksum.0, raw.global_checksum.1]),
+            size_check,
+            logo_valid: raw.logo == NINTENDO_LOGO,
+            actual_header_checksum: header_checksum(rom),
+        })
+    }
+
+    // Real hardware locks up unless both the logo and the header checksum match
+    pub const fn verify_boot(&self) -> Result<(), HeaderError> {
+        if !self.logo_valid {
+            return Err(HeaderError::BadLogo);
+        }
+        if !self.header_checksum_valid() {
+            return Err(HeaderError::HeaderChecksum {
+                expected: self.header_checksum,
+                actual: self.actual_header_checksum,
+            });
+        }
+        Ok(())
+    }
+
+    pub const fn logo_valid(&self) -> bool {
+        self.logo_valid
+    }
+
+    pub const fn header_checksum_valid(&self) -> bool {
+        self.header_checksum == self.actual_header_checksum
+    }
+
+    // Real hardware never checks this and some homebrew and hacks get it wrong
+    pub fn verify_global_checksum(&self, rom: &[u8]) -> Result<(), HeaderError> {
+        let actual = global_checksum(rom);
+        if actual != self.global_checksum {
+            return Err(HeaderError::GlobalChecksum {
+                expected: self.global_checksum,
+                actual,
+            });
+        }
+        Ok(())
+    }
+
+    pub const fn size_check(&self) -> RomSizeCheck {
+        self.size_check
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn manufacturer_code(&self) -> Option<&str> {
+        self.manufacturer_code.as_deref()
+    }
+
+    pub const fn cgb_flag(&self) -> ColorMode {
+        self.cgb_flag
+    }
+
+    pub const fn sgb_flag(&self) -> SgbSupport {
+        self.sgb_flag
+    }
+
+    // Two characters for the new code, two hex digits for the old one
+    pub fn licensee_code(&self) -> &str {
+        &self.licensee_code
+    }
+
+    pub fn licensee_name(&self) -> &str {
+        &self.licensee_name
+    }
+
+    pub const fn mapper(&self) -> MemoryBankControllerType {
+        self.mbc_type
+    }
+
+    // In bytes
+    pub const fn rom_size(&self) -> u64 {
+        self.rom_size
+    }
+
+    pub const fn rom_banks(&self) -> u16 {
+        self.rom_banks
+    }
+
+    // In bytes
+    pub const fn ram_size(&self) -> u32 {
+        self.ram_size
+    }
+
+    pub const fn destination(&self) -> Destination {
+        self.destination
+    }
+
+    pub const fn version(&self) -> u8 {
+        self.version_number
+    }
+
+    pub const fn header_checksum(&self) -> u8 {
+        self.header_checksum
+    }
+
+    pub const fn global_checksum(&self) -> u16 {
+        self.global_checksum
     }
+
+    // Codes are kept as the header spells them, sizes in bytes
+    pub fn to_json(&self) -> Value {
+        json!({
+            "title": self.title,
+            "manufacturer_code": self.manufacturer_code,
+            "cgb_flag": format!("{:?}", self.cgb_flag),
+            "sgb_flag": format!("{:?}", self.sgb_flag),
+            "licensee_code": self.licensee_code,
+            "licensee_name": self.licensee_name,
+            "mapper": format!("{:?}", self.mbc_type),
+            "mapper_code": self.mbc_type as u8,
+            "rom_size": self.rom_size,
+            "rom_banks": self.rom_banks,
+            "ram_size": self.ram_size,
+            "destination": format!("{:?}", self.destination),
+            "version": self.version_number,
+            "header_checksum": self.header_checksum,
+            "global_checksum": self.global_checksum,
+            "logo_valid": self.logo_valid,
+            "header_checksum_valid": self.header_checksum_valid(),
+        })
+    }
+}
+
+// Header fields to overwrite, anything left as none keeps what the ROM has. The logo is always
+// put back and both checksums recomputed, so the result boots
+#[derive(Debug, Clone, Default)]
+pub struct HeaderFix {
+    pub title: Option<String>, // Overwrites the manufacturer code too
+    pub mapper: Option<MemoryBankControllerType>,
+    pub rom_size: Option<u8>, // Header codes, see `rom_size_code` and `ram_size_code`
+    pub ram_size: Option<u8>,
+    pub cgb_flag: Option<ColorMode>,
+    pub pad: Option<u8>, // Pads the ROM to the next valid size with this byte, setting the ROM size
+}
+
+impl HeaderFix {
+    pub fn apply(&self, rom: &mut Vec<u8>) -> Result<(), HeaderError> {
+        let mut raw = CartrigeHeaderRaw::from_bytes(rom)?;
+        raw.logo = NINTENDO_LOGO;
+        // The manufacturer code and CGB flag are the end of the title, so edit it and copy back
+        if let Some(cgb_flag) = self.cgb_flag {
+            raw.title[15] = cgb_flag.into();
+        }
+        if let Some(title) = &self.title {
+            let max = match ColorMode::from(raw.title[15]) {
+                ColorMode::Unknown => 16,
+                _ => 15,
+            };
+            if title.len() > max {
+                return Err(HeaderError::TitleTooLong {
+                    len: title.len(),
+                    max,
+                });
+            }
+            raw.title[..max].fill(0);
+            raw.title[..title.len()].copy_from_slice(title.as_bytes());
+        }
+        raw.manufacturer_code = array(&raw.title, 11);
+        raw.cgb_flag = raw.title[15];
+        if let Some(mapper) = self.mapper {
+            raw.mbc_type = mapper as u8;
+        }
+        // Everything is checked before the ROM is padded, a fix that fails leaves it untouched
+        if let Some(code) = self.rom_size {
+            get_rom_size(code).ok_or(HeaderError::UnknownRomSize(code))?;
+        }
+        if let Some(code) = self.ram_size {
+            get_ram_size(code).ok_or(HeaderError::UnknownRamSize(code))?;
+        }
+        let padded = match self.pad {
+            Some(pad) => Some((
+                pad,
+                (0..=8)
+                    .find(|&c| get_rom_size(c).is_some_and(|s| s >= rom.len() as u64))
+                    .ok_or(HeaderError::TooLong(rom.len()))?,
+            )),
+            None => None,
+        };
+
+        if let Some((pad, code)) = padded {
+            rom.resize(get_rom_size(code).unwrap_or_default() as usize, pad);
+            raw.rom_size = code;
+        }
+        if let Some(code) = self.rom_size {
+            raw.rom_size = code;
+        }
+        if let Some(code) = self.ram_size {
+            raw.ram_size = code;
+        }
+
+        raw.write(rom);
+        rom[0x14D] = header_checksum(rom);
+        let global = global_checksum(rom).to_be_bytes();
+        rom[0x14E..=0x14F].copy_from_slice(&global);
+        Ok(())
+    }
+}
+
+pub fn rom_size_code(size: u64) -> Option<u8> {
+    (0..=0xFF).find(|&c| get_rom_size(c) == Some(size))
+}
+
+pub fn ram_size_code(size: u32) -> Option<u8> {
+    (0..=0xFF).find(|&c| get_ram_size(c) == Some(size))
+}
+
+// Over 0x134 - 0x14C
+fn header_checksum(rom: &[u8]) -> u8 {
+    rom[0x134..=0x14C]
+        .iter()
+        .fold(0u8, |x, &b| x.wrapping_sub(b).wrapping_sub(1))
+}
+
+// Every byte of the ROM except the checksum itself
+fn global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|&(i, _)| i != 0x14E && i != 0x14F)
+        .fold(0u16, |sum, (_, &b)| sum.wrapping_add(b as u16))
+}
+
+fn array<const N: usize>(rom: &[u8], start: usize) -> [u8; N] {
+    let mut array = [0; N];
+    array.copy_from_slice(&rom[start..start + N]);
+    array
 }
 
-// TODO - remove transmute - impl from/to conversion
 impl CartrigeHeaderRaw {
-    fn from_bytes(slice: &[Byte]) -> Self {
-        Self {
-            title: slice
-                .iter()
-                .skip(0x33)
-                .take(16)
-                .map(|x| x.0)
-                .collect::<Vec<u8>>()
-                .try_into()
-                .expect("Should not overflow"),
-            manufacturer_code: slice
-                .iter()
-                .skip(0x3E)
-                .take(4)
-                .map(|x| x.0)
-                .collect::<Vec<u8>>()
-                .try_into()
-                .expect("Should not overflow"),
-            cgb_flag: slice[0x43].0,
-            new_licensee_code: (slice[0x44].0, slice[0x45].0),
-            sgb_flag: slice[0x46].0,
-            mbc_type: slice[0x47].0,
-            rom_size: slice[0x48].0,
-            ram_size: slice[0x49].0,
-            destination: slice[0x4A].0,
-            old_licensee_code: slice[0x4B].0,
-            version_number: slice[0x4C].0,
-            header_checksum: slice[0x4D].0,
-            global_checksum: (slice[0x4E].0, slice[0x4F].0),
+    fn from_bytes(rom: &[u8]) -> Result<Self, HeaderError> {
+        if rom.len() <= HEADER_END {
+            return Err(HeaderError::TooShort(rom.len()));
         }
+        Ok(Self {
+            logo: array(rom, 0x104),
+            title: array(rom, 0x134),
+            manufacturer_code: array(rom, 0x13F),
+            cgb_flag: rom[0x143],
+            new_licensee_code: array(rom, 0x144),
+            sgb_flag: rom[0x146],
+            mbc_type: rom[0x147],
+            rom_size: rom[0x148],
+            ram_size: rom[0x149],
+            destination: rom[0x14A],
+            old_licensee_code: rom[0x14B],
+            version_number: rom[0x14C],
+            header_checksum: rom[0x14D],
+            global_checksum: (rom[0x14E], rom[0x14F]),
+        })
+    }
+
+    // The title is written first, so the CGB flag and manufacturer code win where they overlap
+    fn write(&self, rom: &mut [u8]) {
+        rom[0x104..0x134].copy_from_slice(&self.logo);
+        rom[0x134..0x144].copy_from_slice(&self.title);
+        rom[0x13F..0x143].copy_from_slice(&self.manufacturer_code);
+        rom[0x143] = self.cgb_flag;
+        rom[0x144..0x146].copy_from_slice(&self.new_licensee_code);
+        rom[0x146] = self.sgb_flag;
+        rom[0x147] = self.mbc_type;
+        rom[0x148] = self.rom_size;
+        rom[0x149] = self.ram_size;
+        rom[0x14A] = self.destination;
+        rom[0x14B] = self.old_licensee_code;
+        rom[0x14C] = self.version_number;
+        rom[0x14D] = self.header_checksum;
+        rom[0x14E] = self.global_checksum.0;
+        rom[0x14F] = self.global_checksum.1;
+    }
+
+    // Colour games use the end of the title for the CGB flag, padding is NUL
+    fn get_title(&self) -> String {
+        let len = match ColorMode::from(self.cgb_flag) {
+            ColorMode::Unknown => 16,
+            _ => 15,
+        };
+        let title = &self.title[..len];
+        let end = title.iter().position(|&c| c == 0).unwrap_or(len);
+        String::from_utf8_lossy(&title[..end]).into_owned()
     }
 
-    const fn get_licensee_code(&self) -> u8 {
+    // Only later colour games have one, it takes the end of the title
+    fn get_manufacturer_code(&self) -> Option<String> {
+        let code = &self.manufacturer_code;
+        let is_code = ColorMode::from(self.cgb_flag) != ColorMode::Unknown
+            && code
+                .iter()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+        is_code.then(|| String::from_utf8_lossy(code).into_owned())
+    }
+
+    fn get_licensee_code(&self) -> String {
         if self.old_licensee_code == 0x33 {
-            merge_nibbles(self.new_licensee_code.0, self.new_licensee_code.1)
+            String::from_utf8_lossy(&self.new_licensee_code).into_owned()
         } else {
-            self.old_licensee_code
+            format!("{:02X}", self.old_licensee_code)
         }
     }
 
     fn get_licensee_name(&self) -> String {
         if self.old_licensee_code == 0x33 {
-            NewLicenseeCode::from(merge_nibbles(
-                self.new_licensee_code.0,
-                self.new_licensee_code.1,
-            ))
-            .to_string()
+            NewLicenseeCode::from(self.new_licensee_code).to_string()
         } else {
             OldLicenseeCode::from(self.old_licensee_code).to_string()
         }
     }
 }
 
-const fn merge_nibbles(a: u8, b: u8) -> u8 {
-    (a << 4) + b
-}
-
-impl From<CartrigeHeaderRaw> for CartrigeHeader {
-    fn from(value: CartrigeHeaderRaw) -> Self {
-        Self {
-            title: String::from_utf8_lossy(&value.title).into_owned(),
-            cgb_flag: value.cgb_flag.into(),
-            licensee_name: value.get_licensee_name(),
-            licensee_code: value.get_licensee_code(),
-            sgb_flag: value.sgb_flag.into(),
-            mbc_type: unsafe { transmute(value.mbc_type) },
-            rom_size: get_rom_size(value.rom_size),
-            rom_banks: (get_rom_size(value.rom_size) / 0x4000) as u16,
-            ram_size: get_ram_size(value.ram_size),
-            destination: value.destination.into(),
-            version_number: value.version_number,
-            header_checksum: value.header_checksum,
-            global_checksum: ((value.global_checksum.0 as u16) << 8)
-                + value.global_checksum.1 as u16,
-        }
-    }
-}
-
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
-enum ColorMode {
+pub enum ColorMode {
     BackwardsCompat,
     ColorOnly,
     Unknown,
 }
 
+impl From<ColorMode> for u8 {
+    fn from(value: ColorMode) -> Self {
+        match value {
+            ColorMode::BackwardsCompat => 0x80,
+            ColorMode::ColorOnly => 0xC0,
+            ColorMode::Unknown => 0x00,
+        }
+    }
+}
+
 impl From<u8> for ColorMode {
     fn from(value: u8) -> Self {
         match value {
@@ -142,7 +464,7 @@ impl From<u8> for ColorMode {
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
-enum SgbSupport {
+pub enum SgbSupport {
     None,
     Supported,
     Unknown,
@@ -159,7 +481,7 @@ impl From<u8> for SgbSupport {
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
-enum Destination {
+pub enum Destination {
     Japan,
     NotJapan,
     Unknown,
@@ -241,6 +563,16 @@ enum NewLicenseeCode {
     Unknown,
 }
 
+// The two characters read as hex, which every listed code is
+impl From<[u8; 2]> for NewLicenseeCode {
+    fn from(value: [u8; 2]) -> Self {
+        std::str::from_utf8(&value)
+            .ok()
+            .and_then(|code| u8::from_str_radix(code, 16).ok())
+            .map_or(Self::Unknown, Self::from)
+    }
+}
+
 impl From<u8> for NewLicenseeCode {
     fn from(value: u8) -> Self {
         use NewLicenseeCode::*;
@@ -817,10 +1149,9 @@ impl Display for OldLicenseeCode {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
 #[allow(non_camel_case_types)]
-#[allow(dead_code)]
-enum MemoryBankControllerType {
+pub enum MemoryBankControllerType {
     ROM_ONLY = 0x00,
     MBC1 = 0x01,
     MBC1_RAM = 0x02,
@@ -851,47 +1182,319 @@ enum MemoryBankControllerType {
     HUC1_RAM_BATTERY = 0xFF,
 }
 
-const fn get_rom_size(code: u8) -> u64 {
+impl TryFrom<u8> for MemoryBankControllerType {
+    type Error = HeaderError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        use MemoryBankControllerType::*;
+        Ok(match value {
+            0x00 => ROM_ONLY,
+            0x01 => MBC1,
+            0x02 => MBC1_RAM,
+            0x03 => MBC1_RAM_BATTERY,
+            0x05 => MBC2,
+            0x06 => MBC2_BATTERY,
+            0x08 => ROM_RAM,
+            0x09 => ROM_RAM_BATTERY,
+            0x0B => MMM01,
+            0x0C => MMM01_RAM,
+            0x0D => MMM01_RAM_BATTERY,
+            0x0F => MBC3_TIMER_BATTERY,
+            0x10 => MBC3_TIMER_RAM_BATTERY,
+            0x11 => MBC3,
+            0x12 => MBC3_RAM,
+            0x13 => MBC3_RAM_BATTERY,
+            0x19 => MBC5,
+            0x1A => MBC5_RAM,
+            0x1B => MBC5_RAM_BATTERY,
+            0x1C => MBC5_RUMBLE,
+            0x1D => MBC5_RUMBLE_RAM,
+            0x1E => MBC5_RUMBLE_RAM_BATTERY,
+            0x20 => MBC6,
+            0x22 => MBC7_SENSOR_RUMBLE_RAM_BATTERY,
+            0xFC => POCKET_CAMERA,
+            0xFD => BANDAI_TAMA5,
+            0xFE => HUC3,
+            0xFF => HUC1_RAM_BATTERY,
+            code => return Err(HeaderError::UnknownMapper(code)),
+        })
+    }
+}
+
+impl MemoryBankControllerType {
+    pub const fn has_battery(self) -> bool {
+        use MemoryBankControllerType::*;
+        matches!(
+            self,
+            MBC1_RAM_BATTERY
+                | MBC2_BATTERY
+                | ROM_RAM_BATTERY
+                | MMM01_RAM_BATTERY
+                | MBC3_TIMER_BATTERY
+                | MBC3_TIMER_RAM_BATTERY
+                | MBC3_RAM_BATTERY
+                | MBC5_RAM_BATTERY
+                | MBC5_RUMBLE_RAM_BATTERY
+                | MBC7_SENSOR_RUMBLE_RAM_BATTERY
+                | HUC1_RAM_BATTERY
+        )
+    }
+}
+
+const fn get_rom_size(code: u8) -> Option<u64> {
     match code {
-        0x00..=0x08 => 0x8000 << (code as u64),
-        0x52 => 0x120000,
-        0x53 => 0x140000,
-        0x54 => 0x180000,
-        _ => unreachable!(),
+        0x00..=0x08 => Some(0x8000 << (code as u64)),
+        0x52 => Some(0x120000),
+        0x53 => Some(0x140000),
+        0x54 => Some(0x180000),
+        _ => None,
     }
 }
 
-const fn get_ram_size(code: u8) -> u32 {
+const fn get_ram_size(code: u8) -> Option<u32> {
     match code {
-        0 => 0x0,     // None
-        1 => 0x800,   // 2Kb
-        2 => 0x2000,  // 8KB
-        3 => 0x8000,  // 32KB
-        4 => 0x20000, // 128KB
-        5 => 0x10000, // 64KB
-        _ => unreachable!(),
+        0 => Some(0x0),     // None
+        1 => Some(0x800),   // 2Kb
+        2 => Some(0x2000),  // 8KB
+        3 => Some(0x8000),  // 32KB
+        4 => Some(0x20000), // 128KB
+        5 => Some(0x10000), // 64KB
+        _ => None,
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{types::cartrige::{get_rom_size, ColorMode, Destination}, Device};
+    use super::{
+        get_rom_size, header_checksum, ram_size_code, rom_size_code, CartrigeHeaderRaw, ColorMode,
+        Destination, HeaderError, HeaderFix, MemoryBankControllerType, RomSizeCheck, NINTENDO_LOGO,
+    };
+    use crate::Device;
+
+    // A 64KB MBC1 colour game from Nintendo, with both checksums correct
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x10000];
+        rom[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+        rom[0x134..0x139].copy_from_slice(b"TITLE");
+        rom[0x143] = 0xC0;
+        rom[0x144..0x146].copy_from_slice(b"01");
+        rom[0x147] = 0x01;
+        rom[0x148] = 0x01;
+        rom[0x14A] = 0x01;
+        rom[0x14B] = 0x33;
+        fix_checksums(&mut rom);
+        rom
+    }
+
+    fn fix_checksums(rom: &mut [u8]) {
+        rom[0x14D] = header_checksum(rom);
+        let sum = rom
+            .iter()
+            .fold(0u16, |s, &b| s.wrapping_add(b as u16))
+            .wrapping_sub(rom[0x14E] as u16 + rom[0x14F] as u16);
+        rom[0x14E..0x150].copy_from_slice(&sum.to_be_bytes());
+    }
 
     #[test]
     fn test_rom_size() {
-        assert_eq!(get_rom_size(0x00), 0x8000);
-        assert_eq!(get_rom_size(0x01), 0x10000);
-        assert_eq!(get_rom_size(0x05), 0x100000);
-        assert_eq!(get_rom_size(0x54), 0x180000);
+        assert_eq!(get_rom_size(0x00), Some(0x8000));
+        assert_eq!(get_rom_size(0x01), Some(0x10000));
+        assert_eq!(get_rom_size(0x05), Some(0x100000));
+        assert_eq!(get_rom_size(0x54), Some(0x180000));
+        assert_eq!(get_rom_size(0x09), None);
     }
 
     #[test]
     fn read_header() {
         let mut d = Device::new();
-        d.load_cartrige("../roms/smbd.gbc").unwrap();
+        d.load_rom(&rom()).unwrap();
         let c = d.get_cartridge_header().unwrap();
+        assert_eq!(c.title, "TITLE");
         assert_eq!(c.cgb_flag, ColorMode::ColorOnly);
-        assert_eq!(c.destination, Destination::NotJapan);    
-        assert_eq!(c.licensee_code, 0x31);
+        assert_eq!(c.destination, Destination::NotJapan);
+        assert_eq!(c.licensee_code, "01");
+        assert_eq!(c.licensee_name, "Nintendo R&D1");
+        assert!(matches!(c.mbc_type, MemoryBankControllerType::MBC1));
+        assert_eq!(c.rom_banks, 4);
+        assert_eq!(c.size_check(), RomSizeCheck::Matches);
+        assert_eq!(c.verify_global_checksum(&rom()), Ok(()));
+    }
+
+    #[test]
+    fn test_header_errors() {
+        use super::CartrigeHeader as Header;
+
+        assert_eq!(
+            Header::parse(&[0; 0x100]).unwrap_err(),
+            HeaderError::TooShort(0x100)
+        );
+
+        let mut bad = rom();
+        bad[0x110] ^= 1;
+        let header = Header::parse(&bad).unwrap();
+        assert!(!header.logo_valid());
+        assert_eq!(header.verify_boot(), Err(HeaderError::BadLogo));
+        assert!(Device::new().load_rom(&bad).is_err());
+
+        let mut bad = rom();
+        bad[0x14D] ^= 1;
+        let header = Header::parse(&bad).unwrap();
+        assert_eq!(
+            (header.logo_valid(), header.header_checksum_valid()),
+            (true, false)
+        );
+        assert!(matches!(
+            header.verify_boot(),
+            Err(HeaderError::HeaderChecksum { .. })
+        ));
+        assert!(Device::new().load_rom(&bad).is_err());
+
+        let mut bad = rom();
+        bad[0x147] = 0x04;
+        fix_checksums(&mut bad);
+        assert_eq!(
+            Header::parse(&bad).unwrap_err(),
+            HeaderError::UnknownMapper(0x04)
+        );
+
+        let mut bad = rom();
+        bad[0x148] = 0x30;
+        fix_checksums(&mut bad);
+        assert_eq!(
+            Header::parse(&bad).unwrap_err(),
+            HeaderError::UnknownRomSize(0x30)
+        );
+
+        // Only checked on request
+        let mut bad = rom();
+        bad[0x8000] = 1;
+        let header = Header::parse(&bad).unwrap();
+        assert_eq!(
+            header.verify_global_checksum(&bad),
+            Err(HeaderError::GlobalChecksum {
+                expected: header.global_checksum,
+                actual: header.global_checksum.wrapping_add(1)
+            })
+        );
+    }
+
+    #[test]
+    fn test_size_check() {
+        use super::CartrigeHeader as Header;
+
+        let rom = rom();
+        let mut overdump = rom.clone();
+        overdump.extend_from_slice(&rom);
+        assert_eq!(
+            Header::parse(&overdump).unwrap().size_check(),
+            RomSizeCheck::Overdump(0x20000)
+        );
+        assert_eq!(
+            Header::parse(&rom[..0x8000]).unwrap().size_check(),
+            RomSizeCheck::Truncated(0x8000)
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        use super::CartrigeHeader as Header;
+
+        let mut rom = rom();
+        rom[0x13F..0x143].copy_from_slice(b"ATLE");
+        rom[0x14C] = 2;
+        fix_checksums(&mut rom);
+        let header = Header::parse(&rom).unwrap();
+        assert_eq!(header.manufacturer_code(), Some("ATLE"));
+
+        let json = header.to_json();
+        assert_eq!(json["title"], "TITLE");
+        assert_eq!(json["manufacturer_code"], "ATLE");
+        assert_eq!(json["cgb_flag"], "ColorOnly");
+        assert_eq!(json["licensee_name"], "Nintendo R&D1");
+        assert_eq!(json["mapper"], "MBC1");
+        assert_eq!(json["mapper_code"], 1);
+        assert_eq!(json["rom_size"], 0x10000);
+        assert_eq!(json["version"], 2);
+        assert_eq!(json["global_checksum"], header.global_checksum());
+
+        // Without the CGB flag the whole 16 bytes are title
+        let mut rom = rom.clone();
+        rom[0x143] = 0;
+        fix_checksums(&mut rom);
+        assert_eq!(Header::parse(&rom).unwrap().manufacturer_code(), None);
+    }
+
+    #[test]
+    fn test_raw_round_trip() {
+        let rom = rom();
+        let mut written = vec![0; rom.len()];
+        CartrigeHeaderRaw::from_bytes(&rom)
+            .unwrap()
+            .write(&mut written);
+        assert_eq!(written[0x104..0x150], rom[0x104..0x150]);
+    }
+
+    #[test]
+    fn test_header_fix() {
+        use super::CartrigeHeader as Header;
+
+        // A linker output with no logo, sizes or checksums
+        let mut rom = vec![0xFF; 0x5000];
+        rom[0x104..0x150].fill(0);
+        let fix = HeaderFix {
+            title: Some("HOMEBREW".to_owned()),
+            mapper: Some(MemoryBankControllerType::MBC5_RAM_BATTERY),
+            ram_size: ram_size_code(0x8000),
+            cgb_flag: Some(ColorMode::BackwardsCompat),
+            pad: Some(0xFF),
+            ..Default::default()
+        };
+        fix.apply(&mut rom).unwrap();
+        assert_eq!(rom.len(), 0x8000);
+
+        let header = Header::parse(&rom).unwrap();
+        assert_eq!(header.title(), "HOMEBREW");
+        assert_eq!(header.mapper(), MemoryBankControllerType::MBC5_RAM_BATTERY);
+        assert_eq!(header.rom_size(), 0x8000);
+        assert_eq!(header.ram_size(), 0x8000);
+        assert_eq!(header.cgb_flag(), ColorMode::BackwardsCompat);
+        assert_eq!(header.size_check(), RomSizeCheck::Matches);
+        assert_eq!(header.verify_global_checksum(&rom), Ok(()));
+
+        // Fixing again changes nothing, without the CGB flag all 16 bytes are title
+        let before = rom.clone();
+        HeaderFix::default().apply(&mut rom).unwrap();
+        assert_eq!(rom, before);
+        let fix = HeaderFix {
+            title: Some("SIXTEEN CHARS OK".to_owned()),
+            cgb_flag: Some(ColorMode::Unknown),
+            rom_size: rom_size_code(0x8000),
+            ..Default::default()
+        };
+        fix.apply(&mut rom).unwrap();
+        assert_eq!(Header::parse(&rom).unwrap().title(), "SIXTEEN CHARS OK");
+
+        let fix = HeaderFix {
+            title: Some("SIXTEEN CHARS OK".to_owned()),
+            cgb_flag: Some(ColorMode::ColorOnly),
+            ..Default::default()
+        };
+        assert_eq!(
+            fix.apply(&mut rom),
+            Err(HeaderError::TitleTooLong { len: 16, max: 15 })
+        );
+
+        // A bad size code is refused before the ROM is padded
+        let mut short = vec![0; 0x5000];
+        let fix = HeaderFix {
+            ram_size: Some(0x42),
+            pad: Some(0xFF),
+            ..Default::default()
+        };
+        assert_eq!(
+            fix.apply(&mut short),
+            Err(HeaderError::UnknownRamSize(0x42))
+        );
+        assert_eq!(short, vec![0; 0x5000]);
     }
 }
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 // MEMORY ADDRESS POSITION CONSTANTS
 // pub const BOOT_ROM_START: u16 = 0x0000;
 // pub const BOOT_ROM_END: u16 = 0x00FF;
@@ -31,8 +33,9 @@ pub const WRAM_SIZE: usize = 0x8000; // 32 KB
 pub const WRAM_BANK_SIZE: usize = 0x1000; // 4 KB
 pub const VRAM_SIZE: usize = 0x4000; // 16 KB
 pub const VRAM_BANK_SIZE: usize = 0x2000; // 8 KB
-pub const ERAM_SIZE: usize = 0x2000; // 8 KB - TODO: ERAM mapper
+pub const ERAM_BANK_SIZE: usize = 0x2000; // 8 KB
 pub const ROM_BANK_SIZE: usize = 0x4000; // 16 KB
+pub const MAX_ROM_SIZE: usize = 0x800000; // 8 MB, the largest a header can describe
 pub const OAM_SIZE: usize = 0xA0; // 160
 pub const HRAM_SIZE: usize = 0x7F; // 127
                                    // pub const IO_SIZE: usize = 0x80; // 128
@@ -62,3 +65,7 @@ bitflags::bitflags! {
 
 // Pixel Processing Unit Constants
 pub const TILE_SIZE: usize = 16;
+pub const DOTS_PER_FRAME: u64 = 70224; // 154 lines of 456 dots
+pub const CLOCK_SPEED: u64 = 4_194_304; // Dots per second
+pub const FRAME_DURATION: Duration = // Roughly 59.73 Hz
+    Duration::from_nanos(DOTS_PER_FRAME * 1_000_000_000 / CLOCK_SPEED);
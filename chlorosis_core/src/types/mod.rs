@@ -5,5 +5,8 @@ pub mod constants;
 mod signed_byte;
 pub use address::Address;
 pub use byte::Byte;
-pub use cartrige::CartrigeHeader;
+pub use cartrige::{
+    ram_size_code, rom_size_code, CartrigeHeader, ColorMode, Destination, HeaderError, HeaderFix,
+    MemoryBankControllerType, RomSizeCheck, SgbSupport, NINTENDO_LOGO,
+};
 pub use signed_byte::SignedByte;
@@ -0,0 +1,96 @@
+use std::{io::BufRead, sync::mpsc::Sender};
+
+use chlorosis_core::{Event, SearchFilter, SearchSize};
+
+const HELP: &str = "Commands:
+  cheat add <code>     Game Genie, GameShark or bank:address=value
+  cheat remove <code>
+  cheat list
+  cheat load <file>
+  search start [8|16]  Snapshot RAM, every location is a candidate
+  search eq|ne|gt|lt [value]
+                       Compare with the value, or the last snapshot without one
+  search changed <n>   Keep values that changed by n since the last snapshot
+  search list
+  help
+
+Values are decimal, or hex when prefixed with 0x or $";
+
+// Reads commands typed into the terminal, for things that have no place in the window
+pub fn spawn(sender: Sender<Event>) {
+    std::thread::Builder::new()
+        .name("Console".to_owned())
+        .spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    return;
+                };
+                match parse(&line) {
+                    Ok(Some(event)) => {
+                        if sender.send(event).is_err() {
+                            return;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => println!("{e}"),
+                }
+            }
+        })
+        .unwrap();
+}
+
+// First word and the rest of the line
+fn split(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    s.split_once(char::is_whitespace)
+        .map_or((s, ""), |(a, b)| (a, b.trim()))
+}
+
+fn parse(line: &str) -> Result<Option<Event>, String> {
+    let (command, rest) = split(line);
+    match (command, split(rest)) {
+        ("", _) => Ok(None),
+        ("cheat", ("add", code)) if !code.is_empty() => Ok(Some(Event::AddCheat(code.to_owned()))),
+        ("cheat", ("remove", code)) if !code.is_empty() => {
+            Ok(Some(Event::RemoveCheat(code.to_owned())))
+        }
+        ("cheat", ("list", "")) => Ok(Some(Event::ListCheats)),
+        ("cheat", ("load", path)) if !path.is_empty() => Ok(Some(Event::LoadCheats(path.into()))),
+        ("search", ("start", size)) => match size {
+            "" | "8" => Ok(Some(Event::StartSearch(SearchSize::Byte))),
+            "16" => Ok(Some(Event::StartSearch(SearchSize::Word))),
+            _ => Err(format!("Unknown search size {size}, expected 8 or 16")),
+        },
+        ("search", ("changed", n)) => n
+            .trim_start_matches('+')
+            .parse()
+            .map(|n| Some(Event::FilterSearch(SearchFilter::ChangedBy(n))))
+            .map_err(|e| format!("Invalid change {n}: {e}")),
+        ("search", ("list", "")) => Ok(Some(Event::ListSearch)),
+        ("search", (comparison @ ("eq" | "ne" | "gt" | "lt"), value)) => {
+            let value = (!value.is_empty())
+                .then(|| parse_value(value))
+                .transpose()?;
+            let filter = match comparison {
+                "eq" => SearchFilter::Equal(value),
+                "ne" => SearchFilter::NotEqual(value),
+                "gt" => SearchFilter::Greater(value),
+                _ => SearchFilter::Less(value),
+            };
+            Ok(Some(Event::FilterSearch(filter)))
+        }
+        ("help", _) => {
+            println!("{HELP}");
+            Ok(None)
+        }
+        _ => Err(format!("Unknown command {line}\n{HELP}")),
+    }
+}
+
+fn parse_value(s: &str) -> Result<u16, String> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix('$')) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|e| format!("Invalid value {s}: {e}"))
+}
@@ -4,8 +4,18 @@ use std::{
     time::Duration,
 };
 
-use chlorosis_core::{Device, Event, KeyCode};
+use chlorosis_core::{Device, DeviceState, Event, KeyCode, Speed};
 use minifb::{Key, Menu, Window, WindowOptions, MENU_KEY_CTRL};
+use options::{Dap, Options};
+use roms::RomMenus;
+use rumble::Rumble;
+use viewer::Viewers;
+
+mod console;
+mod options;
+mod roms;
+mod rumble;
+mod viewer;
 
 const WIDTH: usize = 160;
 const HEIGHT: usize = 144;
@@ -13,31 +23,143 @@ const BLACK: [u32; WIDTH * HEIGHT] = [0; WIDTH * HEIGHT];
 const GREY: [u32; WIDTH * HEIGHT] = [0x00555555; WIDTH * HEIGHT];
 
 fn main() {
+    let options = Options::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    });
+
     let mut dev = Device::default();
+
+    // The editor drives the device, so no window is opened
+    if let Some(dap) = &options.dap {
+        let result = match dap {
+            Dap::Stdio => dev.serve_dap(std::io::stdin(), std::io::stdout()),
+            Dap::Port(port) => dev.listen_dap(("127.0.0.1", *port)),
+        };
+        if let Err(e) = result {
+            eprintln!("DAP server failed: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let mut state = DebuggerState::Stopped;
+    let mut tracing = options.trace_on_start;
+    let mut speed = Speed::Normal;
 
+    let mut roms = RomMenus::new(options.library.as_deref(), options.dat.as_deref());
     let mut window = build_window();
+    roms.add_library_menu(&mut window);
 
     let (buffer_sender, buffer_receiver) = std::sync::mpsc::channel();
     let (event_sender, event_receiver) = std::sync::mpsc::channel();
+    let mut viewers = Viewers::new(event_sender.clone());
+    let (ppu_sender, ppu_receiver) = std::sync::mpsc::channel();
+    dev.set_ppu_sender(ppu_sender);
+    let (error_sender, error_receiver) = std::sync::mpsc::channel();
+    dev.set_error_sender(error_sender);
+    let (rumble_sender, rumble_receiver) = std::sync::mpsc::channel();
+    dev.set_rumble_sender(rumble_sender);
+    let (state_sender, state_receiver) = std::sync::mpsc::channel();
+    dev.set_state_sender(state_sender);
+    let mut rumble = Rumble::new(rumble_receiver);
 
-    std::thread::Builder::new()
+    if let Some(port) = options.gdb {
+        if let Err(e) = dev.listen_gdb(("127.0.0.1", port)) {
+            eprintln!("Failed to start GDB server on port {port}: {e}");
+        }
+    }
+
+    if tracing {
+        event_sender
+            .send(Event::StartTrace(options.trace.clone()))
+            .unwrap();
+    }
+
+    console::spawn(event_sender.clone());
+
+    let core = std::thread::Builder::new()
         .name("Core".to_owned())
         .spawn(move || dev.run(buffer_sender, event_receiver))
         .unwrap();
 
     while window.is_open() && state != DebuggerState::Quitting {
         match state {
-            DebuggerState::Running => running(&mut window, &buffer_receiver, &event_sender),
+            DebuggerState::Running => {
+                running(&mut window, &buffer_receiver, &event_sender, &mut rumble)
+            }
             DebuggerState::Stopped => stopped(&mut window),
             DebuggerState::Paused => paused(&mut window),
             DebuggerState::Quitting => unreachable!("Cannot be quiting in loop"),
         }
 
-        handle_debugger_input(&mut window, &mut state, &event_sender);
+        handle_debugger_input(
+            &mut window,
+            &mut state,
+            &event_sender,
+            &mut viewers,
+            &mut speed,
+            &mut roms,
+        );
+        roms.update(&mut window);
+
+        // Held, fast forwards without a frame limit
+        if window.is_key_pressed(Key::Tab, minifb::KeyRepeat::No) {
+            event_sender.send(Event::SetSpeed(Speed::Uncapped)).unwrap();
+        }
+        if window.is_key_released(Key::Tab) {
+            event_sender.send(Event::SetSpeed(speed)).unwrap();
+        }
+
+        if window.is_key_pressed(Key::T, minifb::KeyRepeat::No) {
+            tracing = !tracing;
+            let event = if tracing {
+                Event::StartTrace(options.trace.clone())
+            } else {
+                Event::StopTrace
+            };
+            event_sender.send(event).unwrap();
+        }
+
+        // Held, the core steps back one snapshot per frame
+        if state != DebuggerState::Stopped && window.is_key_down(Key::Backspace) {
+            event_sender.send(Event::Rewind).unwrap();
+        }
+
+        // Loads start the game and faults or breakpoints pause it, all decided by the core
+        for core_state in state_receiver.try_iter() {
+            if state != DebuggerState::Quitting {
+                state = match core_state {
+                    DeviceState::Stopped => DebuggerState::Stopped,
+                    DeviceState::Running => DebuggerState::Running,
+                    DeviceState::Paused => DebuggerState::Paused,
+                };
+            }
+        }
+
+        for error in error_receiver.try_iter() {
+            show_error(&error.to_string());
+        }
+
+        viewers.receive(&ppu_receiver);
+        viewers.update();
     }
 
+    // The core flushes battery RAM, traces and movies before it stops
     event_sender.send(Event::Exit).unwrap();
+    core.join().unwrap();
+}
+
+// The core reports whether an error paused it through its state channel
+fn show_error(text: &str) {
+    let result = native_dialog::MessageDialog::new()
+        .set_title("Chlorosis - Error")
+        .set_text(text)
+        .set_type(native_dialog::MessageType::Error)
+        .show_alert();
+    if let Err(e) = result {
+        eprintln!("Error: {text} ({e})");
+    }
 }
 
 fn build_window() -> Window {
@@ -66,9 +188,33 @@ fn build_window() -> Window {
     menu.add_item("Open ROM", 1)
         .shortcut(Key::O, MENU_KEY_CTRL)
         .build();
+    menu.add_item("Open ROM With Patch", 17).build();
     menu.add_item("Reset", 2).build();
     window.add_menu(&menu);
 
+    let mut view = Menu::new("View").unwrap();
+    view.add_item("Tiles", 3).build();
+    view.add_item("Tile Map", 4).build();
+    view.add_item("OAM", 5).build();
+    view.add_item("Palettes", 6).build();
+    window.add_menu(&view);
+
+    let mut movie = Menu::new("Movie").unwrap();
+    movie.add_item("Record From Power On", 7).build();
+    movie.add_item("Record From Here", 8).build();
+    movie.add_item("Play", 9).build();
+    movie.add_item("Stop", 10).build();
+    window.add_menu(&movie);
+
+    let mut speed = Menu::new("Speed").unwrap();
+    speed.add_item("Normal", 11).build();
+    speed.add_item("Fast Forward 2x", 12).build();
+    speed.add_item("Fast Forward 4x", 13).build();
+    speed.add_item("Uncapped", 14).build();
+    speed.add_item("Slow Motion 1/2x", 15).build();
+    speed.add_item("Slow Motion 1/4x", 16).build();
+    window.add_menu(&speed);
+
     window
 }
 
@@ -91,13 +237,21 @@ fn handle_debugger_input(
     window: &mut Window,
     state: &mut DebuggerState,
     event_sender: &Sender<Event>,
+    viewers: &mut Viewers,
+    speed: &mut Speed,
+    roms: &mut RomMenus,
 ) {
     if window.is_key_down(Key::Escape) {
         *state = DebuggerState::Quitting;
     }
 
     if let Some(n) = window.is_menu_pressed() {
-        handle_menu(n, event_sender, state);
+        if let Some(path) = roms.path(n) {
+            event_sender.send(Event::LoadFile(path.clone())).unwrap();
+            roms.opened(path);
+        } else {
+            handle_menu(n, event_sender, viewers, speed, roms);
+        }
     }
 
     if window.is_key_released(Key::Space) {
@@ -119,21 +273,79 @@ fn handle_debugger_input(
     }
 }
 
-fn handle_menu(menu: usize, sender: &Sender<Event>, state: &mut DebuggerState) {
+fn handle_menu(
+    menu: usize,
+    sender: &Sender<Event>,
+    viewers: &mut Viewers,
+    speed: &mut Speed,
+    roms: &mut RomMenus,
+) {
     match menu {
         1 => {
             let f = native_dialog::FileDialog::new()
-                .add_filter("GBC ROM", &["gbc"])
+                .add_filter("Game Boy ROM", &["gb", "gbc", "sgb", "zip", "gz"])
                 .show_open_single_file()
                 .unwrap();
             if let Some(f) = f {
-                sender.send(Event::LoadFile(f)).unwrap();
-                *state = DebuggerState::Running;
+                sender.send(Event::LoadFile(f.clone())).unwrap();
+                roms.opened(f);
             }
         }
         2 => {
             sender.send(Event::Reset).unwrap();
         }
+        17 => {
+            let rom = native_dialog::FileDialog::new()
+                .add_filter("Game Boy ROM", &["gb", "gbc", "sgb", "zip", "gz"])
+                .show_open_single_file()
+                .unwrap();
+            let patch = rom.as_ref().and_then(|_| {
+                native_dialog::FileDialog::new()
+                    .add_filter("Patch", &["ips", "ups", "bps"])
+                    .show_open_single_file()
+                    .unwrap()
+            });
+            if let (Some(rom), Some(patch)) = (rom, patch) {
+                sender
+                    .send(Event::LoadPatchedFile(rom.clone(), patch))
+                    .unwrap();
+                roms.opened(rom);
+            }
+        }
+        3 => viewers.open_tiles(),
+        4 => viewers.open_tilemap(),
+        5 => viewers.open_oam(),
+        6 => viewers.open_palette(),
+        7 | 8 => {
+            let f = native_dialog::FileDialog::new()
+                .add_filter("Movie", &["movie"])
+                .show_save_single_file()
+                .unwrap();
+            if let Some(f) = f {
+                sender.send(Event::RecordMovie(f, menu == 7)).unwrap();
+            }
+        }
+        9 => {
+            let f = native_dialog::FileDialog::new()
+                .add_filter("Movie", &["movie"])
+                .show_open_single_file()
+                .unwrap();
+            if let Some(f) = f {
+                sender.send(Event::PlayMovie(f)).unwrap();
+            }
+        }
+        10 => sender.send(Event::StopMovie).unwrap(),
+        11..=16 => {
+            *speed = match menu {
+                12 => Speed::FastForward(2),
+                13 => Speed::FastForward(4),
+                14 => Speed::Uncapped,
+                15 => Speed::SlowMotion(2),
+                16 => Speed::SlowMotion(4),
+                _ => Speed::Normal,
+            };
+            sender.send(Event::SetSpeed(*speed)).unwrap();
+        }
         _ => println!("Unhandled menu {menu}"),
     }
 }
@@ -150,11 +362,14 @@ fn running(
     window: &mut Window,
     buffer_receiver: &Receiver<Vec<u32>>,
     event_sender: &Sender<Event>,
+    rumble: &mut Rumble,
 ) {
-    if let Ok(b) = buffer_receiver.try_recv() {
+    // Only the newest frame is drawn, otherwise the last one stays up
+    if let Some(b) = buffer_receiver.try_iter().last() {
+        let b = rumble.shake(&b, WIDTH);
         window.update_with_buffer(&b, WIDTH, HEIGHT).unwrap();
     } else {
-        window.update_with_buffer(&BLACK, WIDTH, HEIGHT).unwrap();
+        window.update();
     }
 
     let keys_down: Vec<KeyCode> = window
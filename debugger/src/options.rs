@@ -0,0 +1,89 @@
+use std::path::PathBuf;
+
+use chlorosis_core::{TraceFormat, TraceOptions};
+
+// Command line options, all optional:
+//   --trace <file>                 start tracing to file immediately
+//   --trace-format doctor|verbose
+//   --trace-pc <start>-<end>       hex, e.g. 0150-3FFF
+//   --trace-bank <n>               hex
+//   --gdb <port>                   listen for GDB on localhost
+//   --dap                          serve the Debug Adapter Protocol on stdio, no window
+//   --dap-port <port>              as above but on a localhost socket
+//   --library <dir>                list the ROMs under dir in the Library menu
+//   --dat <file>                   No-Intro DAT used to name library ROMs
+pub struct Options {
+    pub trace: TraceOptions,
+    pub trace_on_start: bool,
+    pub gdb: Option<u16>,
+    pub dap: Option<Dap>,
+    pub library: Option<PathBuf>,
+    pub dat: Option<PathBuf>,
+}
+
+pub enum Dap {
+    Stdio,
+    Port(u16),
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            trace: TraceOptions::new("trace.log"),
+            trace_on_start: false,
+            gdb: None,
+            dap: None,
+            library: None,
+            dat: None,
+        }
+    }
+}
+
+impl Options {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("Missing value for {arg}"));
+            match arg.as_str() {
+                "--trace" => {
+                    options.trace.path = value()?.into();
+                    options.trace_on_start = true;
+                }
+                "--trace-format" => {
+                    options.trace.format = match value()?.as_str() {
+                        "doctor" => TraceFormat::Doctor,
+                        "verbose" => TraceFormat::Verbose,
+                        f => return Err(format!("Unknown trace format {f}")),
+                    }
+                }
+                "--trace-pc" => {
+                    let v = value()?;
+                    let (start, end) = v
+                        .split_once('-')
+                        .ok_or(format!("Expected <start>-<end>, got {v}"))?;
+                    options.trace.pc_range =
+                        Some(parse_hex(start)? as u16..=parse_hex(end)? as u16);
+                }
+                "--trace-bank" => options.trace.bank = Some(parse_hex(&value()?)?),
+                "--gdb" => options.gdb = Some(parse_port(&value()?)?),
+                "--dap" => options.dap = Some(Dap::Stdio),
+                "--dap-port" => options.dap = Some(Dap::Port(parse_port(&value()?)?)),
+                "--library" => options.library = Some(value()?.into()),
+                "--dat" => options.dat = Some(value()?.into()),
+                _ => return Err(format!("Unknown argument {arg}")),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn parse_hex(s: &str) -> Result<usize, String> {
+    let digits = s.trim_start_matches("0x").trim_start_matches('$');
+    usize::from_str_radix(digits, 16).map_err(|e| format!("Invalid hex value {s}: {e}"))
+}
+
+fn parse_port(s: &str) -> Result<u16, String> {
+    s.parse().map_err(|e| format!("Invalid port {s}: {e}"))
+}
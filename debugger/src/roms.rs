@@ -0,0 +1,121 @@
+use std::path::{Path, PathBuf};
+
+use chlorosis_core::{Dat, Library, LIBRARY_CACHE_NAME};
+use minifb::{Menu, MenuHandle, Window};
+
+// The Library and Recent menus, item ids are offsets into the lists
+const LIBRARY_ID: usize = 1000;
+const RECENT_ID: usize = 2000;
+const RECENT_LIMIT: usize = 10;
+const RECENT_FILE: &str = ".chlorosis-recent";
+
+pub struct RomMenus {
+    library: Vec<(String, PathBuf)>,
+    recent: Vec<PathBuf>,
+    recent_menu: Option<MenuHandle>,
+    changed: bool,
+}
+
+impl RomMenus {
+    pub fn new(library: Option<&Path>, dat: Option<&Path>) -> Self {
+        let dat = dat.and_then(|d| {
+            Dat::load(d)
+                .inspect_err(|e| eprintln!("Failed to load DAT {}: {e}", d.display()))
+                .ok()
+        });
+        let library = library.map_or_else(Vec::new, |dir| {
+            let cache = dir.join(LIBRARY_CACHE_NAME);
+            match Library::scan(dir, dat.as_ref(), Some(&cache)) {
+                Ok(library) => {
+                    if let Some(e) = library.cache_error() {
+                        eprintln!("Ignoring library cache: {e}");
+                    }
+                    for (path, e) in library.skipped() {
+                        eprintln!("Skipping {}: {e}", path.display());
+                    }
+                    println!("{} ROMs in library", library.entries().len());
+                    library
+                        .entries()
+                        .iter()
+                        .map(|e| (e.name(), e.path.clone()))
+                        .collect()
+                }
+                Err(e) => {
+                    eprintln!("Failed to scan library {}: {e}", dir.display());
+                    vec![]
+                }
+            }
+        });
+
+        let recent = recent_file()
+            .and_then(|f| std::fs::read_to_string(f).ok())
+            .map(|s| s.lines().map(PathBuf::from).collect())
+            .unwrap_or_default();
+
+        Self {
+            library,
+            recent,
+            recent_menu: None,
+            changed: true,
+        }
+    }
+
+    pub fn add_library_menu(&self, window: &mut Window) {
+        if self.library.is_empty() {
+            return;
+        }
+        let mut menu = Menu::new("Library").unwrap();
+        for (i, (name, _)) in self.library.iter().enumerate() {
+            menu.add_item(name, LIBRARY_ID + i).build();
+        }
+        window.add_menu(&menu);
+    }
+
+    // Rebuilds the Recent menu after a ROM has been opened
+    pub fn update(&mut self, window: &mut Window) {
+        if !self.changed {
+            return;
+        }
+        self.changed = false;
+        if let Some(handle) = self.recent_menu.take() {
+            window.remove_menu(handle);
+        }
+        let mut menu = Menu::new("Recent").unwrap();
+        for (i, path) in self.recent.iter().enumerate() {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            menu.add_item(&name, RECENT_ID + i).build();
+        }
+        self.recent_menu = Some(window.add_menu(&menu));
+    }
+
+    pub fn path(&self, id: usize) -> Option<PathBuf> {
+        match id {
+            LIBRARY_ID.. if id < RECENT_ID => self.library.get(id - LIBRARY_ID).map(|(_, p)| p),
+            RECENT_ID.. => self.recent.get(id - RECENT_ID),
+            _ => None,
+        }
+        .cloned()
+    }
+
+    pub fn opened(&mut self, path: PathBuf) {
+        self.recent.retain(|p| *p != path);
+        self.recent.insert(0, path);
+        self.recent.truncate(RECENT_LIMIT);
+        self.changed = true;
+
+        if let Some(f) = recent_file() {
+            let lines: Vec<_> = self
+                .recent
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect();
+            if let Err(e) = std::fs::write(&f, lines.join("\n")) {
+                eprintln!("Failed to save recent ROMs to {}: {e}", f.display());
+            }
+        }
+    }
+}
+
+fn recent_file() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|h| Path::new(&h).join(RECENT_FILE))
+}
@@ -0,0 +1,44 @@
+use std::sync::mpsc::Receiver;
+
+// There's rarely force feedback to drive, so the picture shakes while the motor runs instead
+const SHAKE: usize = 2;
+
+pub struct Rumble {
+    receiver: Receiver<bool>,
+    active: bool,
+    frame: usize,
+}
+
+impl Rumble {
+    pub const fn new(receiver: Receiver<bool>) -> Self {
+        Self {
+            receiver,
+            active: false,
+            frame: 0,
+        }
+    }
+
+    // Games pulse the motor to vary its strength, any pulse since the last frame counts
+    pub fn shake(&mut self, buffer: &[u32], width: usize) -> Vec<u32> {
+        let mut pulsed = self.active;
+        for active in self.receiver.try_iter() {
+            self.active = active;
+            pulsed |= active;
+        }
+        if !pulsed {
+            return buffer.to_vec();
+        }
+
+        // Alternate left and right every frame, filling the gap with black
+        self.frame += 1;
+        let mut shaken = vec![0; buffer.len()];
+        for (src, dst) in buffer.chunks(width).zip(shaken.chunks_mut(width)) {
+            if self.frame.is_multiple_of(2) {
+                dst[SHAKE..].copy_from_slice(&src[..width - SHAKE]);
+            } else {
+                dst[..width - SHAKE].copy_from_slice(&src[SHAKE..]);
+            }
+        }
+        shaken
+    }
+}
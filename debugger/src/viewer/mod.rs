@@ -0,0 +1,210 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::Path,
+    sync::mpsc::{Receiver, Sender},
+};
+
+use chlorosis_core::{Event, PixelProcessor};
+use minifb::{Window, WindowOptions};
+
+mod font;
+mod oam;
+mod palette;
+mod tilemap;
+mod tiles;
+
+use oam::OamViewer;
+use palette::PaletteViewer;
+use tilemap::TileMapViewer;
+use tiles::TileViewer;
+
+pub struct Viewers {
+    sender: Sender<Event>,
+    ppu: Option<PixelProcessor>,
+    tiles: Option<TileViewer>,
+    tilemap: Option<TileMapViewer>,
+    oam: Option<OamViewer>,
+    palette: Option<PaletteViewer>,
+}
+
+impl Viewers {
+    pub const fn new(sender: Sender<Event>) -> Self {
+        Self {
+            sender,
+            ppu: None,
+            tiles: None,
+            tilemap: None,
+            oam: None,
+            palette: None,
+        }
+    }
+
+    // Keep only the most recent PPU state, older frames are of no use to the viewers
+    pub fn receive(&mut self, receiver: &Receiver<PixelProcessor>) {
+        while let Ok(ppu) = receiver.try_recv() {
+            self.ppu = Some(ppu);
+        }
+    }
+
+    pub fn open_tiles(&mut self) {
+        if self.tiles.is_none() {
+            self.tiles = Some(TileViewer::new());
+        }
+    }
+
+    pub fn open_tilemap(&mut self) {
+        if self.tilemap.is_none() {
+            self.tilemap = Some(TileMapViewer::new());
+        }
+    }
+
+    pub fn open_oam(&mut self) {
+        if self.oam.is_none() {
+            self.oam = Some(OamViewer::new());
+        }
+    }
+
+    pub fn open_palette(&mut self) {
+        if self.palette.is_none() {
+            self.palette = Some(PaletteViewer::new());
+        }
+    }
+
+    pub fn update(&mut self) {
+        let ppu = self.ppu.get_or_insert_with(PixelProcessor::default);
+
+        if let Some(v) = &mut self.tiles {
+            if v.is_open() {
+                v.update(ppu);
+            } else {
+                self.tiles = None;
+            }
+        }
+
+        if let Some(v) = &mut self.tilemap {
+            if v.is_open() {
+                v.update(ppu);
+            } else {
+                self.tilemap = None;
+            }
+        }
+
+        if let Some(v) = &mut self.oam {
+            if v.is_open() {
+                v.update(ppu);
+            } else {
+                self.oam = None;
+            }
+        }
+
+        if let Some(v) = &mut self.palette {
+            if v.is_open() {
+                v.update(ppu, &self.sender);
+            } else {
+                self.palette = None;
+            }
+        }
+    }
+}
+
+fn build_window(title: &str, width: usize, height: usize) -> Window {
+    let mut window = Window::new(
+        title,
+        width,
+        height,
+        WindowOptions {
+            resize: true,
+            scale: minifb::Scale::X2,
+            scale_mode: minifb::ScaleMode::AspectRatioStretch,
+            ..WindowOptions::default()
+        },
+    )
+    .unwrap_or_else(|e| {
+        panic!("{}", e);
+    });
+    window.limit_update_rate(Some(std::time::Duration::from_millis(16)));
+    window
+}
+
+// Draws a one pixel outline, clipped to the buffer
+fn draw_rect(
+    buffer: &mut [u32],
+    width: usize,
+    (x, y): (usize, usize),
+    (w, h): (usize, usize),
+    colour: u32,
+) {
+    let height = buffer.len() / width;
+    for i in x..x + w {
+        for j in [y, y + h - 1] {
+            if i < width && j < height {
+                buffer[j * width + i] = colour;
+            }
+        }
+    }
+    for j in y..y + h {
+        for i in [x, x + w - 1] {
+            if i < width && j < height {
+                buffer[j * width + i] = colour;
+            }
+        }
+    }
+}
+
+fn fill_rect(
+    buffer: &mut [u32],
+    width: usize,
+    (x, y): (usize, usize),
+    (w, h): (usize, usize),
+    colour: u32,
+) {
+    let height = buffer.len() / width;
+    for j in y..(y + h).min(height) {
+        for i in x..(x + w).min(width) {
+            buffer[j * width + i] = colour;
+        }
+    }
+}
+
+// Same as draw_rect, but wrapping around the edges of the buffer
+fn draw_wrapped_rect(
+    buffer: &mut [u32],
+    width: usize,
+    (x, y): (usize, usize),
+    (w, h): (usize, usize),
+    colour: u32,
+) {
+    let height = buffer.len() / width;
+    for i in x..x + w {
+        for j in [y, y + h - 1] {
+            buffer[(j % height) * width + i % width] = colour;
+        }
+    }
+    for j in y..y + h {
+        for i in [x, x + w - 1] {
+            buffer[(j % height) * width + i % width] = colour;
+        }
+    }
+}
+
+fn export_png(
+    path: &Path,
+    buffer: &[u32],
+    width: usize,
+    height: usize,
+) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        width as u32,
+        height as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let data: Vec<u8> = buffer
+        .iter()
+        .flat_map(|p| [(p >> 16) as u8, (p >> 8) as u8, *p as u8])
+        .collect();
+    encoder.write_header()?.write_image_data(&data)
+}
@@ -0,0 +1,197 @@
+use chlorosis_core::{
+    ObjectAttribute, ObjectSize, Palette, PixelProcessor, OBJECTS_PER_LINE, OBJECT_COUNT,
+};
+use minifb::{Key, KeyRepeat, MouseMode, Window};
+
+use super::{
+    build_window, fill_rect,
+    font::{draw_text, CHAR_WIDTH, LINE_HEIGHT},
+};
+
+// Entries are listed in two columns of 20, each with an 8x16 preview followed by the decoded attributes
+const COLUMNS: usize = 2;
+const ROWS: usize = OBJECT_COUNT / COLUMNS;
+const ROW_HEIGHT: usize = 18;
+const PREVIEW_X: usize = 2;
+const TEXT_X: usize = 14;
+const COLUMN_WIDTH: usize = TEXT_X + 33 * CHAR_WIDTH + 4;
+const HEADER_HEIGHT: usize = LINE_HEIGHT + 4;
+const WIDTH: usize = COLUMN_WIDTH * COLUMNS;
+const HEIGHT: usize = HEADER_HEIGHT + ROWS * ROW_HEIGHT;
+
+const BACKGROUND_COLOUR: u32 = 0x00202020;
+const TEXT_COLOUR: u32 = 0x00E0E0E0;
+const SELECTED_COLOUR: u32 = 0x00205020;
+const DROPPED_COLOUR: u32 = 0x00702020;
+
+pub struct OamViewer {
+    window: Window,
+    buffer: Vec<u32>,
+    cgb: bool,
+    line: Option<u8>, // None follows LY
+}
+
+impl OamViewer {
+    pub fn new() -> Self {
+        Self {
+            window: build_window("Chlorosis - OAM", WIDTH, HEIGHT),
+            buffer: vec![BACKGROUND_COLOUR; WIDTH * HEIGHT],
+            cgb: false,
+            line: None,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.window.is_open() && !self.window.is_key_down(Key::Escape)
+    }
+
+    pub fn update(&mut self, ppu: &PixelProcessor) {
+        self.handle_input(ppu);
+
+        let line = self.line.unwrap_or_else(|| ppu.read_ly());
+        let objects = ppu.read_object_attributes();
+        let (selected, dropped) = ppu.scan_line_objects(line);
+
+        self.buffer.fill(BACKGROUND_COLOUR);
+        let header = format!(
+            "LINE {line:3}{} - {} SELECTED, {} DROPPED (MAX {OBJECTS_PER_LINE})",
+            if self.line.is_none() { " (LY)" } else { "" },
+            selected.len(),
+            dropped.len(),
+        );
+        draw_text(&mut self.buffer, WIDTH, (2, 2), &header, TEXT_COLOUR);
+
+        for (i, o) in objects.iter().enumerate() {
+            let (x0, y0) = Self::entry_position(i);
+            if dropped.contains(&i) {
+                fill_rect(
+                    &mut self.buffer,
+                    WIDTH,
+                    (x0, y0),
+                    (COLUMN_WIDTH, ROW_HEIGHT),
+                    DROPPED_COLOUR,
+                );
+            } else if selected.contains(&i) {
+                fill_rect(
+                    &mut self.buffer,
+                    WIDTH,
+                    (x0, y0),
+                    (COLUMN_WIDTH, ROW_HEIGHT),
+                    SELECTED_COLOUR,
+                );
+            }
+            self.draw_preview(ppu, o, (x0 + PREVIEW_X, y0 + 1));
+
+            let text = format!(
+                "{i:02} X:{:02X} Y:{:02X} T:{:02X} C:{} D:{} B:{} {}{}{}",
+                o.x,
+                o.y,
+                o.tile_index,
+                o.palette,
+                o.dmg_palette as u8,
+                o.vram_bank as u8,
+                if o.xflip { 'X' } else { '-' },
+                if o.yflip { 'Y' } else { '-' },
+                if o.is_occluded { 'P' } else { '-' },
+            );
+            let text_y = y0 + (ROW_HEIGHT - LINE_HEIGHT) / 2;
+            draw_text(
+                &mut self.buffer,
+                WIDTH,
+                (x0 + TEXT_X, text_y),
+                &text,
+                TEXT_COLOUR,
+            );
+        }
+
+        let title = match self.hovered_entry() {
+            Some(i) => Self::describe(i, &objects[i]),
+            None => "Chlorosis - OAM".to_owned(),
+        };
+        self.window.set_title(&title);
+        self.window
+            .update_with_buffer(&self.buffer, WIDTH, HEIGHT)
+            .unwrap();
+    }
+
+    fn handle_input(&mut self, ppu: &PixelProcessor) {
+        if self.window.is_key_pressed(Key::C, KeyRepeat::No) {
+            self.cgb = !self.cgb;
+        }
+        if self.window.is_key_pressed(Key::L, KeyRepeat::No) {
+            self.line = None;
+        }
+        let line = self.line.unwrap_or_else(|| ppu.read_ly());
+        if self.window.is_key_pressed(Key::Up, KeyRepeat::Yes) {
+            self.line = Some(line.saturating_sub(1));
+        }
+        if self.window.is_key_pressed(Key::Down, KeyRepeat::Yes) {
+            self.line = Some(line.saturating_add(1).min(143));
+        }
+    }
+
+    const fn entry_position(i: usize) -> (usize, usize) {
+        (
+            (i / ROWS) * COLUMN_WIDTH,
+            HEADER_HEIGHT + (i % ROWS) * ROW_HEIGHT,
+        )
+    }
+
+    // Colour 0 is transparent for objects, so it is left as the row background
+    fn draw_preview(
+        &mut self,
+        ppu: &PixelProcessor,
+        o: &ObjectAttribute,
+        (x0, y0): (usize, usize),
+    ) {
+        let palette = if self.cgb {
+            Palette::CgbObject(o.palette)
+        } else if o.dmg_palette {
+            Palette::Object1
+        } else {
+            Palette::Object0
+        };
+        let colours = ppu.read_palette(palette).map(u32::from);
+        let bank = if self.cgb { o.vram_bank as usize } else { 0 };
+
+        let (height, tiles) = match ppu.read_obj_size() {
+            ObjectSize::Square => (8, [o.tile_index, o.tile_index]),
+            ObjectSize::Tall => (16, [o.tile_index & 0xFE, o.tile_index | 0x01]),
+        };
+        let tiles = tiles.map(|t| ppu.read_tile_data(bank, t as usize));
+
+        for y in 0..height {
+            for x in 0..8 {
+                let ty = if o.yflip { height - 1 - y } else { y };
+                let tx = if o.xflip { 7 - x } else { x };
+                let c = tiles[ty / 8].color_index(tx, ty % 8);
+                if c != 0 {
+                    self.buffer[(y0 + y) * WIDTH + x0 + x] = colours[c as usize];
+                }
+            }
+        }
+    }
+
+    fn hovered_entry(&self) -> Option<usize> {
+        let (x, y) = self.window.get_mouse_pos(MouseMode::Discard)?;
+        let (x, y) = (x as usize, (y as usize).checked_sub(HEADER_HEIGHT)?);
+        let i = (x / COLUMN_WIDTH) * ROWS + y / ROW_HEIGHT;
+        (i < OBJECT_COUNT).then_some(i)
+    }
+
+    fn describe(i: usize, o: &ObjectAttribute) -> String {
+        format!(
+            "Chlorosis - OAM #{i} @ {:#06X} - Screen ({}, {}) Tile {:#04X} Bank {} CGB Palette {} DMG Palette OBP{}{}{}{}",
+            0xFE00 + i * 4,
+            o.x as i16 - 8,
+            o.y as i16 - 16,
+            o.tile_index,
+            o.vram_bank as u8,
+            o.palette,
+            o.dmg_palette as u8,
+            if o.xflip { " XFlip" } else { "" },
+            if o.yflip { " YFlip" } else { "" },
+            if o.is_occluded { " Behind BG" } else { "" },
+        )
+    }
+}
@@ -0,0 +1,231 @@
+use std::sync::mpsc::Sender;
+
+use chlorosis_core::{Event, Palette, Pixel, PixelProcessor};
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window};
+
+use super::{
+    build_window, draw_rect, fill_rect,
+    font::{draw_text, CHAR_WIDTH, LINE_HEIGHT},
+};
+
+// CGB BG palettes on the left, OBJ palettes on the right, DMG palettes underneath
+const SWATCH: usize = 14;
+const SPACING: usize = SWATCH + 2;
+const LABEL_WIDTH: usize = 5 * CHAR_WIDTH;
+const COLUMN_WIDTH: usize = LABEL_WIDTH + 4 * SPACING + 8;
+const CGB_ROWS: usize = 8;
+const DMG_TOP: usize = 2 + CGB_ROWS * SPACING + 6;
+const DMG_ROWS: usize = 3;
+const FOOTER_TOP: usize = DMG_TOP + DMG_ROWS * SPACING + 4;
+const WIDTH: usize = COLUMN_WIDTH * 2;
+const HEIGHT: usize = FOOTER_TOP + LINE_HEIGHT * 2 + 2;
+
+const BACKGROUND_COLOUR: u32 = 0x00202020;
+const TEXT_COLOUR: u32 = 0x00E0E0E0;
+const SELECTED_COLOUR: u32 = 0x00FF0000;
+
+const DMG_PALETTES: [Palette; DMG_ROWS] = [Palette::Background, Palette::Object0, Palette::Object1];
+
+struct Selection {
+    palette: Palette,
+    colour: u8,
+    value: u16,
+}
+
+pub struct PaletteViewer {
+    window: Window,
+    buffer: Vec<u32>,
+    selected: Option<Selection>,
+}
+
+impl PaletteViewer {
+    pub fn new() -> Self {
+        Self {
+            window: build_window("Chlorosis - Palettes", WIDTH, HEIGHT),
+            buffer: vec![BACKGROUND_COLOUR; WIDTH * HEIGHT],
+            selected: None,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.window.is_open() && !self.window.is_key_down(Key::Escape)
+    }
+
+    pub fn update(&mut self, ppu: &PixelProcessor, sender: &Sender<Event>) {
+        if self.window.get_mouse_down(MouseButton::Left) {
+            if let Some((palette, colour)) = self.hovered_swatch() {
+                let value = ppu.read_palette_value(palette, colour);
+                self.selected = Some(Selection {
+                    palette,
+                    colour,
+                    value,
+                });
+            }
+        }
+        self.edit(sender);
+
+        self.buffer.fill(BACKGROUND_COLOUR);
+        for n in 0..CGB_ROWS as u8 {
+            self.draw_palette(ppu, Palette::CgbBackground(n));
+            self.draw_palette(ppu, Palette::CgbObject(n));
+        }
+        for palette in DMG_PALETTES {
+            self.draw_palette(ppu, palette);
+        }
+        self.draw_footer();
+
+        self.window.set_title("Chlorosis - Palettes");
+        self.window
+            .update_with_buffer(&self.buffer, WIDTH, HEIGHT)
+            .unwrap();
+    }
+
+    // CGB components are adjusted with Q/A (red), W/S (green) and E/D (blue),
+    // DMG shades with Up/Down
+    fn edit(&mut self, sender: &Sender<Event>) {
+        let Some(s) = &mut self.selected else {
+            return;
+        };
+        let pressed = |k| self.window.is_key_pressed(k, KeyRepeat::Yes);
+
+        let value = match s.palette {
+            Palette::Grey => return,
+            Palette::Background | Palette::Object0 | Palette::Object1 => {
+                if pressed(Key::Up) {
+                    (s.value + 1).min(3)
+                } else if pressed(Key::Down) {
+                    s.value.saturating_sub(1)
+                } else {
+                    return;
+                }
+            }
+            Palette::CgbBackground(_) | Palette::CgbObject(_) => {
+                let mut value = s.value;
+                for (up, down, shift) in [
+                    (Key::Q, Key::A, 0),
+                    (Key::W, Key::S, 5),
+                    (Key::E, Key::D, 10),
+                ] {
+                    let c = (value >> shift) & 0x1F;
+                    let c = if pressed(up) {
+                        (c + 1).min(0x1F)
+                    } else if pressed(down) {
+                        c.saturating_sub(1)
+                    } else {
+                        c
+                    };
+                    value = (value & !(0x1F << shift)) | (c << shift);
+                }
+                value
+            }
+        };
+
+        if value != s.value {
+            s.value = value;
+            sender
+                .send(Event::WritePalette(s.palette, s.colour, value))
+                .unwrap();
+        }
+    }
+
+    fn position(palette: Palette) -> (usize, usize) {
+        match palette {
+            Palette::CgbBackground(n) => (0, 2 + n as usize * SPACING),
+            Palette::CgbObject(n) => (COLUMN_WIDTH, 2 + n as usize * SPACING),
+            Palette::Background => (0, DMG_TOP),
+            Palette::Object0 => (0, DMG_TOP + SPACING),
+            Palette::Object1 => (0, DMG_TOP + 2 * SPACING),
+            Palette::Grey => (COLUMN_WIDTH, DMG_TOP),
+        }
+    }
+
+    fn draw_palette(&mut self, ppu: &PixelProcessor, palette: Palette) {
+        let (x0, y0) = Self::position(palette);
+        let text_y = y0 + (SWATCH - LINE_HEIGHT) / 2 + 1;
+        draw_text(
+            &mut self.buffer,
+            WIDTH,
+            (x0 + 2, text_y),
+            &palette.to_string(),
+            TEXT_COLOUR,
+        );
+
+        let mut colours = ppu.read_palette(palette).map(u32::from);
+        if let Some(s) = self.selected.as_ref().filter(|s| s.palette == palette) {
+            colours[s.colour as usize] = match palette {
+                Palette::CgbBackground(_) | Palette::CgbObject(_) => Pixel::from_rgb555(s.value),
+                _ => Pixel::SHADES[s.value as usize],
+            }
+            .into();
+        }
+
+        for (i, c) in colours.iter().enumerate() {
+            let x = x0 + LABEL_WIDTH + i * SPACING;
+            fill_rect(&mut self.buffer, WIDTH, (x, y0), (SWATCH, SWATCH), *c);
+            if self
+                .selected
+                .as_ref()
+                .is_some_and(|s| s.palette == palette && s.colour as usize == i)
+            {
+                draw_rect(
+                    &mut self.buffer,
+                    WIDTH,
+                    (x, y0),
+                    (SWATCH, SWATCH),
+                    SELECTED_COLOUR,
+                );
+            }
+        }
+    }
+
+    fn draw_footer(&mut self) {
+        let text = match &self.selected {
+            None => "CLICK A COLOUR TO EDIT".to_owned(),
+            Some(s) => match s.palette {
+                Palette::CgbBackground(_) | Palette::CgbObject(_) => format!(
+                    "{} COLOUR {} = {:#06X} R:{:02} G:{:02} B:{:02}",
+                    s.palette,
+                    s.colour,
+                    s.value,
+                    s.value & 0x1F,
+                    (s.value >> 5) & 0x1F,
+                    (s.value >> 10) & 0x1F,
+                ),
+                _ => format!("{} COLOUR {} = SHADE {}", s.palette, s.colour, s.value),
+            },
+        };
+        let help = match self.selected.as_ref().map(|s| s.palette) {
+            Some(Palette::CgbBackground(_) | Palette::CgbObject(_)) => {
+                "Q/A RED  W/S GREEN  E/D BLUE"
+            }
+            Some(_) => "UP/DOWN SHADE",
+            None => "",
+        };
+        draw_text(&mut self.buffer, WIDTH, (2, FOOTER_TOP), &text, TEXT_COLOUR);
+        draw_text(
+            &mut self.buffer,
+            WIDTH,
+            (2, FOOTER_TOP + LINE_HEIGHT),
+            help,
+            TEXT_COLOUR,
+        );
+    }
+
+    fn hovered_swatch(&self) -> Option<(Palette, u8)> {
+        let (x, y) = self.window.get_mouse_pos(MouseMode::Discard)?;
+        let (x, y) = (x as usize, y as usize);
+        let palettes = (0..CGB_ROWS as u8)
+            .flat_map(|n| [Palette::CgbBackground(n), Palette::CgbObject(n)])
+            .chain(DMG_PALETTES);
+        for palette in palettes {
+            let (x0, y0) = Self::position(palette);
+            for i in 0..4 {
+                let sx = x0 + LABEL_WIDTH + i * SPACING;
+                if (sx..sx + SWATCH).contains(&x) && (y0..y0 + SWATCH).contains(&y) {
+                    return Some((palette, i as u8));
+                }
+            }
+        }
+        None
+    }
+}
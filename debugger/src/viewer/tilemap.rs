@@ -0,0 +1,176 @@
+use std::ops::RangeInclusive;
+
+use chlorosis_core::{MapTile, Palette, PixelProcessor};
+use minifb::{Key, KeyRepeat, MouseMode, Window};
+
+use super::{build_window, draw_rect, draw_wrapped_rect, export_png};
+
+const WIDTH: usize = 256;
+const HEIGHT: usize = 256;
+const LOW_MAP: RangeInclusive<u16> = 0x9800..=0x9BFF;
+const HIGH_MAP: RangeInclusive<u16> = 0x9C00..=0x9FFF;
+
+const VIEWPORT_COLOUR: u32 = 0x00FF0000;
+const WINDOW_COLOUR: u32 = 0x000080FF;
+const HOVER_COLOUR: u32 = 0x0000FF00;
+
+pub struct TileMapViewer {
+    window: Window,
+    buffer: Vec<u32>,
+    high_map: bool,
+    cgb: bool,
+}
+
+impl TileMapViewer {
+    pub fn new() -> Self {
+        Self {
+            window: build_window("Chlorosis - Tile Map", WIDTH, HEIGHT),
+            buffer: vec![0; WIDTH * HEIGHT],
+            high_map: false,
+            cgb: false,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.window.is_open() && !self.window.is_key_down(Key::Escape)
+    }
+
+    pub fn update(&mut self, ppu: &PixelProcessor) {
+        if self.window.is_key_pressed(Key::M, KeyRepeat::No) {
+            self.high_map = !self.high_map;
+        }
+        if self.window.is_key_pressed(Key::C, KeyRepeat::No) {
+            self.cgb = !self.cgb;
+        }
+
+        let map = ppu.get_tile_map(self.area());
+        self.draw(ppu, &map);
+
+        if self.window.is_key_pressed(Key::E, KeyRepeat::No) {
+            self.export();
+        }
+
+        let (scx, scy) = ppu.read_scroll();
+        draw_wrapped_rect(
+            &mut self.buffer,
+            WIDTH,
+            (scx as usize, scy as usize),
+            (160, 144),
+            VIEWPORT_COLOUR,
+        );
+
+        // The window is drawn from its top left tile at (WX - 7, WY) on screen
+        let (wx, wy) = ppu.read_window_position();
+        if ppu.is_window_enabled() && wx <= 166 && wy <= 143 {
+            let w = 167 - wx as usize;
+            let h = 144 - wy as usize;
+            draw_rect(&mut self.buffer, WIDTH, (0, 0), (w, h), WINDOW_COLOUR);
+        }
+
+        let hovered = self.hovered_tile();
+        if let Some(i) = hovered {
+            let pos = ((i % 32) * 8, (i / 32) * 8);
+            draw_rect(&mut self.buffer, WIDTH, pos, (8, 8), HOVER_COLOUR);
+        }
+
+        self.window
+            .set_title(&self.title(ppu, hovered.map(|i| (i, map[i]))));
+        self.window
+            .update_with_buffer(&self.buffer, WIDTH, HEIGHT)
+            .unwrap();
+    }
+
+    const fn area(&self) -> RangeInclusive<u16> {
+        if self.high_map {
+            HIGH_MAP
+        } else {
+            LOW_MAP
+        }
+    }
+
+    fn draw(&mut self, ppu: &PixelProcessor, map: &[MapTile]) {
+        for (i, entry) in map.iter().enumerate() {
+            let palette = if self.cgb {
+                Palette::CgbBackground(entry.attributes.palette)
+            } else {
+                Palette::Background
+            };
+            let colours = ppu.read_palette(palette).map(u32::from);
+            let (x0, y0) = ((i % 32) * 8, (i / 32) * 8);
+            for y in 0..8 {
+                for x in 0..8 {
+                    let tx = if self.cgb && entry.attributes.hflip {
+                        7 - x
+                    } else {
+                        x
+                    };
+                    let ty = if self.cgb && entry.attributes.vflip {
+                        7 - y
+                    } else {
+                        y
+                    };
+                    self.buffer[(y0 + y) * WIDTH + x0 + x] =
+                        colours[entry.tile.color_index(tx, ty) as usize];
+                }
+            }
+        }
+    }
+
+    // Exports the map as currently drawn, before any overlays are added
+    fn export(&self) {
+        let f = native_dialog::FileDialog::new()
+            .set_filename(&format!("tilemap_{:04X}.png", self.area().start()))
+            .add_filter("PNG image", &["png"])
+            .show_save_single_file()
+            .unwrap();
+        if let Some(f) = f {
+            match export_png(&f, &self.buffer, WIDTH, HEIGHT) {
+                Ok(()) => println!("Exported tile map to {}", f.display()),
+                Err(e) => println!("Failed to export tile map: {e}"),
+            }
+        }
+    }
+
+    fn hovered_tile(&self) -> Option<usize> {
+        let (x, y) = self.window.get_mouse_pos(MouseMode::Discard)?;
+        let (x, y) = (x as usize, y as usize);
+        // A resized window can report positions past the edge of the map
+        (x < WIDTH && y < HEIGHT).then_some((y / 8) * 32 + x / 8)
+    }
+
+    fn title(&self, ppu: &PixelProcessor, hovered: Option<(usize, MapTile)>) -> String {
+        let start = *self.area().start();
+        let mut usage = vec![];
+        if *ppu.read_background_tile_map_area().start() == start {
+            usage.push("BG");
+        }
+        if *ppu.read_window_tile_map_area().start() == start {
+            usage.push("Window");
+        }
+        let mut title = format!("Chlorosis - Tile Map {start:#06X} [{}]", usage.join(", "));
+
+        if let Some((i, entry)) = hovered {
+            let a = entry.attributes;
+            title += &format!(
+                " - ({}, {}) @ {:#06X} Tile {:#04X} Bank {} Palette {}",
+                i % 32,
+                i / 32,
+                start as usize + i,
+                entry.index,
+                a.vram_bank as u8,
+                a.palette
+            );
+            for (set, name) in [
+                (a.hflip, "XFlip"),
+                (a.vflip, "YFlip"),
+                (a.priority, "Priority"),
+            ] {
+                if set {
+                    title += " ";
+                    title += name;
+                }
+            }
+        }
+        title
+    }
+}
@@ -0,0 +1,99 @@
+use chlorosis_core::{Palette, PixelProcessor};
+use minifb::{Key, KeyRepeat, MouseMode, Window};
+
+use super::{build_window, draw_rect};
+
+// Each bank is laid out as 16 x 24 tiles
+const TILES_PER_ROW: usize = 16;
+const TILES_PER_BANK: usize = 384;
+const BANK_WIDTH: usize = TILES_PER_ROW * 8;
+const BANK_HEIGHT: usize = TILES_PER_BANK / TILES_PER_ROW * 8;
+const GAP: usize = 8;
+const WIDTH: usize = BANK_WIDTH * 2 + GAP;
+const HEIGHT: usize = BANK_HEIGHT;
+
+const GAP_COLOUR: u32 = 0x00303030;
+const HOVER_COLOUR: u32 = 0x00FF0000;
+
+pub struct TileViewer {
+    window: Window,
+    buffer: Vec<u32>,
+    palette: usize,
+}
+
+impl TileViewer {
+    pub fn new() -> Self {
+        Self {
+            window: build_window("Chlorosis - Tiles", WIDTH, HEIGHT),
+            buffer: vec![GAP_COLOUR; WIDTH * HEIGHT],
+            palette: 0,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.window.is_open() && !self.window.is_key_down(Key::Escape)
+    }
+
+    pub fn update(&mut self, ppu: &PixelProcessor) {
+        if self.window.is_key_pressed(Key::Right, KeyRepeat::Yes) {
+            self.palette = (self.palette + 1) % Palette::ALL.len();
+        }
+        if self.window.is_key_pressed(Key::Left, KeyRepeat::Yes) {
+            self.palette = (self.palette + Palette::ALL.len() - 1) % Palette::ALL.len();
+        }
+
+        self.draw(ppu);
+
+        let hovered = self.hovered_tile();
+        if let Some((bank, index)) = hovered {
+            let x = bank * (BANK_WIDTH + GAP) + (index % TILES_PER_ROW) * 8;
+            let y = (index / TILES_PER_ROW) * 8;
+            draw_rect(&mut self.buffer, WIDTH, (x, y), (8, 8), HOVER_COLOUR);
+        }
+
+        self.window.set_title(&self.title(hovered));
+        self.window
+            .update_with_buffer(&self.buffer, WIDTH, HEIGHT)
+            .unwrap();
+    }
+
+    fn draw(&mut self, ppu: &PixelProcessor) {
+        let colours = ppu.read_palette(Palette::ALL[self.palette]).map(u32::from);
+
+        for bank in 0..2 {
+            for index in 0..TILES_PER_BANK {
+                let tile = ppu.read_tile_data(bank, index);
+                let x0 = bank * (BANK_WIDTH + GAP) + (index % TILES_PER_ROW) * 8;
+                let y0 = (index / TILES_PER_ROW) * 8;
+                for y in 0..8 {
+                    for x in 0..8 {
+                        self.buffer[(y0 + y) * WIDTH + x0 + x] =
+                            colours[tile.color_index(x, y) as usize];
+                    }
+                }
+            }
+        }
+    }
+
+    fn hovered_tile(&self) -> Option<(usize, usize)> {
+        let (x, y) = self.window.get_mouse_pos(MouseMode::Discard)?;
+        let (x, y) = (x as usize, y as usize);
+        let (bank, x) = match x {
+            0..BANK_WIDTH => (0, x),
+            _ if x >= BANK_WIDTH + GAP => (1, x - BANK_WIDTH - GAP),
+            _ => return None,
+        };
+        Some((bank, (y / 8) * TILES_PER_ROW + x / 8))
+    }
+
+    fn title(&self, hovered: Option<(usize, usize)>) -> String {
+        let palette = Palette::ALL[self.palette];
+        match hovered {
+            Some((bank, index)) => format!(
+                "Chlorosis - Tiles - Palette: {palette} - Bank {bank} Tile {index:#05X} @ {:#06X}",
+                0x8000 + index * 16
+            ),
+            None => format!("Chlorosis - Tiles - Palette: {palette}"),
+        }
+    }
+}
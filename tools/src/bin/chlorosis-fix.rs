@@ -0,0 +1,122 @@
+use std::path::PathBuf;
+
+use chlorosis_core::{
+    ram_size_code, rom_size_code, CartrigeHeader, ColorMode, HeaderFix, MemoryBankControllerType,
+    RomSizeCheck,
+};
+
+const USAGE: &str = "Usage: chlorosis-fix [options] <rom>
+
+Puts the Nintendo logo into the header and recomputes both checksums, in place unless --output
+is given. Options:
+  --title <title>       at most 15 characters, 16 without the CGB flag
+  --mapper <code>       cartridge type, hex, e.g. 19 for MBC5
+  --rom-size <KB>       e.g. 32, 64, 1024
+  --ram-size <KB>       0, 2, 8, 32, 64 or 128
+  --cgb none|compat|only
+  --pad <byte>          hex, pad the ROM to the next valid size and set the ROM size
+  --output <file>";
+
+struct Options {
+    fix: HeaderFix,
+    rom: PathBuf,
+    output: Option<PathBuf>,
+}
+
+fn main() {
+    let options = parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{e}\n\n{USAGE}");
+        std::process::exit(2);
+    });
+    if let Err(e) = fix(&options) {
+        eprintln!("{}: {e}", options.rom.display());
+        std::process::exit(1);
+    }
+}
+
+fn fix(options: &Options) -> Result<(), String> {
+    let mut rom = std::fs::read(&options.rom).map_err(|e| e.to_string())?;
+    options.fix.apply(&mut rom).map_err(|e| e.to_string())?;
+
+    // The fixed header must parse, e.g. a size code that doesn't match the file is only a warning
+    let header = CartrigeHeader::parse(&rom).map_err(|e| e.to_string())?;
+    match header.size_check() {
+        RomSizeCheck::Matches => {}
+        RomSizeCheck::Truncated(len) => eprintln!("Warning: ROM is truncated, {len} bytes"),
+        RomSizeCheck::Overdump(len) => eprintln!("Warning: ROM is overdumped, {len} bytes"),
+    }
+    let output = options.output.as_ref().unwrap_or(&options.rom);
+    std::fs::write(output, &rom).map_err(|e| e.to_string())?;
+    println!(
+        "{}: {} {:?}, {} KB ROM, {} KB RAM, checksums 0x{:02X} 0x{:04X}",
+        output.display(),
+        header.title(),
+        header.mapper(),
+        header.rom_size() / 1024,
+        header.ram_size() / 1024,
+        header.header_checksum(),
+        header.global_checksum()
+    );
+    Ok(())
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut fix = HeaderFix::default();
+    let mut rom = None;
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {arg}"));
+        match arg.as_str() {
+            "--title" => fix.title = Some(value()?),
+            "--mapper" => {
+                let code = parse_hex(&value()?)?;
+                let mapper = MemoryBankControllerType::try_from(code).map_err(|e| e.to_string())?;
+                fix.mapper = Some(mapper);
+            }
+            "--rom-size" => {
+                let kb = parse_kb(&value()?)?;
+                let code = kb.checked_mul(1024).and_then(rom_size_code);
+                fix.rom_size = Some(code.ok_or(format!("Invalid ROM size {kb} KB"))?);
+            }
+            "--ram-size" => {
+                let kb = parse_kb(&value()?)?;
+                let code = kb
+                    .checked_mul(1024)
+                    .and_then(|size| u32::try_from(size).ok())
+                    .and_then(ram_size_code);
+                fix.ram_size = Some(code.ok_or(format!("Invalid RAM size {kb} KB"))?);
+            }
+            "--cgb" => {
+                fix.cgb_flag = Some(match value()?.as_str() {
+                    "none" => ColorMode::Unknown,
+                    "compat" => ColorMode::BackwardsCompat,
+                    "only" => ColorMode::ColorOnly,
+                    c => return Err(format!("Unknown CGB mode {c}")),
+                })
+            }
+            "--pad" => fix.pad = Some(parse_hex(&value()?)?),
+            "--output" | "-o" => output = Some(value()?.into()),
+            _ if arg.starts_with('-') => return Err(format!("Unknown argument {arg}")),
+            _ if rom.is_none() => rom = Some(arg.into()),
+            _ => return Err(format!("Unexpected argument {arg}")),
+        }
+    }
+
+    Ok(Options {
+        fix,
+        rom: rom.ok_or("Missing ROM")?,
+        output,
+    })
+}
+
+fn parse_hex(s: &str) -> Result<u8, String> {
+    let digits = s.trim_start_matches("0x").trim_start_matches('$');
+    u8::from_str_radix(digits, 16).map_err(|e| format!("Invalid hex value {s}: {e}"))
+}
+
+fn parse_kb(s: &str) -> Result<u64, String> {
+    s.trim_end_matches(['K', 'k'])
+        .parse()
+        .map_err(|e| format!("Invalid size {s}: {e}"))
+}
@@ -0,0 +1,139 @@
+use std::path::Path;
+
+use chlorosis_core::{extract_rom, CartrigeHeader, RomSizeCheck};
+use serde_json::{json, Value};
+
+const USAGE: &str = "Usage: chlorosis-info [--json] <rom>...
+
+Prints the cartridge header of each ROM, which may be zipped or gzipped";
+
+// Everything about one file, or why it couldn't be read
+struct Info {
+    path: String,
+    header: Result<(CartrigeHeader, bool), String>, // Header and whether the global checksum matches
+}
+
+fn main() {
+    let mut json = false;
+    let mut paths = vec![];
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return;
+            }
+            _ if arg.starts_with("--") => {
+                eprintln!("Unknown argument {arg}\n{USAGE}");
+                std::process::exit(2);
+            }
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("{USAGE}");
+        std::process::exit(2);
+    }
+
+    let infos: Vec<Info> = paths.into_iter().map(read_info).collect();
+    if json {
+        let values: Vec<Value> = infos.iter().map(to_json).collect();
+        let out = match values.as_slice() {
+            [value] => value.clone(),
+            _ => Value::Array(values),
+        };
+        println!("{}", serde_json::to_string_pretty(&out).unwrap());
+    } else {
+        for (i, info) in infos.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            print_info(info);
+        }
+    }
+
+    if infos.iter().any(|i| i.header.is_err()) {
+        std::process::exit(1);
+    }
+}
+
+fn read_info(path: String) -> Info {
+    let header = std::fs::read(Path::new(&path))
+        .and_then(extract_rom)
+        .map_err(|e| e.to_string())
+        .and_then(|(rom, _)| {
+            let header = CartrigeHeader::parse(&rom).map_err(|e| e.to_string())?;
+            let checksum_ok = header.verify_global_checksum(&rom).is_ok();
+            Ok((header, checksum_ok))
+        });
+    Info { path, header }
+}
+
+fn to_json(info: &Info) -> Value {
+    match &info.header {
+        Ok((header, checksum_ok)) => {
+            let mut value = header.to_json();
+            value["file"] = json!(info.path);
+            value["global_checksum_valid"] = json!(checksum_ok);
+            value["size_check"] = json!(format!("{:?}", header.size_check()));
+            value
+        }
+        Err(e) => json!({ "file": info.path, "error": e }),
+    }
+}
+
+fn print_info(info: &Info) {
+    println!("{}", info.path);
+    let (header, checksum_ok) = match &info.header {
+        Ok(h) => h,
+        Err(e) => {
+            println!("  Error: {e}");
+            return;
+        }
+    };
+    println!("  Title:            {}", header.title());
+    if let Some(code) = header.manufacturer_code() {
+        println!("  Manufacturer:     {code}");
+    }
+    println!(
+        "  Licensee:         {} ({})",
+        header.licensee_name(),
+        header.licensee_code()
+    );
+    println!(
+        "  Mapper:           {:?} (0x{:02X})",
+        header.mapper(),
+        header.mapper() as u8
+    );
+    println!(
+        "  ROM size:         {} KB, {} banks",
+        header.rom_size() / 1024,
+        header.rom_banks()
+    );
+    println!("  RAM size:         {} KB", header.ram_size() / 1024);
+    println!("  CGB:              {:?}", header.cgb_flag());
+    println!("  SGB:              {:?}", header.sgb_flag());
+    println!("  Destination:      {:?}", header.destination());
+    println!("  Version:          {}", header.version());
+    let valid = |ok: bool| if ok { "valid" } else { "invalid" };
+    println!("  Logo:             {}", valid(header.logo_valid()));
+    println!(
+        "  Header checksum:  0x{:02X} ({})",
+        header.header_checksum(),
+        valid(header.header_checksum_valid())
+    );
+    println!(
+        "  Global checksum:  0x{:04X} ({})",
+        header.global_checksum(),
+        valid(*checksum_ok)
+    );
+    match header.size_check() {
+        RomSizeCheck::Matches => {}
+        RomSizeCheck::Truncated(len) => {
+            println!("  Warning:          truncated, file is {len} bytes")
+        }
+        RomSizeCheck::Overdump(len) => {
+            println!("  Warning:          overdump, file is {len} bytes")
+        }
+    }
+}